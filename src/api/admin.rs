@@ -0,0 +1,75 @@
+use crate::api::helpers::{to_json, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::menu_catalog::CatalogEstimator;
+use serde::Serialize;
+use std::sync::Arc;
+
+// This file contains handlers of the admin endpoints
+
+#[derive(Serialize)]
+pub struct CatalogReloaded {
+    pub items: usize,
+}
+
+// Re-reads the menu catalog file, the running catalog is kept on failure
+pub async fn reload_menu_catalog_handler(request: Request) -> Response {
+    let catalog: Arc<CatalogEstimator> = Arc::clone(request.extensions().get().unwrap());
+    let result = match tokio::task::spawn_blocking(move || catalog.reload()).await {
+        Ok(result) => result,
+        Err(err) => Err(err.to_string()),
+    };
+    match result {
+        Ok(items) => to_json(CatalogReloaded { items }, HttpStatus::OK).await,
+        Err(msg) => to_json(FailMsg { msg }, HttpStatus::INTERNAL_SERVER_ERROR).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::menu_catalog::CatalogEstimator;
+    use crate::domain::prep_time::FixedEstimator;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn reload(catalog: Arc<CatalogEstimator>) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(http::Method::POST)
+            .uri("/admin/menu-catalog/reload")
+            .body(hyper::Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(catalog);
+        let response = handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_reload_the_menu_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("menu.json");
+        std::fs::write(&path, r#"{ "Margherita": { "base_minutes": 12 } }"#).unwrap();
+        let catalog = Arc::new(
+            CatalogEstimator::try_new(Some(path.clone()), Box::new(FixedEstimator(10))).unwrap(),
+        );
+
+        std::fs::write(
+            &path,
+            r#"{ "Margherita": { "base_minutes": 12 }, "Tiramisu": { "base_minutes": 5 } }"#,
+        )
+        .unwrap();
+        let (status, json) = reload(catalog.clone()).await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json["items"], 2);
+
+        std::fs::write(&path, "not json").unwrap();
+        let (status, _) = reload(catalog).await;
+        assert_eq!(status, HttpStatus::INTERNAL_SERVER_ERROR);
+    }
+}
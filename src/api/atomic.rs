@@ -0,0 +1,81 @@
+use crate::api::helpers::{
+    json_body, to_json, ConflictMsg, FailMsg, OpStatusResponse, StatusWithBody,
+};
+use crate::api::HttpStatus;
+use crate::api::Response;
+use crate::domain::apply_batch::{execute, BatchRequest, Error};
+use crate::domain::delete_item::DeleteOneResponse;
+use crate::domain::events::ItemEvents;
+use crate::domain::types::{IdType, Item, ItemId};
+use crate::repository::{BatchOp, Repository};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// This file contains the ?atomic=true mode of the multi-item handlers: the whole
+// batch is applied in one repository transaction or nothing is applied
+
+pub async fn atomic_batch_response(
+    repo: Arc<dyn Repository>,
+    events: ItemEvents,
+    req: BatchRequest,
+    success_status: HttpStatus,
+) -> Response {
+    let ids: Vec<ItemId<IdType>> = req
+        .ops
+        .iter()
+        .map(|op| match op {
+            BatchOp::Insert(item) | BatchOp::Update(item) => item.id,
+            BatchOp::Delete(item_id) => *item_id,
+        })
+        .collect();
+
+    let (status, msg) = match execute(repo, events, req).await {
+        Ok(res) => {
+            let mut op_status: OpStatusResponse = HashMap::new();
+            for (id, item) in ids.into_iter().zip(res.items) {
+                let body = match item {
+                    Some(item) => json_body::<Item>(item),
+                    None => json_body::<DeleteOneResponse>(DeleteOneResponse {}),
+                };
+                op_status.insert(
+                    id,
+                    StatusWithBody {
+                        status: success_status.as_u16(),
+                        body,
+                    },
+                );
+            }
+            return to_json(op_status, success_status).await;
+        }
+        Err(Error::Conflict(id)) => (
+            HttpStatus::BAD_REQUEST,
+            format!("Item {} already exists", id),
+        ),
+        Err(Error::UnknownItemId(id)) => (HttpStatus::NOT_FOUND, format!("Unknown item id {}", id)),
+        Err(Error::UnknowTableId) => (HttpStatus::NOT_FOUND, "Unknown table id".to_string()),
+        Err(Error::VersionConflict(id, item)) => {
+            return to_json(
+                ConflictMsg::new(
+                    format!(
+                    "Version mismatch: server has newer version of item {}, no item was changed",
+                    id
+                ),
+                    item,
+                ),
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(Error::Unknown) => (
+            HttpStatus::INTERNAL_SERVER_ERROR,
+            "Server error".to_string(),
+        ),
+    };
+    to_json(
+        FailMsg {
+            msg: format!("{}, no item was changed", msg),
+        },
+        status,
+    )
+    .await
+}
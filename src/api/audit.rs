@@ -0,0 +1,219 @@
+use crate::api::helpers::{parse_audit_query, to_json, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::audit::{Auditor, Caller};
+use crate::domain::read_audit::{execute, Error};
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains the function to handle GET /audit and the one that makes
+// the item changes of a request go to the audit log
+
+pub const CALLER_HEADER: &str = "X-Caller-Id";
+
+async fn fail(msg: String, status: HttpStatus) -> Response {
+    to_json(FailMsg { msg }, status).await
+}
+
+// Swaps the repository of the request for one logging the changes under the
+// caller named by the X-Caller-Id header, anonymous when it is missing
+pub async fn audited(mut request: Request) -> Result<Request, Response> {
+    let auditor: Arc<Auditor> = match request.extensions().get() {
+        Some(auditor) => Arc::clone(auditor),
+        None => return Ok(request),
+    };
+    let repo: Arc<dyn Repository> = match request.extensions().get() {
+        Some(repo) => Arc::clone(repo),
+        None => return Ok(request),
+    };
+    let caller = match request.headers().get(CALLER_HEADER) {
+        None => Caller::anonymous(),
+        Some(value) => match value
+            .to_str()
+            .map_err(|err| err.to_string())
+            .and_then(|value| Caller::try_from(value.to_string()))
+        {
+            Ok(caller) => caller,
+            Err(err) => return Err(fail(err, HttpStatus::BAD_REQUEST).await),
+        },
+    };
+
+    let audited = auditor.audit(repo, caller);
+    request.extensions_mut().insert(audited);
+    Ok(request)
+}
+
+pub async fn read_audit_handler(request: Request) -> Response {
+    let req = match parse_audit_query(request.uri().query()) {
+        Ok(req) => req,
+        Err(err) => return fail(err, HttpStatus::BAD_REQUEST).await,
+    };
+    let auditor: Arc<Auditor> = match request.extensions().get() {
+        Some(auditor) => Arc::clone(auditor),
+        None => {
+            return fail(
+                "Audit log is not enabled".to_string(),
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+    };
+
+    match execute(auditor.log(), req).await {
+        Ok(res) => to_json(res.records, HttpStatus::OK).await,
+        Err(Error::Unknown) => {
+            fail(
+                "Server error".to_string(),
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::audit::Auditor;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn call(
+        repo: &Arc<dyn Repository>,
+        auditor: &Arc<Auditor>,
+        method: http::Method,
+        uri: &str,
+        caller: Option<&str>,
+        body: &str,
+    ) -> (HttpStatus, Value) {
+        let mut builder = hyper::Request::builder().method(method).uri(uri);
+        if let Some(caller) = caller {
+            builder = builder.header(super::CALLER_HEADER, caller);
+        }
+        let mut request = builder.body(hyper::Body::from(body.to_string())).unwrap();
+        request.extensions_mut().insert(repo.clone());
+        request.extensions_mut().insert(auditor.clone());
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        let response = handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn it_should_tell_who_changed_a_table_and_when() {
+        let repo = InMemoryRepository::new();
+        let auditor = Arc::new(Auditor::new(repo.audit_log()));
+        let repo: Arc<dyn Repository> = Arc::new(repo);
+        let items = r#"{"1": {"name": "Pizza", "notes": "", "quantity": 1}}"#;
+        call(
+            &repo,
+            &auditor,
+            http::Method::POST,
+            "/tables/12/items",
+            Some("waiter-7"),
+            items,
+        )
+        .await;
+        call(
+            &repo,
+            &auditor,
+            http::Method::POST,
+            "/tables/3/items",
+            None,
+            items,
+        )
+        .await;
+        let patch = r#"{"quantity": 2}"#;
+        call(
+            &repo,
+            &auditor,
+            http::Method::PATCH,
+            "/tables/12/items/1",
+            Some("chef"),
+            patch,
+        )
+        .await;
+        call(
+            &repo,
+            &auditor,
+            http::Method::DELETE,
+            "/tables/12/items/1",
+            Some("chef"),
+            "",
+        )
+        .await;
+
+        let (status, records) = call(
+            &repo,
+            &auditor,
+            http::Method::GET,
+            "/audit?table=12",
+            None,
+            "",
+        )
+        .await;
+        assert_eq!(status, HttpStatus::OK);
+        let summary: Vec<(&str, &str, u64)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                (
+                    record["action"].as_str().unwrap(),
+                    record["caller"].as_str().unwrap(),
+                    record["version"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("insert", "waiter-7", 1),
+                ("update", "chef", 2),
+                ("delete", "chef", 2)
+            ]
+        );
+        assert_eq!(records[1]["old"]["quantity"], 1);
+        assert_eq!(records[1]["new"]["quantity"], 2);
+        assert_eq!(records[2]["new"], Value::Null);
+
+        let (_, records) = call(&repo, &auditor, http::Method::GET, "/audit", None, "").await;
+        assert_eq!(records.as_array().unwrap().len(), 4);
+        assert_eq!(records[1]["caller"], "anonymous");
+
+        let (_, records) = call(
+            &repo,
+            &auditor,
+            http::Method::GET,
+            "/audit?since=2999-01-01T00:00:00Z",
+            None,
+            "",
+        )
+        .await;
+        assert_eq!(records, serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_bad_queries_and_callers() {
+        let repo = InMemoryRepository::new();
+        let auditor = Arc::new(Auditor::new(repo.audit_log()));
+        let repo: Arc<dyn Repository> = Arc::new(repo);
+
+        for uri in ["/audit?table=0", "/audit?since=yesterday", "/audit?limit=1"] {
+            let (status, _) = call(&repo, &auditor, http::Method::GET, uri, None, "").await;
+            assert_eq!(status, HttpStatus::BAD_REQUEST);
+        }
+        let (status, _) = call(&repo, &auditor, http::Method::GET, "/audit", Some(" "), "").await;
+        assert_eq!(status, HttpStatus::BAD_REQUEST);
+    }
+}
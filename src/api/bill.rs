@@ -0,0 +1,173 @@
+use crate::api::helpers::{parse_numeric_id, to_json, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::checkout::{self, CheckoutRequest};
+use crate::domain::events::ItemEvents;
+use crate::domain::read_bill::{self, ReadBillRequest};
+use crate::domain::types::{IdType, ItemName, TableId, TaxRate};
+use crate::menu::MenuRepository;
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains handlers to bill and check out a table
+
+async fn fail(msg: &str, status: HttpStatus) -> Response {
+    to_json(
+        FailMsg {
+            msg: msg.to_string(),
+        },
+        status,
+    )
+    .await
+}
+
+async fn unpriced(names: Vec<ItemName>) -> Response {
+    let names: Vec<String> = names.into_iter().map(String::from).collect();
+    fail(
+        &format!("Not on the menu: {}", names.join(", ")),
+        HttpStatus::CONFLICT,
+    )
+    .await
+}
+
+pub async fn read_bill_handler(request: Request) -> Response {
+    let table_id =
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), "/tables/:tid/bill", "tid")
+        {
+            Ok(tid) => tid,
+            Err(err) => return fail(&err, HttpStatus::BAD_REQUEST).await,
+        };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    let tax_rate: TaxRate = request.extensions().get().copied().unwrap_or_default();
+
+    match read_bill::execute(repo, menu, ReadBillRequest { table_id, tax_rate }).await {
+        Ok(res) => to_json(res.bill, HttpStatus::OK).await,
+        Err(read_bill::Error::UnknowTableId) => {
+            fail("No items to bill on this table", HttpStatus::NOT_FOUND).await
+        }
+        Err(read_bill::Error::UnpricedItems(names)) => unpriced(names).await,
+        Err(read_bill::Error::Unknown) => {
+            fail("Server error", HttpStatus::INTERNAL_SERVER_ERROR).await
+        }
+    }
+}
+
+pub async fn checkout_handler(request: Request) -> Response {
+    let table_id = match parse_numeric_id::<TableId<IdType>>(
+        request.uri().path(),
+        "/tables/:tid/checkout",
+        "tid",
+    ) {
+        Ok(tid) => tid,
+        Err(err) => return fail(&err, HttpStatus::BAD_REQUEST).await,
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    let tax_rate: TaxRate = request.extensions().get().copied().unwrap_or_default();
+
+    match checkout::execute(repo, menu, events, CheckoutRequest { table_id, tax_rate }).await {
+        Ok(res) => to_json(res, HttpStatus::CREATED).await,
+        Err(checkout::Error::UnknowTableId) => {
+            fail("No items to bill on this table", HttpStatus::NOT_FOUND).await
+        }
+        Err(checkout::Error::UnpricedItems(names)) => unpriced(names).await,
+        Err(checkout::Error::Conflict) => {
+            fail(
+                "Items of the table changed during checkout, try again",
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(checkout::Error::Unknown) => {
+            fail("Server error", HttpStatus::INTERNAL_SERVER_ERROR).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::domain::types::TaxRate;
+    use crate::menu::inmemory::InMemoryMenuRepository;
+    use crate::menu::{MenuItem, MenuRepository};
+    use crate::repository::sqlite::SqliteRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn call(
+        repo: &Arc<dyn Repository>,
+        menu: &Arc<dyn MenuRepository>,
+        method: http::Method,
+        uri: &str,
+        body: &str,
+    ) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(repo.clone());
+        request.extensions_mut().insert(menu.clone());
+        request
+            .extensions_mut()
+            .insert(TaxRate::try_from("10".to_string()).unwrap());
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        let response = handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_bill_and_check_out_a_table() {
+        let repo: Arc<dyn Repository> = Arc::new(SqliteRepository::try_new("", true).unwrap());
+        let menu = InMemoryMenuRepository::new();
+        menu.insert(MenuItem::new(1, "Margherita", 950)).ok();
+        menu.insert(MenuItem::new(2, "Tiramisu", 500)).ok();
+        let menu: Arc<dyn MenuRepository> = Arc::new(menu);
+        let items = r#"{"1": {"name": "Margherita", "notes": "", "quantity": 2},
+                        "2": {"name": "Tiramisu", "notes": "", "quantity": 1}}"#;
+        call(&repo, &menu, http::Method::POST, "/tables/3/items", items).await;
+
+        let (status, bill) = call(&repo, &menu, http::Method::GET, "/tables/3/bill", "").await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(bill["lines"][0]["amount"], 1900);
+        assert_eq!(bill["subtotal"], 2400);
+        assert_eq!(bill["tax"], 240);
+        assert_eq!(bill["total"], 2640);
+
+        let (status, receipt) =
+            call(&repo, &menu, http::Method::POST, "/tables/3/checkout", "").await;
+        assert_eq!(status, HttpStatus::CREATED);
+        assert_eq!(receipt["receipt_id"], 1);
+        assert_eq!(receipt["bill"], bill);
+
+        let (_, items) = call(&repo, &menu, http::Method::GET, "/tables/3/items", "").await;
+        assert_eq!(items, serde_json::json!({}));
+        let (status, _) = call(&repo, &menu, http::Method::GET, "/tables/3/bill", "").await;
+        assert_eq!(status, HttpStatus::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_check_out_items_missing_from_the_menu() {
+        let repo: Arc<dyn Repository> = Arc::new(SqliteRepository::try_new("", true).unwrap());
+        let menu: Arc<dyn MenuRepository> = Arc::new(InMemoryMenuRepository::new());
+        let items = r#"{"1": {"name": "Lasagna", "notes": "", "quantity": 1}}"#;
+        call(&repo, &menu, http::Method::POST, "/tables/3/items", items).await;
+
+        let (status, _) = call(&repo, &menu, http::Method::POST, "/tables/3/checkout", "").await;
+        assert_eq!(status, HttpStatus::CONFLICT);
+        let (_, items) = call(&repo, &menu, http::Method::GET, "/tables/3/items", "").await;
+        assert_eq!(items.as_object().unwrap().len(), 1);
+    }
+}
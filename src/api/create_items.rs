@@ -1,20 +1,25 @@
+use crate::api::atomic::atomic_batch_response;
 use crate::api::helpers::{
-    extract_string_payload, json_body, parse_create_or_update_items, parse_numeric_id, to_json,
-    FailMsg, OpStatusResponse, StatusWithBody,
+    extract_string_payload, json_body, parse_batch_query, parse_create_or_update_items,
+    parse_numeric_id, to_json, FailMsg, OpStatusResponse, StatusWithBody,
 };
 use crate::api::HttpStatus;
 use crate::api::{Request, Response};
+use crate::domain::apply_batch::BatchRequest;
 use crate::domain::create_item::{execute, CreateItemRequest, Error};
+use crate::domain::events::ItemEvents;
+use crate::domain::prep_time::PrepTimeEstimator;
 use crate::domain::types::{IdType, Item, TableId};
+use crate::menu::{names_for_orders, MenuPolicy, MenuRepository};
 use crate::repository::Repository;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-// This function handles POST requests to create 
+// This function handles POST requests to create
 pub async fn create_items_handler(request: Request) -> Response {
     // Read table id
     let table_id = match parse_numeric_id::<TableId<IdType>>(
-        &request.uri().path(),
+        request.uri().path(),
         "/tables/:tid/items",
         "tid",
     ) {
@@ -29,12 +34,40 @@ pub async fn create_items_handler(request: Request) -> Response {
             .await
         }
     };
+    let batch_query = match parse_batch_query(request.uri().query()) {
+        Ok(query) => query,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let estimator: Arc<dyn PrepTimeEstimator> = Arc::clone(request.extensions().get().unwrap());
+    let menu_policy: MenuPolicy = request.extensions().get().copied().unwrap_or_default();
+    let menu_repo: Option<Arc<dyn MenuRepository>> = request.extensions().get().cloned();
+    let menu_names = match names_for_orders(menu_policy, menu_repo).await {
+        Ok(names) => names,
+        Err(_) => {
+            return to_json(
+                FailMsg {
+                    msg: "Unable to read the menu".to_string(),
+                },
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    };
     // parse item(s)
     let payload: String = extract_string_payload(request)
         .await
         .unwrap_or_else(|_| String::from(""));
-    let new_items = match parse_create_or_update_items(&payload) {
+    let new_items = match parse_create_or_update_items(&payload, menu_names.as_ref()) {
         Ok(items) => items,
         Err(err) => {
             return to_json(
@@ -47,16 +80,25 @@ pub async fn create_items_handler(request: Request) -> Response {
         }
     };
 
-    // insert item(s) and make response for each 
+    if batch_query.atomic {
+        let req = BatchRequest::inserts(table_id, new_items, estimator.as_ref());
+        return atomic_batch_response(repo, events, req, HttpStatus::CREATED).await;
+    }
+
+    // insert item(s) and make response for each
     let mut op_status: OpStatusResponse = HashMap::new();
-    new_items.iter().for_each(|item| {
+    for item in new_items.iter() {
         match execute(
             repo.clone(),
+            estimator.clone(),
+            events.clone(),
             CreateItemRequest {
-                table_id: table_id,
+                table_id,
                 item: item.clone(),
             },
-        ) {
+        )
+        .await
+        {
             Ok(res) => op_status.insert(
                 res.item.id,
                 StatusWithBody {
@@ -83,7 +125,7 @@ pub async fn create_items_handler(request: Request) -> Response {
                 },
             ),
         };
-    });
+    }
 
     match op_status.len() {
         1 => {
@@ -98,8 +140,10 @@ pub async fn create_items_handler(request: Request) -> Response {
 #[cfg(test)]
 mod test {
     use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::domain::types::{ItemId, TableId};
     use crate::repository::inmemory::InMemoryRepository;
-    use crate::repository::Repository;
+    use crate::repository::{FetchOneError, Repository};
     use futures::future::join_all;
     use hyper::http;
     use pretty_assertions::assert_eq;
@@ -128,6 +172,9 @@ mod test {
             .body(hyper::Body::from(body))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status(), HttpStatus::CREATED);
@@ -140,7 +187,8 @@ mod test {
         assert_eq!(json["quantity"], 1);
         assert_eq!(json["version"], 1);
         assert_eq!(json["deleted"], false);
-        assert!(json["time_to_prepare"] != "");
+        assert!(json["ready_at"].is_string());
+        assert_eq!(json["prep_minutes"], 10);
     }
     #[tokio::test]
     async fn it_should_fail_create_one_item() {
@@ -178,6 +226,9 @@ mod test {
                 .body(hyper::Body::from(b.to_string()))
                 .unwrap();
             request.extensions_mut().insert(context);
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
             let response = handle(request).await;
 
             assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
@@ -208,6 +259,9 @@ mod test {
             .body(hyper::Body::from(body))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status().clone(), HttpStatus::MULTI_STATUS);
@@ -227,7 +281,7 @@ mod test {
             assert_eq!(json[id]["body"]["quantity"], 1);
             assert_eq!(json[id]["body"]["version"], 1);
             assert_eq!(json[id]["body"]["deleted"], false);
-            assert!(json[id]["body"]["time_to_prepare"] != "");
+            assert!(json[id]["body"]["ready_at"].is_string());
         }))
         .await;
     }
@@ -255,6 +309,9 @@ mod test {
             .body(hyper::Body::from(body))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
@@ -284,4 +341,41 @@ mod test {
         let message = document.find(Name("h1")).next().unwrap().text();
         assert_eq!(message, "NOT FOUND!".to_owned());
     }
+
+    #[tokio::test]
+    async fn it_should_create_all_or_no_items_in_atomic_mode() {
+        let context: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let post = |uri: &str, body: &str| {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::POST)
+                .uri(uri)
+                .body(hyper::Body::from(body.to_string()))
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            handle(request)
+        };
+        let item = r#"{"name": "Pizza", "notes": "", "quantity": 1}"#;
+
+        let body = format!(r#"{{"1": {}, "2": {}}}"#, item, item);
+        let response = post("/tables/1/items?atomic=true", &body).await;
+        assert_eq!(response.status(), HttpStatus::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["1"]["status"], 201);
+        assert_eq!(json["2"]["body"]["name"], "Pizza");
+
+        // Item 2 exists, so item 3 must not be created either
+        let body = format!(r#"{{"3": {}, "2": {}}}"#, item, item);
+        let response = post("/tables/1/items?atomic=true", &body).await;
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+        let response = post("/tables/1/items?atomic=yes", &body).await;
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+        match context.fetch_one(TableId::from_int(1), ItemId::from_int(3)) {
+            Err(FetchOneError::UnknownItemId) => {}
+            _ => unreachable!(),
+        }
+    }
 }
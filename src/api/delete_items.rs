@@ -1,10 +1,13 @@
+use crate::api::atomic::atomic_batch_response;
 use crate::api::helpers::{
-    extract_string_payload, json_body, parse_delete_items_request, parse_numeric_id, to_json,
-    FailMsg, OpStatusResponse, StatusWithBody,
+    extract_string_payload, json_body, parse_batch_query, parse_delete_items_request,
+    parse_numeric_id, parse_version_match, to_json, FailMsg, OpStatusResponse, StatusWithBody,
 };
 use crate::api::HttpStatus;
 use crate::api::{Request, Response};
+use crate::domain::apply_batch::BatchRequest;
 use crate::domain::delete_item::{execute, DeleteOneRequest, DeleteOneResponse, Error};
+use crate::domain::events::ItemEvents;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -17,7 +20,7 @@ pub async fn delete_item_handler(request: Request) -> Response {
     let url_pattern = "/tables/:tid/items/:id";
     // Check table exists
     let table_id =
-        match parse_numeric_id::<TableId<IdType>>(&request.uri().path(), url_pattern, "tid") {
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
             Ok(tid) => tid,
             Err(err) => {
                 return to_json(
@@ -30,8 +33,9 @@ pub async fn delete_item_handler(request: Request) -> Response {
             }
         };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
     // Read ItemId
-    let item_id = match parse_numeric_id::<ItemId<IdType>>(&request.uri().path(), url_pattern, "id")
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
     {
         Ok(it_id) => it_id,
         Err(err) => {
@@ -45,10 +49,42 @@ pub async fn delete_item_handler(request: Request) -> Response {
         }
     };
 
-    match execute(repo, DeleteOneRequest { table_id, item_id }) {
-        Ok(res) => return to_json(res, HttpStatus::OK).await,
-        Err(Error::UnknowTableId) => {
+    let if_match = match parse_version_match(&request, hyper::header::IF_MATCH) {
+        Ok(if_match) => if_match,
+        Err(err) => {
             return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
+
+    match execute(
+        repo,
+        events,
+        DeleteOneRequest {
+            table_id,
+            item_id,
+            if_match,
+        },
+    )
+    .await
+    {
+        Ok(res) => to_json(res, HttpStatus::OK).await,
+        Err(Error::PreconditionFailed) => {
+            to_json(
+                FailMsg {
+                    msg: "Precondition failed: the item is in another version".to_string(),
+                },
+                HttpStatus::PRECONDITION_FAILED,
+            )
+            .await
+        }
+        Err(Error::UnknowTableId) => {
+            to_json(
                 FailMsg {
                     msg: "Unknown table id".to_string(),
                 },
@@ -57,7 +93,7 @@ pub async fn delete_item_handler(request: Request) -> Response {
             .await
         }
         Err(Error::UnknownItemId) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Unknown item id".to_string(),
                 },
@@ -66,7 +102,7 @@ pub async fn delete_item_handler(request: Request) -> Response {
             .await
         }
         Err(Error::Unknown) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Server error".to_string(),
                 },
@@ -83,7 +119,7 @@ pub async fn delete_items_handler(request: Request) -> Response {
     let url_pattern = "/tables/:tid/items";
     // Check table exists
     let table_id =
-        match parse_numeric_id::<TableId<IdType>>(&request.uri().path(), url_pattern, "tid") {
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
             Ok(tid) => tid,
             Err(err) => {
                 return to_json(
@@ -95,7 +131,20 @@ pub async fn delete_items_handler(request: Request) -> Response {
                 .await
             }
         };
+    let batch_query = match parse_batch_query(request.uri().query()) {
+        Ok(query) => query,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
 
     // parse item ids
     let payload: String = extract_string_payload(request)
@@ -114,15 +163,24 @@ pub async fn delete_items_handler(request: Request) -> Response {
         }
     };
 
+    if batch_query.atomic {
+        let req = BatchRequest::deletes(table_id, items_to_delete);
+        return atomic_batch_response(repo, events, req, HttpStatus::OK).await;
+    }
+
     let mut op_status: OpStatusResponse = HashMap::new();
-    items_to_delete.into_iter().for_each(|id| {
+    for id in items_to_delete.into_iter() {
         match execute(
             repo.clone(),
+            events.clone(),
             DeleteOneRequest {
-                table_id: table_id,
+                table_id,
                 item_id: id,
+                if_match: None,
             },
-        ) {
+        )
+        .await
+        {
             Ok(_) => op_status.insert(
                 id,
                 StatusWithBody {
@@ -139,7 +197,7 @@ pub async fn delete_items_handler(request: Request) -> Response {
                     }),
                 },
             ),
-            Err(Error::Unknown) => op_status.insert(
+            Err(Error::Unknown) | Err(Error::PreconditionFailed) => op_status.insert(
                 id,
                 StatusWithBody {
                     status: HttpStatus::BAD_REQUEST.as_u16(),
@@ -158,15 +216,18 @@ pub async fn delete_items_handler(request: Request) -> Response {
                 },
             ),
         };
-    });
+    }
     to_json(op_status, HttpStatus::MULTI_STATUS).await
 }
 
 #[cfg(test)]
 mod test {
     use crate::api::HttpStatus;
-    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, TableId};
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
     use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
     use crate::repository::Repository;
     use hyper::http;
 
@@ -187,7 +248,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -220,7 +281,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -255,13 +316,13 @@ mod test {
         ids.iter_mut().for_each(|id| {
             repo.insert(
                 TableId::from_int(1),
-                ItemId::from_int(id.clone()),
+                ItemId::from_int(*id),
                 ItemName::pizza(),
                 ItemNotes::some_notes(),
                 ItemQuantity::one(),
                 false,
                 ItemVersion::ver_one(),
-                "2023/12/12".to_string(),
+                PrepTime::from_date("2023-12-12"),
             )
             .ok();
         });
@@ -286,11 +347,88 @@ mod test {
         let json: Value = serde_json::from_str(result.as_str()).unwrap();
         ids.iter().for_each(|id| {
             assert_eq!(json[&id.to_string()]["status"], HttpStatus::OK.as_u16());
-            match context.fetch_one(TableId::from_int(1), ItemId::from_int(id.clone())) {
+            match context.fetch_one(TableId::from_int(1), ItemId::from_int(*id)) {
                 Err(_) => {}
                 _ => unreachable!(),
             }
         });
         assert_eq!(json["3"]["status"], HttpStatus::NOT_FOUND.as_u16());
     }
+
+    #[tokio::test]
+    async fn it_should_delete_all_or_no_items_in_atomic_mode() {
+        let context: Arc<dyn Repository> = Arc::new(SqliteRepository::try_new("", true).unwrap());
+        for id in [1, 2] {
+            context
+                .insert(
+                    TableId::from_int(1),
+                    ItemId::from_int(id),
+                    ItemName::pizza(),
+                    ItemNotes::some_notes(),
+                    ItemQuantity::one(),
+                    false,
+                    ItemVersion::ver_one(),
+                    PrepTime::from_date("2023-12-12"),
+                )
+                .ok();
+        }
+        let delete = |body: &str| {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::DELETE)
+                .uri("/tables/1/items?atomic=true")
+                .body(hyper::Body::from(body.to_string()))
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            handle(request)
+        };
+
+        let response = delete(r#"{"ids": [1, 3]}"#).await;
+        assert_eq!(response.status(), HttpStatus::NOT_FOUND);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["msg"], "Unknown item id 3, no item was changed");
+        assert!(context
+            .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+            .is_ok());
+
+        let response = delete(r#"{"ids": [1, 2]}"#).await;
+        assert_eq!(response.status(), HttpStatus::OK);
+        assert!(context
+            .fetch_one(TableId::from_int(1), ItemId::from_int(2))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_delete_one_item_only_if_the_etag_matches() {
+        let repo: InMemoryRepository = InMemoryRepository::new();
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::from_int(2),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        for (if_match, status) in [
+            (r#""1""#, HttpStatus::PRECONDITION_FAILED),
+            ("1", HttpStatus::BAD_REQUEST),
+            (r#""2""#, HttpStatus::OK),
+        ] {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::DELETE)
+                .uri("/tables/1/items/1")
+                .header(hyper::header::IF_MATCH, if_match)
+                .body(hyper::Body::empty())
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), status);
+        }
+    }
 }
@@ -0,0 +1,252 @@
+use crate::api::helpers::{parse_numeric_id, to_json, FailMsg};
+use crate::api::websocket::Closing;
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::events::{EventId, ItemEvent, ItemEvents};
+use crate::domain::types::{IdType, TableId};
+use hyper::http;
+use std::time::Duration;
+
+// This file contains the functions to handle GET /tables/:tid/events and GET /events,
+// the item events streamed as Server-Sent Events
+
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+// A comment line sent while nothing happens, so proxies keep the connection open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+async fn fail(msg: String, status: HttpStatus) -> Response {
+    to_json(FailMsg { msg }, status).await
+}
+
+pub async fn table_events_handler(request: Request) -> Response {
+    let table_id = match parse_numeric_id::<TableId<IdType>>(
+        request.uri().path(),
+        "/tables/:tid/events",
+        "tid",
+    ) {
+        Ok(tid) => tid,
+        Err(err) => return fail(err, HttpStatus::BAD_REQUEST).await,
+    };
+    stream_events(request, Some(table_id)).await
+}
+
+pub async fn events_handler(request: Request) -> Response {
+    stream_events(request, None).await
+}
+
+fn parse_last_event_id(request: &Request) -> Result<Option<EventId>, String> {
+    match request.headers().get(LAST_EVENT_ID_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|id| id.trim().parse().ok()) {
+            Some(id) => Ok(Some(id)),
+            None => Err(format!(
+                "'{:?}' is not a valid {}.",
+                value, LAST_EVENT_ID_HEADER
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn to_sse(event: &ItemEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id,
+        event.event.as_str(),
+        data
+    )
+}
+
+// Tells the client that the events it missed are lost, it reloads the items and
+// resumes from this id
+fn to_reset_sse(id: EventId) -> String {
+    let data = serde_json::json!({"id": id, "event": "reset"});
+    format!("id: {}\nevent: reset\ndata: {}\n\n", id, data)
+}
+
+async fn stream_events(request: Request, table_id: Option<TableId<IdType>>) -> Response {
+    let last_event_id = match parse_last_event_id(&request) {
+        Ok(id) => id,
+        Err(err) => return fail(err, HttpStatus::BAD_REQUEST).await,
+    };
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let mut subscription = events.subscribe(table_id, last_event_id);
+    let closing: Option<Closing> = request.extensions().get().cloned();
+
+    // The stream ends when the client goes away, falls too far behind or the server
+    // shuts down
+    let (mut sender, body) = hyper::Body::channel();
+    tokio::spawn(async move {
+        let closing = async move {
+            match closing {
+                Some(closing) => closing.wait().await,
+                None => futures::future::pending().await,
+            }
+        };
+        tokio::pin!(closing);
+        if let Some(id) = subscription.reset() {
+            if sender.send_data(to_reset_sse(id).into()).await.is_err() {
+                return;
+            }
+        }
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE);
+        keep_alive.tick().await;
+        loop {
+            let chunk = tokio::select! {
+                event = subscription.next() => match event {
+                    Some(event) => to_sse(&event),
+                    None => break,
+                },
+                _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+                _ = &mut closing => break,
+            };
+            if sender.send_data(chunk.into()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    http::Response::builder()
+        .status(HttpStatus::OK)
+        .header(http::header::CONTENT_TYPE, "text/event-stream")
+        .header(http::header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::LAST_EVENT_ID_HEADER;
+    use crate::api::HttpStatus;
+    use crate::domain::events::{ItemEventKind, ItemEvents};
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::domain::types::{
+        IdType, Item, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::body::HttpBody;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    fn request(
+        events: &ItemEvents,
+        method: http::Method,
+        uri: &str,
+        body: &str,
+    ) -> crate::api::Request {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        request.extensions_mut().insert(repo);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        request.extensions_mut().insert(events.clone());
+        request
+    }
+
+    fn item(id: IdType) -> Item {
+        Item::new(
+            ItemId::from_int(id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_table_events_from_the_last_event_id() {
+        let events = ItemEvents::counting_from(0);
+        events.created(TableId::from_int(1), &item(1));
+        events.created(TableId::from_int(2), &item(1));
+        events.updated(TableId::from_int(1), &item(1));
+
+        let mut request = request(&events, http::Method::GET, "/tables/1/events", "");
+        request
+            .headers_mut()
+            .insert(LAST_EVENT_ID_HEADER, "1".parse().unwrap());
+        let response = handle(request).await;
+        assert_eq!(response.status(), HttpStatus::OK);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+
+        let mut body = response.into_body();
+        let chunk = body.data().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.starts_with("id: 3\nevent: updated\ndata: {"));
+        assert!(chunk.contains(r#""table_id":1"#));
+
+        events.deleted(TableId::from_int(1), ItemId::from_int(1));
+        let chunk = body.data().await.unwrap().unwrap();
+        assert_eq!(
+            String::from_utf8(chunk.to_vec()).unwrap(),
+            "id: 4\nevent: deleted\ndata: {\"id\":4,\"event\":\"deleted\",\"table_id\":1,\"item_id\":1}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_ask_for_a_reset_when_the_missed_events_are_lost() {
+        let events = ItemEvents::counting_from(10);
+        events.created(TableId::from_int(1), &item(1));
+
+        // From another run, and older than the history
+        for last_event_id in ["20", "5"] {
+            let mut request = request(&events, http::Method::GET, "/tables/1/events", "");
+            request
+                .headers_mut()
+                .insert(LAST_EVENT_ID_HEADER, last_event_id.parse().unwrap());
+            let mut body = handle(request).await.into_body();
+            let chunk = body.data().await.unwrap().unwrap();
+            assert_eq!(
+                String::from_utf8(chunk.to_vec()).unwrap(),
+                "id: 11\nevent: reset\ndata: {\"event\":\"reset\",\"id\":11}\n\n"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_publish_the_changes_made_through_the_api() {
+        let events = ItemEvents::new();
+        let mut subscription = events.subscribe(None, None);
+
+        let body = r#"{"1": {"name": "Pizza", "notes": "", "quantity": 1}}"#;
+        handle(request(
+            &events,
+            http::Method::POST,
+            "/tables/3/items",
+            body,
+        ))
+        .await;
+
+        let event = subscription.next().await.unwrap();
+        assert_eq!(event.event, ItemEventKind::Created);
+        assert_eq!(event.table_id, TableId::from_int(3));
+        assert_eq!(
+            event.item.unwrap().name,
+            ItemName::try_from("Pizza".to_string()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_an_invalid_last_event_id() {
+        let events = ItemEvents::new();
+        let mut request = request(&events, http::Method::GET, "/events", "");
+        request
+            .headers_mut()
+            .insert(LAST_EVENT_ID_HEADER, "abc".parse().unwrap());
+        let response = handle(request).await;
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+    }
+}
@@ -1,7 +1,15 @@
+use crate::domain::kitchen_queue::{KitchenQueueRequest, QueueFilter};
+use crate::domain::read_audit::ReadAuditRequest;
+use crate::domain::read_items::ReadAllRequest;
 use crate::domain::types::{
-    IdType, Item, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, VersionType,
+    IdType, Item, ItemCursor, ItemFilter, ItemId, ItemName, ItemNotes, ItemPage, ItemPatch,
+    ItemQuantity, ItemSort, ItemState, ItemVersion, PageLimit, PrepTime, TableId, VersionMatch,
+    VersionType,
 };
+use crate::menu::{MenuCategory, MenuItem, MenuItemId, MenuNames, MenuPrice};
+use chrono::{DateTime, Utc};
 use hyper::http;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use urlpattern::Error;
@@ -10,7 +18,7 @@ use urlpattern::UrlPatternInit;
 use urlpattern::UrlPatternMatchInput;
 use urlpattern::UrlPatternResult;
 
-// This file contains helper structs and functions to read 
+// This file contains helper structs and functions to read
 // data from requests and output to responses
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,15 +28,38 @@ pub struct NewItem {
     pub quantity: i32,
     pub version: Option<i32>,
 }
+// Body of POST /menu and PUT /menu/:id, the id is taken from the url on PUT
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewMenuItem {
+    pub id: Option<i64>,
+    pub name: String,
+    pub category: String,
+    pub price: i64,
+    pub available: Option<bool>,
+}
+
 #[derive(Serialize)]
 pub struct FailMsg {
     pub msg: String,
 }
 
+// Body of a 409 on a version conflict, the client can retry on top of the item
 #[derive(Serialize)]
-pub struct MultiOperationResponse<T> {
-    pub status: String,
-    pub body: T,
+pub struct ConflictMsg {
+    pub msg: String,
+    pub version: ItemVersion<VersionType>,
+    pub item: Item,
+}
+
+impl ConflictMsg {
+    pub fn new(msg: String, item: Item) -> Self {
+        Self {
+            msg,
+            version: item.version.clone(),
+            item,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,15 +67,110 @@ pub struct ItemIdsList {
     pub ids: Vec<ItemId<IdType>>,
 }
 
+// Body of POST /tables/:tid/items/:id/transition, e.g. {"state": "ready", "version": 2}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemTransition {
+    pub state: String,
+    pub version: Option<i32>,
+}
+
+// Query string of GET /tables/:tid/items, e.g. ?name_contains=pizza&sort=-quantity
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadItemsQuery {
+    pub name_contains: Option<String>,
+    pub min_quantity: Option<String>,
+    pub max_quantity: Option<String>,
+    pub sort: Option<String>,
+    pub limit: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+// Query string of POST, PUT and DELETE /tables/:tid/items, e.g. ?atomic=true
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchQuery {
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+// Query string of GET /audit, e.g. ?table=12&since=2023-12-12T10:00:00Z
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditQuery {
+    pub table: Option<String>,
+    pub since: Option<String>,
+}
+
+// Query string of GET /kitchen/queue, e.g. ?overdue=true or ?due_within_minutes=10
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KitchenQueueQuery {
+    #[serde(default)]
+    pub overdue: bool,
+    pub due_within_minutes: Option<String>,
+}
+
+// A page of items returned when ?limit= or ?cursor= is used, the items are
+// keyed by id or listed when deleted copies are included
+#[derive(Serialize)]
+pub struct ItemsPageResponse<T> {
+    pub items: T,
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct StatusWithBody {
     pub status: u16,
     pub body: serde_json::value::Value,
 }
 
+// Correlates a websocket command with its response, chosen by the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommandId {
+    Number(u64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandOp {
+    Create,
+    Update,
+    Delete,
+}
+
+// A command sent over the websocket, e.g.
+// {"id": 1, "op": "create", "table_id": 3, "body": {"1": {"name": "Pizza", "notes": "", "quantity": 1}}}
+// body has the shape of the matching request body: NewItem by item id or ItemIdsList
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemsCommand {
+    pub id: CommandId,
+    pub op: CommandOp,
+    pub table_id: IdType,
+    #[serde(default)]
+    pub atomic: bool,
+    pub idempotency_key: Option<String>,
+    pub body: serde_json::value::Value,
+}
+
+// The answer to a command, id is None when the command could not be read
+#[derive(Serialize)]
+pub struct CommandResponse {
+    pub id: Option<CommandId>,
+    #[serde(flatten)]
+    pub result: StatusWithBody,
+}
+
 pub type CreateOrUpdateItemRequest = HashMap<ItemId<IdType>, NewItem>;
 pub type OpStatusResponse = HashMap<ItemId<IdType>, StatusWithBody>;
-pub type OpItemsResponse = HashMap<ItemId<IdType>, serde_json::value::Value>;
+// Keeps the order in which items were listed, e.g. after sorting
+pub type OpItemsResponse = IndexMap<ItemId<IdType>, serde_json::value::Value>;
 
 pub type Request = http::Request<hyper::Body>;
 pub type Response = http::Response<hyper::Body>;
@@ -130,8 +256,12 @@ pub fn parse_delete_items_request(data: &str) -> Result<Vec<ItemId<IdType>>, Str
         _ => Ok(req_items.ids),
     }
 }
-// Used to parse items from POST and PUT json data with validation
-pub fn parse_create_or_update_items(data: &str) -> Result<Vec<Item>, String> {
+// Used to parse items from POST and PUT json data with validation.
+// When menu names are given, only dishes on the menu can be ordered.
+pub fn parse_create_or_update_items(
+    data: &str,
+    menu: Option<&MenuNames>,
+) -> Result<Vec<Item>, String> {
     let items_map: CreateOrUpdateItemRequest =
         serde_json::from_str(data).map_err(|err| err.to_string())?;
 
@@ -141,24 +271,7 @@ pub fn parse_create_or_update_items(data: &str) -> Result<Vec<Item>, String> {
     for (idx, new_item) in items_map {
         let item_id: ItemId<IdType> =
             ItemId::try_from(idx.to_string()).map_err(|err| err_tpl(idx, err))?;
-        let name: ItemName = ItemName::try_from(new_item.name).map_err(|err| err_tpl(idx, err))?;
-        let notes: ItemNotes =
-            ItemNotes::try_from(new_item.notes).map_err(|err| err_tpl(idx, err))?;
-        let quantity: ItemQuantity<u32> = ItemQuantity::try_from(new_item.quantity.to_string())
-            .map_err(|err| err_tpl(idx, err))?;
-        let item_version_str: String = new_item.version.unwrap_or_else(|| 1).to_string();
-        let item_version: ItemVersion<VersionType> =
-            ItemVersion::try_from(item_version_str).map_err(|err| err_tpl(idx, err))?;
-
-        let it: Item = Item {
-            id: item_id,
-            name,
-            notes,
-            quantity,
-            deleted: false,
-            version: item_version,
-            time_to_prepare: "".to_string(),
-        };
+        let it: Item = parse_new_item(item_id, new_item, menu).map_err(|err| err_tpl(idx, err))?;
 
         items.push(it);
     }
@@ -169,12 +282,272 @@ pub fn parse_create_or_update_items(data: &str) -> Result<Vec<Item>, String> {
     }
 }
 
+// Used to parse the item of PUT /tables/:tid/items/:id json data, the id is taken from the url
+pub fn parse_update_item(
+    data: &str,
+    item_id: ItemId<IdType>,
+    menu: Option<&MenuNames>,
+) -> Result<Item, String> {
+    let new_item: NewItem = serde_json::from_str(data).map_err(|err| err.to_string())?;
+    parse_new_item(item_id, new_item, menu)
+}
+
+fn parse_new_item(
+    item_id: ItemId<IdType>,
+    new_item: NewItem,
+    menu: Option<&MenuNames>,
+) -> Result<Item, String> {
+    let name: ItemName = ItemName::try_from(new_item.name)?;
+    check_on_menu(&name, menu)?;
+    let notes: ItemNotes = ItemNotes::try_from(new_item.notes)?;
+    let quantity: ItemQuantity<u32> = ItemQuantity::try_from(new_item.quantity.to_string())?;
+    let item_version_str: String = new_item.version.unwrap_or(1).to_string();
+    let item_version: ItemVersion<VersionType> = ItemVersion::try_from(item_version_str)?;
+
+    Ok(Item::new(
+        item_id,
+        name,
+        notes,
+        quantity,
+        false,
+        item_version,
+        PrepTime::default(),
+    ))
+}
+
+fn check_on_menu(name: &ItemName, menu: Option<&MenuNames>) -> Result<(), String> {
+    match menu {
+        Some(menu) if !menu.contains(name) => Err(format!(
+            "'{}' is not on the menu.",
+            String::from(name.clone())
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Used to parse a JSON Merge Patch (RFC 7396) of an item from PATCH json data.
+// Returns the patch and the version the client read, if it sent one.
+// A null removes a field: only notes can be removed, they become empty.
+pub fn parse_item_patch(
+    data: &str,
+    menu: Option<&MenuNames>,
+) -> Result<(ItemPatch, Option<ItemVersion<VersionType>>), String> {
+    let fields = match serde_json::from_str(data).map_err(|err| err.to_string())? {
+        serde_json::Value::Object(fields) => fields,
+        _ => return Err("A patch must be a JSON object".to_string()),
+    };
+
+    let mut patch = ItemPatch::default();
+    let mut version = None;
+    for (field, value) in fields {
+        match (field.as_str(), value) {
+            ("notes", serde_json::Value::Null) => {
+                patch.notes = Some(ItemNotes::try_from(String::new())?)
+            }
+            ("version", serde_json::Value::Null) => version = None,
+            (_, serde_json::Value::Null) => return Err(format!("'{}' can't be removed", field)),
+            ("name", serde_json::Value::String(name)) => {
+                let name = ItemName::try_from(name)?;
+                check_on_menu(&name, menu)?;
+                patch.name = Some(name);
+            }
+            ("notes", serde_json::Value::String(notes)) => {
+                patch.notes = Some(ItemNotes::try_from(notes)?)
+            }
+            ("quantity", value) if value.is_u64() => {
+                patch.quantity = Some(ItemQuantity::try_from(value.to_string())?)
+            }
+            ("version", value) if value.is_u64() => {
+                version = Some(ItemVersion::try_from(value.to_string())?)
+            }
+            ("name" | "notes" | "quantity" | "version", value) => {
+                return Err(format!("'{}' is not a valid {}", value, field))
+            }
+            _ => return Err(format!("unknown field `{}`", field)),
+        }
+    }
+
+    match patch == ItemPatch::default() {
+        true => Err("An empty patch provided".to_string()),
+        false => Ok((patch, version)),
+    }
+}
+
+// Used to parse a menu item from POST /menu (path_id is None) and PUT /menu/:id json data
+pub fn parse_menu_item(data: &str, path_id: Option<MenuItemId>) -> Result<MenuItem, String> {
+    let new_item: NewMenuItem = serde_json::from_str(data).map_err(|err| err.to_string())?;
+
+    let body_id = match new_item.id {
+        Some(id) => Some(MenuItemId::try_from(id.to_string())?),
+        None => None,
+    };
+    let id = match (path_id, body_id) {
+        (Some(path_id), Some(body_id)) if path_id != body_id => {
+            return Err(format!(
+                "Menu item id {} does not match the url id {}",
+                body_id, path_id
+            ))
+        }
+        (Some(id), _) | (None, Some(id)) => id,
+        (None, None) => return Err("A menu item id is required".to_string()),
+    };
+
+    Ok(MenuItem {
+        id,
+        name: ItemName::try_from(new_item.name)?,
+        category: MenuCategory::try_from(new_item.category)?,
+        price: MenuPrice::try_from(new_item.price.to_string())?,
+        available: new_item.available.unwrap_or(true),
+    })
+}
+
+// Used to parse filter and sort query parameters of an items listing with validation
+pub fn parse_read_items_query(
+    table_id: TableId<IdType>,
+    query: Option<&str>,
+) -> Result<ReadAllRequest, String> {
+    let query: ReadItemsQuery =
+        serde_urlencoded::from_str(query.unwrap_or("")).map_err(|err| err.to_string())?;
+
+    let name_contains = match query.name_contains {
+        Some(name) => Some(String::from(ItemName::try_from(name)?)),
+        None => None,
+    };
+    let min_quantity = match query.min_quantity {
+        Some(quantity) => Some(ItemQuantity::try_from(quantity)?),
+        None => None,
+    };
+    let max_quantity = match query.max_quantity {
+        Some(quantity) => Some(ItemQuantity::try_from(quantity)?),
+        None => None,
+    };
+    if let (Some(min), Some(max)) = (&min_quantity, &max_quantity) {
+        if min > max {
+            return Err("min_quantity cannot be greater than max_quantity".to_string());
+        }
+    }
+    let sort_by = match query.sort {
+        Some(sort) => ItemSort::try_from(sort)?,
+        None => ItemSort::default(),
+    };
+    let page = match (query.limit, query.cursor) {
+        (None, None) => None,
+        (limit, cursor) => {
+            let limit = match limit {
+                Some(limit) => PageLimit::try_from(limit)?,
+                None => PageLimit::default(),
+            };
+            let after = match cursor {
+                Some(cursor) => Some(ItemCursor::try_from(cursor)?),
+                None => None,
+            };
+            Some(ItemPage {
+                after,
+                limit: usize::from(limit),
+            })
+        }
+    };
+    if page.is_some() && sort_by != ItemSort::default() {
+        return Err("sort cannot be combined with limit or cursor".to_string());
+    }
+
+    Ok(ReadAllRequest {
+        table_id,
+        include_deleted: query.include_deleted,
+        filter: ItemFilter {
+            name_contains,
+            min_quantity,
+            max_quantity,
+        },
+        sort_by,
+        page,
+    })
+}
+
+pub fn parse_batch_query(query: Option<&str>) -> Result<BatchQuery, String> {
+    serde_urlencoded::from_str(query.unwrap_or("")).map_err(|err| err.to_string())
+}
+
+pub fn parse_transition(
+    data: &str,
+) -> Result<(ItemState, Option<ItemVersion<VersionType>>), String> {
+    let transition: ItemTransition = serde_json::from_str(data).map_err(|err| err.to_string())?;
+
+    let state = ItemState::try_from(transition.state)?;
+    let version = match transition.version {
+        Some(version) => Some(ItemVersion::try_from(version.to_string())?),
+        None => None,
+    };
+    Ok((state, version))
+}
+
+pub fn parse_kitchen_queue_query(query: Option<&str>) -> Result<KitchenQueueRequest, String> {
+    let query: KitchenQueueQuery =
+        serde_urlencoded::from_str(query.unwrap_or("")).map_err(|err| err.to_string())?;
+
+    let filter = match (query.overdue, query.due_within_minutes) {
+        (false, None) => QueueFilter::All,
+        (true, None) => QueueFilter::Overdue,
+        (false, Some(minutes)) => match minutes.parse::<u32>() {
+            Ok(minutes) => QueueFilter::DueWithin(chrono::Duration::minutes(minutes as i64)),
+            Err(_) => return Err(format!("'{}' is not a valid number of minutes.", minutes)),
+        },
+        (true, Some(_)) => return Err("overdue cannot be used with due_within_minutes".to_string()),
+    };
+    Ok(KitchenQueueRequest { filter })
+}
+
+pub fn parse_audit_query(query: Option<&str>) -> Result<ReadAuditRequest, String> {
+    let query: AuditQuery =
+        serde_urlencoded::from_str(query.unwrap_or("")).map_err(|err| err.to_string())?;
+
+    let table_id = match query.table {
+        Some(table) => Some(TableId::try_from(table)?),
+        None => None,
+    };
+    let since = match query.since {
+        Some(since) => match DateTime::parse_from_rfc3339(&since) {
+            Ok(since) => Some(since.with_timezone(&Utc)),
+            Err(_) => return Err(format!("'{}' is not a valid RFC 3339 timestamp.", since)),
+        },
+        None => None,
+    };
+    Ok(ReadAuditRequest { table_id, since })
+}
+
+// Read an If-Match or If-None-Match header, None when it is missing. If-Match takes
+// strong tags only, If-None-Match compares weak ones too.
+pub fn parse_version_match(
+    request: &Request,
+    header: http::header::HeaderName,
+) -> Result<Option<VersionMatch>, String> {
+    let parse = match header == http::header::IF_MATCH {
+        true => VersionMatch::try_strong,
+        false => VersionMatch::try_from,
+    };
+    match request.headers().get(&header) {
+        Some(value) => value
+            .to_str()
+            .map_err(|err| err.to_string())
+            .and_then(|value| parse(value.to_string()))
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn with_etag(mut response: Response, version: &ItemVersion<VersionType>) -> Response {
+    if let Ok(etag) = http::HeaderValue::from_str(&version.etag()) {
+        response.headers_mut().insert(http::header::ETAG, etag);
+    }
+    response
+}
+
 // Read table and item id from string url
 pub fn parse_numeric_id<T>(url: &str, url_pattern: &str, numeric_group: &str) -> Result<T, String>
 where
     T: TryFrom<String, Error = String>,
 {
-    let match_url_result = match_url_result(&url, url_pattern).map_err(|err| err.to_string())?;
+    let match_url_result = match_url_result(url, url_pattern).map_err(|err| err.to_string())?;
     let numeric_id_str = match_url_result
         .unwrap()
         .pathname
@@ -182,7 +555,7 @@ where
         .get(numeric_group)
         .ok_or_else(|| format!("Numeric group '{}' not found", numeric_group))?
         .clone();
-    Ok(T::try_from(numeric_id_str).map_err(|err| format!("{}", err))?)
+    T::try_from(numeric_id_str).map_err(|err| err.to_string())
 }
 
 pub fn json_body<T: Serialize>(body: T) -> serde_json::Value {
@@ -191,9 +564,14 @@ pub fn json_body<T: Serialize>(body: T) -> serde_json::Value {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::types::{IdType, TableId};
+    use crate::domain::types::{IdType, ItemNotes, ItemVersion, TableId};
 
-    use super::{parse_create_or_update_items, parse_delete_items_request, parse_numeric_id};
+    use crate::menu::{MenuItem, MenuItemId, MenuNames};
+
+    use super::{
+        parse_create_or_update_items, parse_delete_items_request, parse_item_patch,
+        parse_menu_item, parse_numeric_id, parse_read_items_query,
+    };
 
     #[test]
     fn a_parse_numeric_ids_from_url() {
@@ -206,16 +584,10 @@ mod tests {
         };
 
         url = "/tables/abc/items/1";
-        match parse_numeric_id::<TableId<IdType>>(url, pattern, "tid") {
-            Ok(_) => unreachable!(),
-            _ => {}
-        };
+        assert!(parse_numeric_id::<TableId<IdType>>(url, pattern, "tid").is_err());
 
         url = "/tables/0/items/1";
-        match parse_numeric_id::<TableId<IdType>>(url, pattern, "tid") {
-            Ok(_) => unreachable!(),
-            _ => {}
-        };
+        assert!(parse_numeric_id::<TableId<IdType>>(url, pattern, "tid").is_err());
     }
 
     #[test]
@@ -233,10 +605,7 @@ mod tests {
         {
             "ids": [1, 2, 3]
         }"#;
-        match parse_delete_items_request(data) {
-            Err(_) => unreachable!(),
-            _ => {}
-        };
+        assert!(parse_delete_items_request(data).is_ok());
     }
 
     #[test]
@@ -249,7 +618,7 @@ mod tests {
                 "quantity": 100
             }
         }"#;
-        match parse_create_or_update_items(data) {
+        match parse_create_or_update_items(data, None) {
             Err(_) => {}
             _ => unreachable!(),
         };
@@ -267,9 +636,142 @@ mod tests {
                 "quantity": 100
             }
         }"#;
-        match parse_create_or_update_items(data) {
-            Err(_) => unreachable!(),
-            _ => {}
+        assert!(parse_create_or_update_items(data, None).is_ok());
+    }
+
+    #[test]
+    fn a_parse_create_update_items_in_strict_menu_mode() {
+        let menu = MenuNames::from_items(&[MenuItem::new(1, "Name from menu", 950)]);
+        let data = r#"
+        {
+            "1": {
+                "name": "name from menu",
+                "notes": "Notes from waiter",
+                "quantity": 1
+            }
+        }"#;
+        assert!(parse_create_or_update_items(data, Some(&menu)).is_ok());
+
+        let data = r#"
+        {
+            "1": {
+                "name": "Name from menu",
+                "notes": "Notes from waiter",
+                "quantity": 1
+            },
+            "2": {
+                "name": "Piza",
+                "notes": "Notes from waiter",
+                "quantity": 1
+            }
+        }"#;
+        assert!(parse_create_or_update_items(data, None).is_ok());
+        match parse_create_or_update_items(data, Some(&menu)) {
+            Err(err) => assert!(err.contains("'Piza' is not on the menu")),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn a_parse_menu_item() {
+        let data = r#"{"id": 3, "name": "Tiramisu", "category": "Desserts", "price": 550}"#;
+        match parse_menu_item(data, None) {
+            Ok(item) => {
+                assert_eq!(item.id, MenuItemId::from_int(3));
+                assert!(item.available);
+            }
+            _ => unreachable!(),
+        };
+        assert!(parse_menu_item(data, Some(MenuItemId::from_int(3))).is_ok());
+        assert!(parse_menu_item(data, Some(MenuItemId::from_int(4))).is_err());
+
+        let data = r#"{"name": "Tiramisu", "category": "Desserts", "price": -1}"#;
+        assert!(parse_menu_item(data, Some(MenuItemId::from_int(3))).is_err());
+        let data = r#"{"name": "Tiramisu", "category": "Desserts", "price": 550}"#;
+        assert!(parse_menu_item(data, None).is_err());
+        assert!(parse_menu_item(data, Some(MenuItemId::from_int(3))).is_ok());
+    }
+
+    #[test]
+    fn a_parse_read_items_query() {
+        match parse_read_items_query(TableId::from_int(1), None) {
+            Ok(req) => {
+                assert!(req.filter.name_contains.is_none());
+                assert!(!req.sort_by.descending);
+            }
+            _ => unreachable!(),
+        };
+
+        let query = "name_contains=pizza&min_quantity=2&max_quantity=5&sort=-ready_at";
+        match parse_read_items_query(TableId::from_int(1), Some(query)) {
+            Ok(req) => {
+                assert_eq!(req.filter.name_contains, Some("pizza".to_string()));
+                assert!(req.sort_by.descending);
+                assert!(req.page.is_none());
+            }
+            _ => unreachable!(),
+        };
+
+        match parse_read_items_query(TableId::from_int(1), Some("cursor=MTox")) {
+            Ok(req) => {
+                let page = req.page.unwrap();
+                assert_eq!(page.limit, 50);
+                assert!(page.after.is_some());
+            }
+            _ => unreachable!(),
+        };
+
+        for query in [
+            "min_quantity=abc",
+            "min_quantity=5&max_quantity=2",
+            "sort=notes",
+            "name_contains=%3Cscript%3E",
+            "unknown=1",
+            "limit=0",
+            "limit=10&sort=-quantity",
+            "cursor=abc",
+        ] {
+            match parse_read_items_query(TableId::from_int(1), Some(query)) {
+                Err(_) => {}
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    #[test]
+    fn a_parse_item_patch() {
+        match parse_item_patch(r#"{"notes": "No cheese", "version": 2}"#, None) {
+            Ok((patch, version)) => {
+                assert_eq!(
+                    patch.notes,
+                    Some(ItemNotes::from_str("No cheese".to_string()))
+                );
+                assert!(patch.name.is_none() && patch.quantity.is_none());
+                assert_eq!(version, Some(ItemVersion::from_int(2)));
+            }
+            _ => unreachable!(),
         };
+        match parse_item_patch(r#"{"notes": null}"#, None) {
+            Ok((patch, version)) => {
+                assert_eq!(patch.notes, Some(ItemNotes::from_str(String::new())));
+                assert!(version.is_none());
+            }
+            _ => unreachable!(),
+        };
+
+        let menu = MenuNames::from_items(&[MenuItem::new(1, "Some pizza", 950)]);
+        assert!(parse_item_patch(r#"{"name": "Some pizza"}"#, Some(&menu)).is_ok());
+        for data in [
+            r#"{"name": "Sushi"}"#,
+            r#"{"name": null}"#,
+            r#"{"quantity": -1}"#,
+            r#"{"quantity": "2"}"#,
+            r#"{"deleted": true}"#,
+            r#"{"version": 2}"#,
+            r#"{}"#,
+            r#"[]"#,
+        ] {
+            assert!(parse_item_patch(data, Some(&menu)).is_err());
+        }
     }
 }
@@ -0,0 +1,215 @@
+use crate::api::helpers::{to_json, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::idempotency::{
+    claim, complete, release, request_hash, ClaimRequest, IdempotencyPolicy,
+};
+use crate::domain::types::{IdempotencyKey, KeyClaim, StoredResponse};
+use crate::repository::Repository;
+use futures::{future::FutureExt, Future};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+// This file contains the Idempotency-Key support of the item write handlers: the
+// response of the first request with a key is stored and replayed to its retries
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+pub async fn idempotent<H, F>(request: Request, handler: H) -> Response
+where
+    H: FnOnce(Request) -> F,
+    F: Future<Output = Response>,
+{
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => return handler(request).await,
+        Some(value) => match value
+            .to_str()
+            .map_err(|err| err.to_string())
+            .and_then(|value| IdempotencyKey::try_from(value.to_string()))
+        {
+            Ok(key) => key,
+            Err(err) => return fail(err, HttpStatus::BAD_REQUEST).await,
+        },
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let policy: IdempotencyPolicy = request.extensions().get().copied().unwrap_or_default();
+
+    // the body is read here to be hashed, the handler gets a copy of it
+    let (parts, body) = request.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(err) => return fail(err.to_string(), HttpStatus::BAD_REQUEST).await,
+    };
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or_else(|| parts.uri.path());
+    let claim_request = ClaimRequest {
+        key: key.clone(),
+        request_hash: request_hash(parts.method.as_str(), path_and_query, &bytes),
+        policy,
+    };
+    match claim(repo.clone(), claim_request).await {
+        Ok(KeyClaim::Claimed) => {}
+        Ok(KeyClaim::Replay(stored)) => return replay(stored),
+        Ok(KeyClaim::InProgress) => {
+            return fail(
+                "A request with this idempotency key is in progress".to_string(),
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Ok(KeyClaim::Mismatch) => {
+            return fail(
+                "The idempotency key was used for a different request".to_string(),
+                HttpStatus::UNPROCESSABLE_ENTITY,
+            )
+            .await
+        }
+        Err(_) => {
+            return fail(
+                "Unable to check the idempotency key".to_string(),
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    }
+
+    let request = Request::from_parts(parts, hyper::Body::from(bytes));
+    let response = match AssertUnwindSafe(handler(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(panic) => {
+            release(repo, key).await.ok();
+            std::panic::resume_unwind(panic)
+        }
+    };
+
+    // server errors are not stored so that a retry can succeed
+    let (parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) if !parts.status.is_server_error() => bytes,
+        Ok(bytes) => {
+            release(repo, key).await.ok();
+            return Response::from_parts(parts, hyper::Body::from(bytes));
+        }
+        Err(_) => {
+            release(repo, key).await.ok();
+            return fail(
+                "Unable to read the response".to_string(),
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await;
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        body: String::from_utf8_lossy(&bytes).to_string(),
+    };
+    if complete(repo.clone(), key.clone(), stored).await.is_err() {
+        release(repo, key).await.ok();
+    }
+    Response::from_parts(parts, hyper::Body::from(bytes))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    hyper::Response::builder()
+        .status(HttpStatus::from_u16(stored.status).unwrap_or(HttpStatus::OK))
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(IDEMPOTENT_REPLAYED_HEADER, "true")
+        .body(hyper::Body::from(stored.body))
+        .unwrap()
+}
+
+async fn fail(msg: String, status: HttpStatus) -> Response {
+    to_json(FailMsg { msg }, status).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::handle;
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    fn repos() -> Vec<Arc<dyn Repository>> {
+        vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).ok().unwrap()),
+        ]
+    }
+
+    async fn post(
+        repo: Arc<dyn Repository>,
+        policy: IdempotencyPolicy,
+        key: &str,
+        name: &str,
+    ) -> Response {
+        let body = format!(
+            r#"{{"1": {{"name": "{}", "notes": "Notes from waiter", "quantity": 1}}}}"#,
+            name
+        );
+        let mut request = hyper::Request::builder()
+            .method(http::Method::POST)
+            .uri("/tables/1/items")
+            .header(IDEMPOTENCY_KEY_HEADER, key)
+            .body(hyper::Body::from(body))
+            .unwrap();
+        request.extensions_mut().insert(repo);
+        request.extensions_mut().insert(policy);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        handle(request).await
+    }
+
+    async fn body_json(response: Response) -> Value {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_replay_the_response_of_a_retried_create() {
+        for repo in repos() {
+            let policy = IdempotencyPolicy::default();
+            let first = post(repo.clone(), policy, "order-1", "Some pizza").await;
+            assert_eq!(first.status(), HttpStatus::CREATED);
+            assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+            let first = body_json(first).await;
+
+            let retry = post(repo.clone(), policy, "order-1", "Some pizza").await;
+            assert_eq!(retry.status(), HttpStatus::CREATED);
+            assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+            assert_eq!(body_json(retry).await, first);
+
+            let other = post(repo, policy, "order-1", "Other pizza").await;
+            assert_eq!(other.status(), HttpStatus::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_run_the_request_again_once_the_key_expired() {
+        for repo in repos() {
+            let policy = IdempotencyPolicy::from_minutes(0);
+            let first = post(repo.clone(), policy, "order-1", "Some pizza").await;
+            assert_eq!(first.status(), HttpStatus::CREATED);
+
+            // the item exists, so the create fails without a stored response
+            let retry = post(repo, policy, "order-1", "Some pizza").await;
+            assert!(retry.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+            assert_eq!(retry.status(), HttpStatus::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_an_invalid_key() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let response = post(repo, IdempotencyPolicy::default(), "", "Some pizza").await;
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+    }
+}
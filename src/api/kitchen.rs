@@ -0,0 +1,110 @@
+use crate::api::helpers::{parse_kitchen_queue_query, to_json, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::kitchen_queue::{execute, Error};
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains the function to handle GET /kitchen/queue
+
+async fn fail(msg: String, status: HttpStatus) -> Response {
+    to_json(FailMsg { msg }, status).await
+}
+
+pub async fn kitchen_queue_handler(request: Request) -> Response {
+    let req = match parse_kitchen_queue_query(request.uri().query()) {
+        Ok(req) => req,
+        Err(err) => return fail(err, HttpStatus::BAD_REQUEST).await,
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+
+    match execute(repo, req).await {
+        Ok(res) => to_json(res.items, HttpStatus::OK).await,
+        Err(Error::Unknown) => {
+            fail(
+                "Server error".to_string(),
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn call(
+        repo: &Arc<dyn Repository>,
+        method: http::Method,
+        uri: &str,
+        body: &str,
+    ) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(repo.clone());
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        let response = handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_list_the_items_of_every_table_with_their_table() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let items = r#"{"1": {"name": "Pizza", "notes": "", "quantity": 1}}"#;
+        call(&repo, http::Method::POST, "/tables/12/items", items).await;
+        call(&repo, http::Method::POST, "/tables/3/items", items).await;
+
+        let (status, queue) = call(&repo, http::Method::GET, "/kitchen/queue", "").await;
+        assert_eq!(status, HttpStatus::OK);
+        let tables: Vec<u64> = queue
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|queued| queued["table_id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(tables.len(), 2);
+        assert!(tables.contains(&12) && tables.contains(&3));
+        assert_eq!(queue[0]["name"], "Pizza");
+        assert_eq!(queue[0]["prep_minutes"], 10);
+
+        // Both items are ready in 10 minutes
+        let (_, queue) = call(&repo, http::Method::GET, "/kitchen/queue?overdue=true", "").await;
+        assert_eq!(queue, serde_json::json!([]));
+        let uri = "/kitchen/queue?due_within_minutes=5";
+        let (_, queue) = call(&repo, http::Method::GET, uri, "").await;
+        assert_eq!(queue, serde_json::json!([]));
+        let uri = "/kitchen/queue?due_within_minutes=15";
+        let (_, queue) = call(&repo, http::Method::GET, uri, "").await;
+        assert_eq!(queue.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_bad_filters() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        for uri in [
+            "/kitchen/queue?due_within_minutes=-1",
+            "/kitchen/queue?overdue=true&due_within_minutes=5",
+            "/kitchen/queue?table=1",
+        ] {
+            let (status, _) = call(&repo, http::Method::GET, uri, "").await;
+            assert_eq!(status, HttpStatus::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::api::helpers::{
+    extract_string_payload, parse_menu_item, parse_numeric_id, to_json, FailMsg,
+};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::menu::{run, MenuError, MenuItemId, MenuRepository};
+use serde::Serialize;
+use std::sync::Arc;
+
+// This file contains handlers of the /menu endpoints
+
+#[derive(Serialize)]
+pub struct MenuItemDeleted {
+    pub id: MenuItemId,
+}
+
+async fn menu_error(err: MenuError) -> Response {
+    match err {
+        MenuError::Conflict => {
+            to_json(
+                FailMsg {
+                    msg: "A menu item with this id or name already exists".to_string(),
+                },
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        MenuError::UnknownItemId => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown menu item id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        MenuError::Unknown => {
+            to_json(
+                FailMsg {
+                    msg: "Server error".to_string(),
+                },
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    }
+}
+
+async fn bad_request(msg: String) -> Response {
+    to_json(FailMsg { msg }, HttpStatus::BAD_REQUEST).await
+}
+
+pub async fn read_menu_handler(request: Request) -> Response {
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    match run(menu, |menu| menu.fetch_all()).await {
+        Ok(items) => to_json(items, HttpStatus::OK).await,
+        Err(err) => menu_error(err).await,
+    }
+}
+
+pub async fn read_menu_item_handler(request: Request) -> Response {
+    let id = match parse_numeric_id::<MenuItemId>(request.uri().path(), "/menu/:id", "id") {
+        Ok(id) => id,
+        Err(err) => return bad_request(err).await,
+    };
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    match run(menu, move |menu| menu.fetch_one(id)).await {
+        Ok(item) => to_json(item, HttpStatus::OK).await,
+        Err(err) => menu_error(err).await,
+    }
+}
+
+pub async fn create_menu_item_handler(request: Request) -> Response {
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    let payload: String = extract_string_payload(request)
+        .await
+        .unwrap_or_else(|_| String::from(""));
+    let item = match parse_menu_item(&payload, None) {
+        Ok(item) => item,
+        Err(err) => return bad_request(err).await,
+    };
+    match run(menu, move |menu| menu.insert(item)).await {
+        Ok(item) => to_json(item, HttpStatus::CREATED).await,
+        Err(err) => menu_error(err).await,
+    }
+}
+
+pub async fn update_menu_item_handler(request: Request) -> Response {
+    let id = match parse_numeric_id::<MenuItemId>(request.uri().path(), "/menu/:id", "id") {
+        Ok(id) => id,
+        Err(err) => return bad_request(err).await,
+    };
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    let payload: String = extract_string_payload(request)
+        .await
+        .unwrap_or_else(|_| String::from(""));
+    let item = match parse_menu_item(&payload, Some(id)) {
+        Ok(item) => item,
+        Err(err) => return bad_request(err).await,
+    };
+    match run(menu, move |menu| menu.update(item)).await {
+        Ok(item) => to_json(item, HttpStatus::OK).await,
+        Err(err) => menu_error(err).await,
+    }
+}
+
+pub async fn delete_menu_item_handler(request: Request) -> Response {
+    let id = match parse_numeric_id::<MenuItemId>(request.uri().path(), "/menu/:id", "id") {
+        Ok(id) => id,
+        Err(err) => return bad_request(err).await,
+    };
+    let menu: Arc<dyn MenuRepository> = Arc::clone(request.extensions().get().unwrap());
+    match run(menu, move |menu| menu.delete(id)).await {
+        Ok(_) => to_json(MenuItemDeleted { id }, HttpStatus::OK).await,
+        Err(err) => menu_error(err).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::menu::inmemory::InMemoryMenuRepository;
+    use crate::menu::{MenuPolicy, MenuRepository};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn call(
+        menu: &Arc<dyn MenuRepository>,
+        method: http::Method,
+        uri: &str,
+        body: &str,
+    ) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(menu.clone());
+        let response = handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_manage_menu_items() {
+        let menu: Arc<dyn MenuRepository> = Arc::new(InMemoryMenuRepository::new());
+
+        let body = r#"{"id": 1, "name": "Margherita", "category": "Pizza", "price": 950}"#;
+        let (status, json) = call(&menu, http::Method::POST, "/menu", body).await;
+        assert_eq!(status, HttpStatus::CREATED);
+        assert_eq!(json["name"], "Margherita");
+        assert_eq!(json["available"], true);
+
+        let (status, _) = call(&menu, http::Method::POST, "/menu", body).await;
+        assert_eq!(status, HttpStatus::CONFLICT);
+
+        let body =
+            r#"{"name": "Margherita", "category": "Pizza", "price": 1050, "available": false}"#;
+        let (status, json) = call(&menu, http::Method::PUT, "/menu/1", body).await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json["price"], 1050);
+
+        let (status, json) = call(&menu, http::Method::GET, "/menu/1", "").await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json["available"], false);
+
+        let (status, json) = call(&menu, http::Method::GET, "/menu", "").await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json.as_array().unwrap().len(), 1);
+
+        let (status, _) = call(&menu, http::Method::DELETE, "/menu/1", "").await;
+        assert_eq!(status, HttpStatus::OK);
+        let (status, _) = call(&menu, http::Method::GET, "/menu/1", "").await;
+        assert_eq!(status, HttpStatus::NOT_FOUND);
+        let (status, _) = call(&menu, http::Method::GET, "/menu/abc", "").await;
+        assert_eq!(status, HttpStatus::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_items_not_on_the_menu_in_strict_mode() {
+        let menu: Arc<dyn MenuRepository> = Arc::new(InMemoryMenuRepository::new());
+        let body = r#"{"id": 1, "name": "Margherita", "category": "Pizza", "price": 950}"#;
+        call(&menu, http::Method::POST, "/menu", body).await;
+        let context: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+
+        for (name, strict, expected) in [
+            ("Piza", true, HttpStatus::BAD_REQUEST),
+            ("Margherita", true, HttpStatus::CREATED),
+            ("Piza", false, HttpStatus::CREATED),
+        ] {
+            let body = format!(
+                r#"{{"1": {{"name": "{}", "notes": "", "quantity": 1}}}}"#,
+                name
+            );
+            let mut request = hyper::Request::builder()
+                .method(http::Method::POST)
+                .uri(format!("/tables/{}/items", name.len()))
+                .body(hyper::Body::from(body))
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            request.extensions_mut().insert(menu.clone());
+            request.extensions_mut().insert(MenuPolicy { strict });
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), expected);
+        }
+    }
+}
@@ -1,16 +1,41 @@
 use hyper::http;
 
-pub mod server;
+pub mod admin;
+pub mod atomic;
+pub mod audit;
+pub mod bill;
 pub mod create_items;
 pub mod delete_items;
+pub mod events;
 pub mod helpers;
+pub mod idempotency;
+pub mod kitchen;
+pub mod menu;
 pub mod read_item;
+pub mod restore_item;
+pub mod server;
+pub mod transition_item;
+pub mod update_item;
 pub mod update_items;
+pub mod websocket;
 
 use crate::api::helpers::{four_oh_four, match_url};
+use admin::reload_menu_catalog_handler;
+use audit::{audited, read_audit_handler};
+use bill::{checkout_handler, read_bill_handler};
 use create_items::create_items_handler;
 use delete_items::{delete_item_handler, delete_items_handler};
-use read_item::{read_item_handler, read_items_handler};
+use events::{events_handler, table_events_handler};
+use idempotency::idempotent;
+use kitchen::kitchen_queue_handler;
+use menu::{
+    create_menu_item_handler, delete_menu_item_handler, read_menu_handler, read_menu_item_handler,
+    update_menu_item_handler,
+};
+use read_item::{read_item_handler, read_item_history_handler, read_items_handler};
+use restore_item::restore_item_handler;
+use transition_item::transition_item_handler;
+use update_item::{patch_item_handler, update_item_handler};
 use update_items::update_items_handler;
 pub type Request = http::Request<hyper::Body>;
 pub type Response = http::Response<hyper::Body>;
@@ -26,30 +51,96 @@ use hyper::StatusCode as HttpStatus;
 ///
 /// An asynchronous `Response` object representing the HTTP response to be sent back to the client.
 pub async fn handle(request: Request) -> Response {
+    // Item changes of the request are logged under its caller
+    let request = match audited(request).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
     // pattern match for both the method and the path of the request
     match (request.method(), request.uri().path()) {
         // Delete
-        (m, s) if m.eq(&hyper::Method::DELETE) && match_url(&s, "/tables/:tid/items/:id") => {
+        (m, s) if m.eq(&hyper::Method::DELETE) && match_url(s, "/tables/:tid/items/:id") => {
             delete_item_handler(request).await
         }
-        (m, s) if m.eq(&hyper::Method::DELETE) && match_url(&s, "/tables/:tid/items") => {
-            delete_items_handler(request).await
+        (m, s) if m.eq(&hyper::Method::DELETE) && match_url(s, "/tables/:tid/items") => {
+            idempotent(request, delete_items_handler).await
         }
         // Get
-        (m, s) if m.eq(&hyper::Method::GET) && match_url(&s, "/tables/:tid/items/:id") => {
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/tables/:tid/items/:id") => {
             read_item_handler(request).await
         }
-        (m, s) if m.eq(&hyper::Method::GET) && match_url(&s, "/tables/:tid/items") => {
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/tables/:tid/items") => {
             read_items_handler(request).await
         }
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/tables/:tid/items/:id/history") => {
+            read_item_history_handler(request).await
+        }
+        // Events
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/tables/:tid/events") => {
+            table_events_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/events") => {
+            events_handler(request).await
+        }
+        // Restore
+        (m, s) if m.eq(&hyper::Method::POST) && match_url(s, "/tables/:tid/items/:id/restore") => {
+            restore_item_handler(request).await
+        }
+        // Transition
+        (m, s)
+            if m.eq(&hyper::Method::POST) && match_url(s, "/tables/:tid/items/:id/transition") =>
+        {
+            transition_item_handler(request).await
+        }
 
         //Update
-        (m, s) if m.eq(&hyper::Method::PUT) && match_url(&s, "/tables/:tid/items") => {
-            update_items_handler(request).await
+        (m, s) if m.eq(&hyper::Method::PUT) && match_url(s, "/tables/:tid/items/:id") => {
+            update_item_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::PATCH) && match_url(s, "/tables/:tid/items/:id") => {
+            patch_item_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::PUT) && match_url(s, "/tables/:tid/items") => {
+            idempotent(request, update_items_handler).await
         }
         //Create
-        (m, s) if m.eq(&hyper::Method::POST) && match_url(&s, "/tables/:tid/items") => {
-            create_items_handler(request).await
+        (m, s) if m.eq(&hyper::Method::POST) && match_url(s, "/tables/:tid/items") => {
+            idempotent(request, create_items_handler).await
+        }
+        // Bill
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/tables/:tid/bill") => {
+            read_bill_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::POST) && match_url(s, "/tables/:tid/checkout") => {
+            checkout_handler(request).await
+        }
+        // Menu
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/menu") => {
+            read_menu_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/menu/:id") => {
+            read_menu_item_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::POST) && match_url(s, "/menu") => {
+            create_menu_item_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::PUT) && match_url(s, "/menu/:id") => {
+            update_menu_item_handler(request).await
+        }
+        (m, s) if m.eq(&hyper::Method::DELETE) && match_url(s, "/menu/:id") => {
+            delete_menu_item_handler(request).await
+        }
+        // Kitchen
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/kitchen/queue") => {
+            kitchen_queue_handler(request).await
+        }
+        // Audit
+        (m, s) if m.eq(&hyper::Method::GET) && match_url(s, "/audit") => {
+            read_audit_handler(request).await
+        }
+        // Admin
+        (m, s) if m.eq(&hyper::Method::POST) && match_url(s, "/admin/menu-catalog/reload") => {
+            reload_menu_catalog_handler(request).await
         }
 
         // Anything else
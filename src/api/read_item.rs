@@ -1,11 +1,16 @@
-use crate::api::helpers::{parse_numeric_id, to_json, FailMsg, OpItemsResponse};
+use crate::api::helpers::{
+    parse_numeric_id, parse_read_items_query, parse_version_match, to_json, with_etag, FailMsg,
+    ItemsPageResponse, OpItemsResponse,
+};
 use crate::api::HttpStatus;
 use crate::api::{Request, Response};
+use crate::domain::read_history::{
+    execute as execute_history, Error as ErrorHistory, ReadHistoryRequest,
+};
 use crate::domain::read_item::{execute, Error, ReadRequest};
-use crate::domain::read_items::{execute as execute_fetch_all, Error as ErrorAll, ReadAllRequest};
+use crate::domain::read_items::{execute as execute_fetch_all, Error as ErrorAll};
 use crate::domain::types::{IdType, ItemId, TableId};
 use crate::repository::Repository;
-use std::collections::HashMap;
 use std::sync::Arc;
 
 // This file contains functions to handle GET requests
@@ -14,7 +19,7 @@ pub async fn read_item_handler(request: Request) -> Response {
     // Check table exists
     let url_pattern = "/tables/:tid/items/:id";
     let table_id =
-        match parse_numeric_id::<TableId<IdType>>(&request.uri().path(), url_pattern, "tid") {
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
             Ok(tid) => tid,
             Err(err) => {
                 return to_json(
@@ -28,7 +33,7 @@ pub async fn read_item_handler(request: Request) -> Response {
         };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
     // Read ItemId
-    let item_id = match parse_numeric_id::<ItemId<IdType>>(&request.uri().path(), url_pattern, "id")
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
     {
         Ok(it_id) => it_id,
         Err(err) => {
@@ -42,11 +47,35 @@ pub async fn read_item_handler(request: Request) -> Response {
         }
     };
 
+    let if_none_match = match parse_version_match(&request, hyper::header::IF_NONE_MATCH) {
+        Ok(if_none_match) => if_none_match,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
+
     //retrieve item
-    match execute(repo, ReadRequest { table_id, item_id }) {
-        Ok(res) => return to_json(res.item, HttpStatus::OK).await,
+    match execute(repo, ReadRequest { table_id, item_id }).await {
+        // the client already holds this version
+        Ok(res) if if_none_match.is_some_and(|tags| tags.matches(&res.item.version)) => {
+            let response = hyper::Response::builder()
+                .status(HttpStatus::NOT_MODIFIED)
+                .body(hyper::Body::empty())
+                .unwrap();
+            with_etag(response, &res.item.version)
+        }
+        Ok(res) => {
+            let version = res.item.version.clone();
+            with_etag(to_json(res.item, HttpStatus::OK).await, &version)
+        }
         Err(Error::UnknowTableId) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Unknown table id".to_string(),
                 },
@@ -55,7 +84,7 @@ pub async fn read_item_handler(request: Request) -> Response {
             .await
         }
         Err(Error::UnknownItemId) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Unknown item id".to_string(),
                 },
@@ -64,7 +93,69 @@ pub async fn read_item_handler(request: Request) -> Response {
             .await
         }
         Err(Error::Unknown) => {
+            to_json(
+                FailMsg {
+                    msg: "Server error".to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    }
+}
+
+pub async fn read_item_history_handler(request: Request) -> Response {
+    let url_pattern = "/tables/:tid/items/:id/history";
+    let table_id =
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
+            Ok(tid) => tid,
+            Err(err) => {
+                return to_json(
+                    FailMsg {
+                        msg: err.to_string(),
+                    },
+                    HttpStatus::BAD_REQUEST,
+                )
+                .await
+            }
+        };
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
+    {
+        Ok(it_id) => it_id,
+        Err(err) => {
             return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+
+    match execute_history(repo, ReadHistoryRequest { table_id, item_id }).await {
+        Ok(res) => to_json(res.items, HttpStatus::OK).await,
+        Err(ErrorHistory::UnknowTableId) => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown table id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(ErrorHistory::UnknownItemId) => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown item id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(ErrorHistory::Unknown) => {
+            to_json(
                 FailMsg {
                     msg: "Server error".to_string(),
                 },
@@ -79,7 +170,7 @@ pub async fn read_items_handler(request: Request) -> Response {
     // Check table exists
     let url_pattern = "/tables/:tid/items";
     let table_id =
-        match parse_numeric_id::<TableId<IdType>>(&request.uri().path(), url_pattern, "tid") {
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
             Ok(tid) => tid,
             Err(err) => {
                 return to_json(
@@ -92,26 +183,53 @@ pub async fn read_items_handler(request: Request) -> Response {
             }
         };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    // Read filter and sort parameters
+    let read_all_request = match parse_read_items_query(table_id, request.uri().query()) {
+        Ok(req) => req,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
 
-    let mut op_status: OpItemsResponse = HashMap::new();
+    let is_paginated = read_all_request.page.is_some();
+    let include_deleted = read_all_request.include_deleted;
+    let mut op_status: OpItemsResponse = OpItemsResponse::new();
     //retrieve items
-    match execute_fetch_all(
-        repo,
-        ReadAllRequest {
-            table_id: table_id,
-            include_deleted: false,
-            filter: String::from(""),
-            sort_by: String::from(""),
+    match execute_fetch_all(repo, read_all_request).await {
+        // copies of an item share its id, so they are listed instead of keyed by id
+        Ok(res) if include_deleted => match is_paginated {
+            true => {
+                let page = ItemsPageResponse {
+                    items: res.items,
+                    next_cursor: res.next_cursor.map(|cursor| cursor.to_string()),
+                };
+                to_json(page, HttpStatus::OK).await
+            }
+            false => to_json(res.items, HttpStatus::OK).await,
         },
-    ) {
         Ok(res) => {
             for r in &res.items {
                 op_status.insert(r.id, serde_json::to_value(r).unwrap());
             }
-            return to_json(op_status, HttpStatus::OK).await;
+            match is_paginated {
+                true => {
+                    let page = ItemsPageResponse {
+                        items: op_status,
+                        next_cursor: res.next_cursor.map(|cursor| cursor.to_string()),
+                    };
+                    to_json(page, HttpStatus::OK).await
+                }
+                false => to_json(op_status, HttpStatus::OK).await,
+            }
         }
         Err(ErrorAll::UnknowTableId) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Unknown table id".to_string(),
                 },
@@ -120,7 +238,7 @@ pub async fn read_items_handler(request: Request) -> Response {
             .await
         }
         Err(ErrorAll::Unknown) => {
-            return to_json(
+            to_json(
                 FailMsg {
                     msg: "Server error".to_string(),
                 },
@@ -134,11 +252,14 @@ pub async fn read_items_handler(request: Request) -> Response {
 #[cfg(test)]
 mod test {
     use crate::api::HttpStatus;
-    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, TableId};
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
     use crate::repository::inmemory::InMemoryRepository;
     use crate::repository::Repository;
     use futures::future::join_all;
     use hyper::http;
+    use indexmap::IndexMap;
     use pretty_assertions::assert_eq;
     use serde_json::Value;
     use std::sync::Arc;
@@ -156,7 +277,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -179,7 +300,7 @@ mod test {
         assert_eq!(json["notes"], String::from(ItemNotes::some_notes()));
         assert_eq!(json["quantity"], 1);
         assert_eq!(json["deleted"], false);
-        assert!(json["time_to_prepare"] != "");
+        assert!(json["ready_at"].is_string());
         assert_eq!(json["version"], 1);
     }
 
@@ -194,7 +315,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -225,19 +346,19 @@ mod test {
     #[tokio::test]
     async fn it_should_return_ok_read_multi_items() {
         let repo = InMemoryRepository::new();
-        let table_ids = vec![1, 2];
-        let item_ids = vec![1, 2];
+        let table_ids = [1, 2];
+        let item_ids = [1, 2];
         table_ids.iter().for_each(|table_id: &u32| {
             item_ids.iter().for_each(|id: &u32| {
                 repo.insert(
-                    TableId::from_int(table_id.clone()),
-                    ItemId::from_int(id.clone()),
+                    TableId::from_int(*table_id),
+                    ItemId::from_int(*id),
                     ItemName::pizza(),
                     ItemNotes::some_notes(),
                     ItemQuantity::one(),
                     false,
                     ItemVersion::ver_one(),
-                    "2023/12/12".to_string(),
+                    PrepTime::from_date("2023-12-12"),
                 )
                 .ok();
             });
@@ -248,7 +369,7 @@ mod test {
             let body: &str = "";
             let mut request = hyper::Request::builder()
                 .method(http::Method::GET)
-                .uri(format!("/tables/{}/items", table_id.to_string()))
+                .uri(format!("/tables/{}/items", *table_id))
                 .body(hyper::Body::from(body))
                 .unwrap();
             request.extensions_mut().insert(context.clone());
@@ -265,10 +386,200 @@ mod test {
                 assert_eq!(obj["notes"], String::from(ItemNotes::some_notes()));
                 assert_eq!(obj["quantity"], 1);
                 assert_eq!(obj["deleted"], false);
-                assert!(obj["time_to_prepare"] != "");
+                assert!(obj["ready_at"].is_string());
                 assert_eq!(obj["version"], 1);
             });
         }))
         .await;
     }
+
+    #[tokio::test]
+    async fn it_should_return_filtered_and_sorted_items() {
+        let repo = InMemoryRepository::new();
+        for (id, name, quantity) in [
+            (1, ItemName::pizza(), 1),
+            (2, ItemName::pasta(), 4),
+            (3, ItemName::pizza(), 3),
+            (4, ItemName::pizza(), 2),
+        ] {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                name,
+                ItemNotes::some_notes(),
+                ItemQuantity::from_int(quantity),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        let mut request = hyper::Request::builder()
+            .method(http::Method::GET)
+            .uri("/tables/1/items?name_contains=pizza&min_quantity=2&sort=quantity")
+            .body(hyper::Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(context.clone());
+        let response = handle(request).await;
+
+        assert_eq!(response.status(), HttpStatus::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let result = String::from_utf8(body.to_vec()).unwrap();
+        let json: IndexMap<String, Value> = serde_json::from_str(result.as_str()).unwrap();
+        let ids: Vec<&String> = json.keys().collect();
+        assert_eq!(ids, vec!["4", "3"]);
+
+        let mut request = hyper::Request::builder()
+            .method(http::Method::GET)
+            .uri("/tables/1/items?sort=notes")
+            .body(hyper::Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(context);
+        let response = handle(request).await;
+
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_items_page_by_page() {
+        let repo = InMemoryRepository::new();
+        for id in 1..=3 {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        let mut uri = "/tables/1/items?limit=2".to_string();
+        let mut pages: Vec<Vec<String>> = vec![];
+        loop {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::GET)
+                .uri(uri.as_str())
+                .body(hyper::Body::empty())
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), HttpStatus::OK);
+
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            let items: IndexMap<String, Value> =
+                serde_json::from_value(json["items"].clone()).unwrap();
+            pages.push(items.keys().cloned().collect());
+            match json["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/tables/1/items?limit=2&cursor={}", cursor),
+                None => break,
+            }
+        }
+        assert_eq!(pages, vec![vec!["1", "2"], vec!["3"]]);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_deleted_copies_page_by_page() {
+        let repo = InMemoryRepository::new();
+        for id in [1, 1, 2] {
+            repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                .ok();
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        let mut uri = "/tables/1/items?include_deleted=true&limit=2".to_string();
+        let mut pages: Vec<Vec<(u64, u64)>> = vec![];
+        loop {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::GET)
+                .uri(uri.as_str())
+                .body(hyper::Body::empty())
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), HttpStatus::OK);
+
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            let items = json["items"].as_array().unwrap();
+            pages.push(
+                items
+                    .iter()
+                    .map(|item| {
+                        (
+                            item["id"].as_u64().unwrap(),
+                            item["version"].as_u64().unwrap(),
+                        )
+                    })
+                    .collect(),
+            );
+            match json["next_cursor"].as_str() {
+                Some(cursor) => {
+                    uri = format!(
+                        "/tables/1/items?include_deleted=true&limit=2&cursor={}",
+                        cursor
+                    )
+                }
+                None => break,
+            }
+        }
+        // both copies of item 1 are deleted, the second one started after the first at 3
+        assert_eq!(pages, vec![vec![(1, 2), (1, 4)], vec![(2, 1)]]);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_not_modified_for_a_matching_etag() {
+        let repo: InMemoryRepository = InMemoryRepository::new();
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::from_int(2),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        for (if_none_match, status) in [
+            (r#""2""#, HttpStatus::NOT_MODIFIED),
+            (r#""1""#, HttpStatus::OK),
+            ("*", HttpStatus::NOT_MODIFIED),
+        ] {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::GET)
+                .uri("/tables/1/items/1")
+                .header(hyper::header::IF_NONE_MATCH, if_none_match)
+                .body(hyper::Body::empty())
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()[hyper::header::ETAG], r#""2""#);
+        }
+    }
 }
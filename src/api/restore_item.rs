@@ -0,0 +1,173 @@
+use crate::api::helpers::{parse_numeric_id, to_json, with_etag, FailMsg};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::events::ItemEvents;
+use crate::domain::restore_item::{execute, Error, RestoreRequest};
+use crate::domain::types::{IdType, ItemId, TableId};
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains the function to handle POST /tables/:tid/items/:id/restore
+
+pub async fn restore_item_handler(request: Request) -> Response {
+    let url_pattern = "/tables/:tid/items/:id/restore";
+    let table_id =
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
+            Ok(tid) => tid,
+            Err(err) => {
+                return to_json(
+                    FailMsg {
+                        msg: err.to_string(),
+                    },
+                    HttpStatus::BAD_REQUEST,
+                )
+                .await
+            }
+        };
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
+    {
+        Ok(it_id) => it_id,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+
+    match execute(repo, events, RestoreRequest { table_id, item_id }).await {
+        Ok(res) => {
+            let version = res.item.version.clone();
+            with_etag(to_json(res.item, HttpStatus::OK).await, &version)
+        }
+        Err(Error::UnknowTableId) => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown table id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(Error::UnknownItemId) => {
+            to_json(
+                FailMsg {
+                    msg: "No deleted item with this id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(Error::AlreadyActive) => {
+            to_json(
+                FailMsg {
+                    msg: "An item with this id is not deleted".to_string(),
+                },
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(Error::Paid) => {
+            to_json(
+                FailMsg {
+                    msg: "The item was paid at checkout".to_string(),
+                },
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(Error::Unknown) => {
+            to_json(
+                FailMsg {
+                    msg: "Server error".to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn send(
+        context: Arc<dyn Repository>,
+        method: http::Method,
+        uri: &str,
+    ) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(context);
+        let response = handle(request).await;
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_list_and_restore_deleted_items() {
+        let repo: InMemoryRepository = InMemoryRepository::new();
+        for id in [1, 2] {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        repo.delete(TableId::from_int(1), ItemId::from_int(2), None)
+            .ok();
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        let (status, json) = send(context.clone(), http::Method::GET, "/tables/1/items").await;
+        assert_eq!(status, HttpStatus::OK);
+        assert!(json.get("2").is_none());
+        let uri = "/tables/1/items?include_deleted=true";
+        let (_, json) = send(context.clone(), http::Method::GET, uri).await;
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["deleted"], true);
+
+        let uri = "/tables/1/items/2/restore";
+        let (status, json) = send(context.clone(), http::Method::POST, uri).await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json["deleted"], false);
+        assert_eq!(json["version"], 3);
+        let (status, _) = send(context.clone(), http::Method::POST, uri).await;
+        assert_eq!(status, HttpStatus::CONFLICT);
+
+        let uri = "/tables/1/items/2/history";
+        let (status, json) = send(context.clone(), http::Method::GET, uri).await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        let uri = "/tables/1/items/3/history";
+        let (status, _) = send(context, http::Method::GET, uri).await;
+        assert_eq!(status, HttpStatus::NOT_FOUND);
+    }
+}
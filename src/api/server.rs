@@ -1,12 +1,52 @@
 use anyhow::Result;
 use backtrace::Backtrace;
-use futures::{future::FutureExt, Future};
+use futures::{future::FutureExt, Future, StreamExt};
 use hyper::http;
-use std::{cell::RefCell, convert::Infallible, panic::AssertUnwindSafe, sync::Arc};
+use std::{cell::RefCell, convert::Infallible, panic::AssertUnwindSafe, sync::Arc, time::Duration};
 
+use crate::api::websocket;
 use crate::api::{Request, Response};
 use crate::repository::Repository;
 
+// Bounds on what a client may ask of the server
+#[derive(Debug, Clone, Copy)]
+pub struct ServerLimits {
+    // Larger bodies are answered with a 413
+    pub max_body_bytes: u64,
+    // Connections that didn't send the request headers in time are closed
+    pub header_read_timeout: Duration,
+    // How long the requests in flight at shutdown may still take
+    pub drain_timeout: Duration,
+}
+
+type PanicHook = dyn Fn(&std::panic::PanicHookInfo<'_>) + Send + Sync;
+
+fn declared_length(request: &Request) -> Option<u64> {
+    request
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok())
+}
+
+// Cuts off a body sent without a length once it goes over max_body_bytes, a declared
+// length is checked before the request is handled
+fn limit_body(request: Request, max_body_bytes: u64) -> Request {
+    if declared_length(&request).is_some() {
+        return request;
+    }
+    let (parts, body) = request.into_parts();
+    let mut read = 0;
+    let body = body.map(move |chunk| {
+        let chunk = chunk?;
+        read += chunk.len() as u64;
+        match read > max_body_bytes {
+            true => Err(format!("Request body is larger than {} bytes", max_body_bytes).into()),
+            false => Ok::<_, Box<dyn std::error::Error + Send + Sync>>(chunk),
+        }
+    });
+    Request::from_parts(parts, hyper::Body::wrap_stream(body))
+}
+
 /// Asynchronously serves HTTP requests at the specified address using the provided handler and context.
 ///
 /// # Arguments
@@ -14,25 +54,42 @@ use crate::repository::Repository;
 /// * `addr` - The socket address (IP address and port) at which the server will listen for incoming connections.
 /// * `context` - An Arc (atomic reference counter) containing the context or state shared across all requests.
 /// * `handler` - A function that takes an HTTP request and returns a future representing the HTTP response.
+/// * `shutdown` - A future that completes when the server should stop accepting connections.
+/// * `limits` - The largest request body, how long to wait for request headers and how long to drain at shutdown.
 ///
 /// # Returns
 ///
 /// A `hyper::Result` indicating the success or failure of serving the HTTP requests.
 ///
+/// # Shutdown
+///
+/// Once `shutdown` completes, no new connection is accepted, the event streams end and the requests in flight are
+/// answered, for up to `limits.drain_timeout`. The websocket connections are closed once their current command is
+/// answered, given `limits.drain_timeout` again. The context's `Repository::shutdown` is called afterwards, even when
+/// either timed out.
+///
 /// # Panics
 ///
 /// Panics inside request handlers will be caught, and a 500 Internal Server Error response with the panic message
 /// and backtrace will be sent to the client.
+///
+/// # WebSocket
+///
+/// A request to `/ws` is upgraded to a websocket. Every command received on it is turned into a request that goes
+/// through `handler` like any other, see api/websocket.rs.
 /// Credits and inspired by https://dev.to/deciduously/oops-i-did-it-againi-made-a-rust-web-api-and-it-was-not-that-difficult-3kk8
-pub async fn serve<C, H, F>(
+pub async fn serve<C, H, F, S>(
     addr: std::net::SocketAddr,
     context: Arc<C>,
     handler: H,
+    shutdown: S,
+    limits: ServerLimits,
 ) -> hyper::Result<()>
 where
     C: Repository + Send + Sync + ?Sized + 'static,
     H: 'static + Fn(Request) -> F + Send + Sync,
-    F: Future<Output = Response> + Send,
+    F: Future<Output = Response> + Send + 'static,
+    S: Future<Output = ()> + Send + 'static,
 {
     // Create a task local that will store the panic message and backtrace if a panic occurs.
     tokio::task_local! {
@@ -41,7 +98,8 @@ where
     async fn service<C, H, F>(
         handler: Arc<H>,
         context: Arc<C>,
-        mut request: http::Request<hyper::Body>,
+        max_body_bytes: u64,
+        request: http::Request<hyper::Body>,
     ) -> Result<http::Response<hyper::Body>, Infallible>
     where
         C: 'static + Send + Sync + 'static + ?Sized,
@@ -51,6 +109,16 @@ where
         let method = request.method().clone();
         let path = request.uri().path_and_query().unwrap().path().to_owned();
         tracing::info!(path = %path, method = %method, "request");
+        if declared_length(&request).is_some_and(|length| length > max_body_bytes) {
+            return Ok(http::Response::builder()
+                .status(http::StatusCode::PAYLOAD_TOO_LARGE)
+                .body(hyper::Body::from(format!(
+                    "Request body is larger than {} bytes",
+                    max_body_bytes
+                )))
+                .unwrap());
+        }
+        let mut request = limit_body(request, max_body_bytes);
         request.extensions_mut().insert(context);
         let result = AssertUnwindSafe(handler(request)).catch_unwind().await;
         let response = result.unwrap_or_else(|_| {
@@ -72,32 +140,181 @@ where
         });
         Ok(response)
     }
+    // Serves one request with its own panic message slot, for the commands of a websocket
+    async fn respond<C, H, F>(
+        handler: Arc<H>,
+        context: Arc<C>,
+        max_body_bytes: u64,
+        request: Request,
+    ) -> Response
+    where
+        C: 'static + Send + Sync + 'static + ?Sized,
+        H: Fn(http::Request<hyper::Body>) -> F + Send + Sync + 'static,
+        F: Future<Output = http::Response<hyper::Body>> + Send,
+    {
+        match PANIC_MESSAGE_AND_BACKTRACE
+            .scope(
+                RefCell::new(None),
+                service(handler, context, max_body_bytes, request),
+            )
+            .await
+        {
+            Ok(response) => response,
+            Err(never) => match never {},
+        }
+    }
     // Install a panic hook that will record the panic message and backtrace if a panic occurs.
-    let hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(|panic_info| {
+    // Panics outside of a request, e.g. in another task, still go to the previous hook.
+    let hook: Arc<PanicHook> = Arc::from(std::panic::take_hook());
+    let previous_hook = hook.clone();
+    std::panic::set_hook(Box::new(move |panic_info| {
         let value = (panic_info.to_string(), Backtrace::new());
-        PANIC_MESSAGE_AND_BACKTRACE.with(|panic_message_and_backtrace| {
+        let recorded = PANIC_MESSAGE_AND_BACKTRACE.try_with(|panic_message_and_backtrace| {
             panic_message_and_backtrace.borrow_mut().replace(value);
-        })
+        });
+        if recorded.is_err() {
+            previous_hook(panic_info);
+        }
     }));
     // Wrap the request handler and context with Arc to allow sharing a reference to it with each task.
     let handler = Arc::new(handler);
+    let max_body_bytes = limits.max_body_bytes;
+    let sessions = Arc::new(websocket::Sessions::new());
+    let service_sessions = sessions.clone();
     let service = hyper::service::make_service_fn(|_| {
         let handler = handler.clone();
         let context = context.clone();
+        let sessions = service_sessions.clone();
         async move {
-            Ok::<_, Infallible>(hyper::service::service_fn(move |request| {
+            Ok::<_, Infallible>(hyper::service::service_fn(move |mut request: Request| {
                 let handler = handler.clone();
                 let context = context.clone();
+                let sessions = sessions.clone();
+                // The event streams end with the websocket sessions
+                request.extensions_mut().insert(sessions.closing());
                 PANIC_MESSAGE_AND_BACKTRACE.scope(RefCell::new(None), async move {
-                    service(handler, context, request).await
+                    if websocket::is_websocket(&request) {
+                        return Ok(websocket::upgrade(request, &sessions, move |request| {
+                            respond(handler.clone(), context.clone(), max_body_bytes, request)
+                        }));
+                    }
+                    service(handler, context, max_body_bytes, request).await
                 })
             }))
         }
     });
-    let server = hyper::server::Server::try_bind(&addr)?;
+    let server = hyper::server::Server::try_bind(&addr)?
+        .http1_header_read_timeout(limits.header_read_timeout);
     tracing::info!("🚀 serving at {}", addr);
-    server.serve(service).await?;
-    std::panic::set_hook(hook);
-    Ok(())
+    // The drain timeout starts when the shutdown signal arrives
+    let (draining, drain_started) = tokio::sync::oneshot::channel();
+    let closing_sessions = sessions.clone();
+    let server = server.serve(service).with_graceful_shutdown(async move {
+        shutdown.await;
+        tracing::info!("stopped accepting connections, draining");
+        // Otherwise the open event streams would keep the drain waiting for its timeout
+        closing_sessions.start_closing();
+        draining.send(()).ok();
+    });
+    let drain_timeout = async move {
+        match drain_started.await {
+            Ok(()) => tokio::time::sleep(limits.drain_timeout).await,
+            // The server stopped on its own
+            Err(_) => futures::future::pending().await,
+        }
+    };
+    let result = tokio::select! {
+        result = server => result,
+        _ = drain_timeout => {
+            tracing::warn!("drain timed out, dropping the requests still in flight");
+            Ok(())
+        }
+    };
+    // Hyper doesn't wait for the upgraded connections, they could still be using the repository
+    match sessions.close(limits.drain_timeout).await {
+        true => tracing::info!("websocket sessions closed"),
+        false => tracing::warn!("websocket sessions did not close in time, aborted them"),
+    }
+
+    match tokio::task::spawn_blocking(move || context.shutdown()).await {
+        Ok(Ok(())) => tracing::info!("repository shut down"),
+        _ => tracing::error!("repository shutdown failed"),
+    }
+    std::panic::set_hook(Box::new(move |panic_info| hook(panic_info)));
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{limit_body, serve, ServerLimits};
+    use crate::domain::events::ItemEvents;
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use hyper::body::HttpBody;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn chunked(chunks: Vec<&'static str>) -> super::Request {
+        let chunks = chunks.into_iter().map(Ok::<_, std::io::Error>);
+        hyper::Request::builder()
+            .body(hyper::Body::wrap_stream(futures::stream::iter(chunks)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_cut_off_a_body_without_length_once_it_is_too_large() {
+        let request = limit_body(chunked(vec!["12345", "678"]), 8);
+        assert!(hyper::body::to_bytes(request.into_body()).await.is_ok());
+
+        let request = limit_body(chunked(vec!["12345", "6789"]), 8);
+        assert!(hyper::body::to_bytes(request.into_body()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_end_the_event_streams_at_shutdown() {
+        // A free port, serve() binds it itself
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let limits = ServerLimits {
+            max_body_bytes: 1024,
+            header_read_timeout: Duration::from_secs(5),
+            drain_timeout: Duration::from_secs(30),
+        };
+        // Held by the test, the streams would end with the last ItemEvents otherwise
+        let events = ItemEvents::new();
+        let handler_events = events.clone();
+        let server = tokio::spawn(serve(
+            addr,
+            repo,
+            move |mut request| {
+                request.extensions_mut().insert(handler_events.clone());
+                crate::handle(request)
+            },
+            async move {
+                stopped.await.ok();
+            },
+            limits,
+        ));
+
+        let client = hyper::Client::new();
+        let uri: hyper::Uri = format!("http://{}/events", addr).parse().unwrap();
+        let response = loop {
+            match client.get(uri.clone()).await {
+                Ok(response) => break response,
+                // Not listening yet
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+
+        let started = Instant::now();
+        stop.send(()).unwrap();
+        assert!(response.into_body().data().await.is_none());
+        assert!(server.await.unwrap().is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 }
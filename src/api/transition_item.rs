@@ -0,0 +1,172 @@
+use crate::api::helpers::{
+    extract_string_payload, parse_numeric_id, parse_transition, to_json, with_etag, ConflictMsg,
+    FailMsg,
+};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::events::ItemEvents;
+use crate::domain::transition_item::{execute, Error, TransitionRequest};
+use crate::domain::types::{IdType, ItemId, TableId};
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains the function to handle POST /tables/:tid/items/:id/transition
+
+pub async fn transition_item_handler(request: Request) -> Response {
+    let url_pattern = "/tables/:tid/items/:id/transition";
+    let bad_request = |msg: String| to_json(FailMsg { msg }, HttpStatus::BAD_REQUEST);
+    let table_id =
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
+            Ok(tid) => tid,
+            Err(err) => return bad_request(err).await,
+        };
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
+    {
+        Ok(it_id) => it_id,
+        Err(err) => return bad_request(err).await,
+    };
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let payload: String = extract_string_payload(request)
+        .await
+        .unwrap_or_else(|_| String::from(""));
+    let (state, version) = match parse_transition(&payload) {
+        Ok(transition) => transition,
+        Err(err) => return bad_request(err).await,
+    };
+
+    let req = TransitionRequest {
+        table_id,
+        item_id,
+        state,
+        version,
+    };
+    match execute(repo, events, req).await {
+        Ok(res) => {
+            let version = res.item.version.clone();
+            with_etag(to_json(res.item, HttpStatus::OK).await, &version)
+        }
+        Err(Error::UnknowTableId) => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown table id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(Error::UnknownItemId) => {
+            to_json(
+                FailMsg {
+                    msg: "Unknown item id".to_string(),
+                },
+                HttpStatus::NOT_FOUND,
+            )
+            .await
+        }
+        Err(Error::VersionConflict(item)) => {
+            to_json(
+                ConflictMsg::new(
+                    "Version mismatch: server has newer version".to_string(),
+                    item,
+                ),
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(Error::InvalidTransition(item)) => {
+            to_json(
+                ConflictMsg::new(format!("Cannot go from {} to {}", item.state, state), item),
+                HttpStatus::CONFLICT,
+            )
+            .await
+        }
+        Err(Error::Unknown) => {
+            to_json(
+                FailMsg {
+                    msg: "Server error".to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    async fn send(context: Arc<dyn Repository>, uri: &str, body: &str) -> (HttpStatus, Value) {
+        let mut request = hyper::Request::builder()
+            .method(http::Method::POST)
+            .uri(uri)
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(context);
+        let response = handle(request).await;
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_transition_items() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(1),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+            let uri = "/tables/1/items/1/transition";
+
+            let (status, json) = send(repo.clone(), uri, r#"{"state": "cooking"}"#).await;
+            assert_eq!(status, HttpStatus::OK);
+            assert_eq!(json["state"], "cooking");
+            assert_eq!(json["version"], 2);
+
+            let body = r#"{"state": "ready", "version": 1}"#;
+            let (status, json) = send(repo.clone(), uri, body).await;
+            assert_eq!(status, HttpStatus::CONFLICT);
+            assert_eq!(json["version"], 2);
+
+            let (status, json) = send(repo.clone(), uri, r#"{"state": "served"}"#).await;
+            assert_eq!(status, HttpStatus::CONFLICT);
+            assert_eq!(json["msg"], "Cannot go from cooking to served");
+            assert_eq!(json["item"]["state"], "cooking");
+
+            let (status, _) = send(repo.clone(), uri, r#"{"state": "eaten"}"#).await;
+            assert_eq!(status, HttpStatus::BAD_REQUEST);
+
+            let (status, json) = send(repo.clone(), uri, r#"{"state": "cancelled"}"#).await;
+            assert_eq!(status, HttpStatus::OK);
+            assert_eq!(json["deleted"], true);
+
+            let (status, _) = send(repo, uri, r#"{"state": "ready"}"#).await;
+            assert_eq!(status, HttpStatus::NOT_FOUND);
+        }
+    }
+}
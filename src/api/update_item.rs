@@ -0,0 +1,311 @@
+use crate::api::helpers::{
+    extract_string_payload, parse_item_patch, parse_numeric_id, parse_update_item,
+    parse_version_match, to_json, with_etag, ConflictMsg, FailMsg,
+};
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use crate::domain::events::ItemEvents;
+use crate::domain::patch_item::{self, PatchRequest};
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{IdType, Item, ItemId, TableId, VersionMatch};
+use crate::domain::update_item::{self, CreateOrUpdateRequest};
+use crate::menu::{names_for_orders, MenuNames, MenuPolicy, MenuRepository};
+use crate::repository::Repository;
+use std::sync::Arc;
+
+// This file contains functions to handle PUT and PATCH requests of a single item
+
+// What both handlers read from the request before the body
+struct ItemRequest {
+    table_id: TableId<IdType>,
+    item_id: ItemId<IdType>,
+    if_match: Option<VersionMatch>,
+    menu_names: Option<MenuNames>,
+    payload: String,
+}
+
+async fn read_item_request(request: Request) -> Result<ItemRequest, Response> {
+    let url_pattern = "/tables/:tid/items/:id";
+    let bad_request = |msg: String| to_json(FailMsg { msg }, HttpStatus::BAD_REQUEST);
+    let table_id =
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
+            Ok(tid) => tid,
+            Err(err) => return Err(bad_request(err).await),
+        };
+    let item_id = match parse_numeric_id::<ItemId<IdType>>(request.uri().path(), url_pattern, "id")
+    {
+        Ok(it_id) => it_id,
+        Err(err) => return Err(bad_request(err).await),
+    };
+    let if_match = match parse_version_match(&request, hyper::header::IF_MATCH) {
+        Ok(if_match) => if_match,
+        Err(err) => return Err(bad_request(err).await),
+    };
+    let menu_policy: MenuPolicy = request.extensions().get().copied().unwrap_or_default();
+    let menu_repo: Option<Arc<dyn MenuRepository>> = request.extensions().get().cloned();
+    let menu_names = match names_for_orders(menu_policy, menu_repo).await {
+        Ok(names) => names,
+        Err(_) => {
+            return Err(to_json(
+                FailMsg {
+                    msg: "Unable to read the menu".to_string(),
+                },
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await)
+        }
+    };
+    let payload: String = extract_string_payload(request)
+        .await
+        .unwrap_or_else(|_| String::from(""));
+
+    Ok(ItemRequest {
+        table_id,
+        item_id,
+        if_match,
+        menu_names,
+        payload,
+    })
+}
+
+pub async fn update_item_handler(request: Request) -> Response {
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let estimator: Arc<dyn PrepTimeEstimator> = Arc::clone(request.extensions().get().unwrap());
+    let req = match read_item_request(request).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+    let item = match parse_update_item(&req.payload, req.item_id, req.menu_names.as_ref()) {
+        Ok(item) => item,
+        Err(err) => return to_json(FailMsg { msg: err }, HttpStatus::BAD_REQUEST).await,
+    };
+
+    let result = update_item::execute(
+        repo,
+        estimator,
+        events,
+        CreateOrUpdateRequest {
+            table_id: req.table_id,
+            item,
+            if_match: req.if_match,
+        },
+    )
+    .await;
+    match result {
+        Ok(res) => item_response(res.item).await,
+        Err(update_item::Error::UnknowTableId) => not_found("Unknown table id").await,
+        Err(update_item::Error::UnknownItemId) => not_found("Unknown item id").await,
+        Err(update_item::Error::VersionConflict(item)) => conflict(item).await,
+        Err(update_item::Error::PreconditionFailed) => precondition_failed().await,
+        Err(update_item::Error::Unknown) => server_error().await,
+    }
+}
+
+pub async fn patch_item_handler(request: Request) -> Response {
+    let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let estimator: Arc<dyn PrepTimeEstimator> = Arc::clone(request.extensions().get().unwrap());
+    let req = match read_item_request(request).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+    let (patch, version) = match parse_item_patch(&req.payload, req.menu_names.as_ref()) {
+        Ok(patch) => patch,
+        Err(err) => return to_json(FailMsg { msg: err }, HttpStatus::BAD_REQUEST).await,
+    };
+
+    let result = patch_item::execute(
+        repo,
+        estimator,
+        events,
+        PatchRequest {
+            table_id: req.table_id,
+            item_id: req.item_id,
+            patch,
+            version,
+            if_match: req.if_match,
+        },
+    )
+    .await;
+    match result {
+        Ok(res) => item_response(res.item).await,
+        Err(patch_item::Error::UnknowTableId) => not_found("Unknown table id").await,
+        Err(patch_item::Error::UnknownItemId) => not_found("Unknown item id").await,
+        Err(patch_item::Error::VersionConflict(item)) => conflict(item).await,
+        Err(patch_item::Error::PreconditionFailed) => precondition_failed().await,
+        Err(patch_item::Error::Unknown) => server_error().await,
+    }
+}
+
+async fn item_response(item: Item) -> Response {
+    let version = item.version.clone();
+    with_etag(to_json(item, HttpStatus::OK).await, &version)
+}
+
+async fn not_found(msg: &str) -> Response {
+    to_json(
+        FailMsg {
+            msg: msg.to_string(),
+        },
+        HttpStatus::NOT_FOUND,
+    )
+    .await
+}
+
+async fn conflict(item: Item) -> Response {
+    to_json(
+        ConflictMsg::new(
+            "Version mismatch: server has newer version".to_string(),
+            item,
+        ),
+        HttpStatus::CONFLICT,
+    )
+    .await
+}
+
+async fn precondition_failed() -> Response {
+    to_json(
+        FailMsg {
+            msg: "Precondition failed: the item is in another version".to_string(),
+        },
+        HttpStatus::PRECONDITION_FAILED,
+    )
+    .await
+}
+
+async fn server_error() -> Response {
+    to_json(
+        FailMsg {
+            msg: "Server error".to_string(),
+        },
+        HttpStatus::BAD_REQUEST,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+    use crate::repository::Repository;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    use crate::handle;
+
+    fn repos() -> Vec<Arc<dyn Repository>> {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos.iter() {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(1),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        repos
+    }
+
+    async fn send(
+        context: Arc<dyn Repository>,
+        method: http::Method,
+        if_match: Option<&str>,
+        body: &str,
+    ) -> (HttpStatus, Value) {
+        let mut builder = hyper::Request::builder()
+            .method(method)
+            .uri("/tables/1/items/1");
+        if let Some(if_match) = if_match {
+            builder = builder.header(hyper::header::IF_MATCH, if_match);
+        }
+        let mut request = builder.body(hyper::Body::from(body.to_string())).unwrap();
+        request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+        let response = handle(request).await;
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_should_patch_only_the_given_fields() {
+        for context in repos() {
+            let body = r#"{"notes": "No cheese"}"#;
+            let (status, json) = send(context.clone(), http::Method::PATCH, None, body).await;
+            assert_eq!(status, HttpStatus::OK);
+            assert_eq!(json["name"], "Some pizza");
+            assert_eq!(json["notes"], "No cheese");
+            assert_eq!(json["quantity"], 1);
+            assert_eq!(json["version"], 2);
+
+            let body = r#"{"quantity": 3, "version": 1}"#;
+            let (status, json) = send(context.clone(), http::Method::PATCH, None, body).await;
+            assert_eq!(status, HttpStatus::CONFLICT);
+            assert_eq!(json["version"], 2);
+            assert_eq!(json["item"]["notes"], "No cheese");
+
+            let body = r#"{"quantity": 3}"#;
+            let (status, _) =
+                send(context.clone(), http::Method::PATCH, Some(r#""1""#), body).await;
+            assert_eq!(status, HttpStatus::PRECONDITION_FAILED);
+            // If-Match compares strongly
+            let (status, _) =
+                send(context.clone(), http::Method::PATCH, Some(r#"W/"2""#), body).await;
+            assert_eq!(status, HttpStatus::BAD_REQUEST);
+            let (status, json) =
+                send(context.clone(), http::Method::PATCH, Some(r#""2""#), body).await;
+            assert_eq!(status, HttpStatus::OK);
+            assert_eq!(json["quantity"], 3);
+
+            let (status, _) = send(context, http::Method::PATCH, None, r#"{"name": null}"#).await;
+            assert_eq!(status, HttpStatus::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_replace_one_item() {
+        for context in repos() {
+            let body = r#"{"name": "Pasta", "notes": "", "quantity": 2}"#;
+            let (status, json) =
+                send(context.clone(), http::Method::PUT, Some(r#""1""#), body).await;
+            assert_eq!(status, HttpStatus::OK);
+            assert_eq!(json["name"], "Pasta");
+            assert_eq!(json["version"], 2);
+
+            let (status, json) = send(context.clone(), http::Method::PUT, None, body).await;
+            assert_eq!(status, HttpStatus::CONFLICT);
+            assert_eq!(json["version"], 2);
+
+            let body = r#"{"name": "Pasta", "notes": "", "quantity": 2}"#;
+            let mut request = hyper::Request::builder()
+                .method(http::Method::PUT)
+                .uri("/tables/1/items/2")
+                .body(hyper::Body::from(body))
+                .unwrap();
+            request.extensions_mut().insert(context);
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            assert_eq!(handle(request).await.status(), HttpStatus::NOT_FOUND);
+        }
+    }
+}
@@ -1,11 +1,17 @@
+use crate::api::atomic::atomic_batch_response;
 use crate::api::helpers::{
-    extract_string_payload, json_body, parse_create_or_update_items, parse_numeric_id, to_json,
-    FailMsg, OpStatusResponse,
+    extract_string_payload, json_body, parse_batch_query, parse_create_or_update_items,
+    parse_numeric_id, parse_version_match, to_json, with_etag, ConflictMsg, FailMsg,
+    OpStatusResponse,
 };
 use crate::api::HttpStatus;
 use crate::api::{Request, Response};
+use crate::domain::apply_batch::BatchRequest;
+use crate::domain::events::ItemEvents;
+use crate::domain::prep_time::PrepTimeEstimator;
 use crate::domain::types::{IdType, Item, TableId};
 use crate::domain::update_item::{execute, CreateOrUpdateRequest, Error};
+use crate::menu::{names_for_orders, MenuPolicy, MenuRepository};
 use crate::repository::Repository;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -17,7 +23,7 @@ use super::helpers::StatusWithBody;
 pub async fn update_items_handler(request: Request) -> Response {
     let url_pattern = "/tables/:tid/items";
     let table_id =
-        match parse_numeric_id::<TableId<IdType>>(&request.uri().path(), url_pattern, "tid") {
+        match parse_numeric_id::<TableId<IdType>>(request.uri().path(), url_pattern, "tid") {
             Ok(tid) => tid,
             Err(err) => {
                 return to_json(
@@ -29,13 +35,54 @@ pub async fn update_items_handler(request: Request) -> Response {
                 .await
             }
         };
+    let batch_query = match parse_batch_query(request.uri().query()) {
+        Ok(query) => query,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
+    let if_match = match parse_version_match(&request, hyper::header::IF_MATCH) {
+        Ok(if_match) => if_match,
+        Err(err) => {
+            return to_json(
+                FailMsg {
+                    msg: err.to_string(),
+                },
+                HttpStatus::BAD_REQUEST,
+            )
+            .await
+        }
+    };
     let repo: Arc<dyn Repository> = Arc::clone(request.extensions().get().unwrap());
+    let events: ItemEvents = request.extensions().get().cloned().unwrap_or_default();
+    let estimator: Arc<dyn PrepTimeEstimator> = Arc::clone(request.extensions().get().unwrap());
+    let menu_policy: MenuPolicy = request.extensions().get().copied().unwrap_or_default();
+    let menu_repo: Option<Arc<dyn MenuRepository>> = request.extensions().get().cloned();
+    let menu_names = match names_for_orders(menu_policy, menu_repo).await {
+        Ok(names) => names,
+        Err(_) => {
+            return to_json(
+                FailMsg {
+                    msg: "Unable to read the menu".to_string(),
+                },
+                HttpStatus::INTERNAL_SERVER_ERROR,
+            )
+            .await
+        }
+    };
 
     // parse item(s)
     let payload: String = extract_string_payload(request)
         .await
         .unwrap_or_else(|_| String::from(""));
-    let updated_items: Vec<Item> = match parse_create_or_update_items(&payload) {
+    let updated_items: Vec<Item> = match parse_create_or_update_items(&payload, menu_names.as_ref())
+    {
         Ok(items) => items,
         Err(err) => {
             return to_json(
@@ -48,21 +95,55 @@ pub async fn update_items_handler(request: Request) -> Response {
         }
     };
 
+    // the condition holds for one item, never for a whole batch
+    if if_match.is_some() && (updated_items.len() != 1 || batch_query.atomic) {
+        return to_json(
+            FailMsg {
+                msg: "If-Match applies to the update of a single item".to_string(),
+            },
+            HttpStatus::BAD_REQUEST,
+        )
+        .await;
+    }
+
+    if batch_query.atomic {
+        let req = BatchRequest::updates(table_id, updated_items, estimator.as_ref());
+        return atomic_batch_response(repo, events, req, HttpStatus::OK).await;
+    }
+
     // update item(s)
     let mut op_status: OpStatusResponse = HashMap::new();
-    updated_items.iter().for_each(|u_item| {
+    let mut new_version = None;
+    for u_item in updated_items.iter() {
         match execute(
             repo.clone(),
+            estimator.clone(),
+            events.clone(),
             CreateOrUpdateRequest {
-                table_id: table_id,
+                table_id,
                 item: u_item.clone(),
+                if_match: if_match.clone(),
             },
-        ) {
-            Ok(res) => op_status.insert(
-                res.item.id,
+        )
+        .await
+        {
+            Ok(res) => {
+                new_version = Some(res.item.version.clone());
+                op_status.insert(
+                    res.item.id,
+                    StatusWithBody {
+                        status: HttpStatus::OK.as_u16(),
+                        body: json_body::<Item>(res.item.clone()),
+                    },
+                )
+            }
+            Err(Error::PreconditionFailed) => op_status.insert(
+                u_item.id,
                 StatusWithBody {
-                    status: HttpStatus::OK.as_u16(),
-                    body: json_body::<Item>(res.item.clone()),
+                    status: HttpStatus::PRECONDITION_FAILED.as_u16(),
+                    body: json_body::<FailMsg>(FailMsg {
+                        msg: "Precondition failed: the item is in another version".to_string(),
+                    }),
                 },
             ),
             Err(Error::Unknown) => op_status.insert(
@@ -92,23 +173,28 @@ pub async fn update_items_handler(request: Request) -> Response {
                     }),
                 },
             ),
-            Err(Error::VersionConflict) => op_status.insert(
+            Err(Error::VersionConflict(item)) => op_status.insert(
                 u_item.id,
                 StatusWithBody {
-                    status: HttpStatus::BAD_REQUEST.as_u16(),
-                    body: json_body::<FailMsg>(FailMsg {
-                        msg: "Version mismatch: server has newer verstion".to_string(),
-                    }),
+                    status: HttpStatus::CONFLICT.as_u16(),
+                    body: json_body::<ConflictMsg>(ConflictMsg::new(
+                        "Version mismatch: server has newer version".to_string(),
+                        item,
+                    )),
                 },
             ),
         };
-    });
+    }
 
     match op_status.len() {
         1 => {
             let response = op_status.get(&updated_items[0].id).unwrap();
             let status = HttpStatus::from_u16(response.status).unwrap();
-            to_json(&response.body, status).await
+            let json = to_json(&response.body, status).await;
+            match new_version {
+                Some(version) => with_etag(json, &version),
+                None => json,
+            }
         }
         _ => to_json(op_status, HttpStatus::MULTI_STATUS).await,
     }
@@ -119,8 +205,12 @@ mod test {
     use hyper::http;
 
     use crate::api::HttpStatus;
-    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, TableId};
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::domain::types::{
+        ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime, TableId,
+    };
     use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
     use crate::repository::Repository;
     use pretty_assertions::assert_eq;
     use serde_json::{json, Value};
@@ -139,7 +229,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -158,6 +248,9 @@ mod test {
             .body(hyper::Body::from(body))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status(), HttpStatus::OK);
@@ -169,7 +262,7 @@ mod test {
         assert_eq!(json["notes"], "Notes from waiter1");
         assert_eq!(json["quantity"], 2);
         assert_eq!(json["deleted"], false);
-        assert!(json["time_to_prepare"] != "");
+        assert!(json["ready_at"].is_string());
         assert_eq!(json["version"], 2);
     }
 
@@ -180,13 +273,13 @@ mod test {
         ids.iter().for_each(|id| {
             repo.insert(
                 TableId::from_int(1),
-                ItemId::from_int(id.clone()),
+                ItemId::from_int(*id),
                 ItemName::from_str(format!("Name from menu {}", id)),
                 ItemNotes::from_str(format!("Notes from waiter {}", id)),
                 ItemQuantity::one(),
                 false,
                 ItemVersion::ver_one(),
-                format!("2023/12/12-{}", id),
+                PrepTime::from_date(&format!("2023-12-{:02}", id)),
             )
             .ok();
         });
@@ -206,6 +299,9 @@ mod test {
             .body(hyper::Body::from(body_json.to_string()))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status(), HttpStatus::MULTI_STATUS);
@@ -222,7 +318,7 @@ mod test {
             assert_eq!(obj["notes"], format!("Notes from waiter {} updated", id));
             assert_eq!(obj["quantity"], 2);
             assert_eq!(obj["deleted"], false);
-            assert!(obj["time_to_prepare"] != "");
+            assert!(obj["ready_at"].is_string());
             assert_eq!(obj["version"], 2);
         });
     }
@@ -238,7 +334,7 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         let context: Arc<dyn Repository> = Arc::new(repo);
@@ -257,8 +353,97 @@ mod test {
             .body(hyper::Body::from(body))
             .unwrap();
         request.extensions_mut().insert(context);
+        request
+            .extensions_mut()
+            .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
         let response = handle(request).await;
 
         assert_eq!(response.status(), HttpStatus::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn it_should_update_one_item_only_if_the_etag_matches() {
+        let repo: InMemoryRepository = InMemoryRepository::new();
+        for id in [1, 2] {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        let context: Arc<dyn Repository> = Arc::new(repo);
+
+        let one = r#"{"1": {"name": "Pasta", "notes": "No cheese", "quantity": 2}}"#;
+        let two = r#"{"1": {"name": "Pasta", "notes": "", "quantity": 2},
+                      "2": {"name": "Pasta", "notes": "", "quantity": 2}}"#;
+        for (body, if_match, status) in [
+            (two, r#""1""#, HttpStatus::BAD_REQUEST),
+            (one, r#""1""#, HttpStatus::OK),
+            (one, r#""1""#, HttpStatus::PRECONDITION_FAILED),
+        ] {
+            let mut request = hyper::Request::builder()
+                .method(http::Method::PUT)
+                .uri("/tables/1/items")
+                .header(hyper::header::IF_MATCH, if_match)
+                .body(hyper::Body::from(body))
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), status);
+            if status == HttpStatus::OK {
+                assert_eq!(response.headers()[hyper::header::ETAG], r#""2""#);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_return_conflict_with_the_stored_item() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for context in repos {
+            context
+                .insert(
+                    TableId::from_int(1),
+                    ItemId::from_int(1),
+                    ItemName::pizza(),
+                    ItemNotes::some_notes(),
+                    ItemQuantity::one(),
+                    false,
+                    ItemVersion::from_int(3),
+                    PrepTime::from_date("2023-12-12"),
+                )
+                .ok();
+
+            let body = r#"{"1": {"name": "Pasta", "notes": "", "quantity": 2, "version": 2}}"#;
+            let mut request = hyper::Request::builder()
+                .method(http::Method::PUT)
+                .uri("/tables/1/items")
+                .body(hyper::Body::from(body))
+                .unwrap();
+            request.extensions_mut().insert(context.clone());
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            let response = handle(request).await;
+
+            assert_eq!(response.status(), HttpStatus::CONFLICT);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["version"], 3);
+            assert_eq!(json["item"]["name"], "Some pizza");
+            assert_eq!(json["item"]["version"], 3);
+        }
+    }
 }
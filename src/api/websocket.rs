@@ -0,0 +1,406 @@
+use crate::api::audit::CALLER_HEADER;
+use crate::api::helpers::{
+    json_body, CommandId, CommandOp, CommandResponse, FailMsg, ItemsCommand, StatusWithBody,
+};
+use crate::api::idempotency::IDEMPOTENCY_KEY_HEADER;
+use crate::api::HttpStatus;
+use crate::api::{Request, Response};
+use futures::{Future, SinkExt, StreamExt};
+use hyper::header::HeaderValue;
+use hyper::http;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+// This file contains the websocket upgrade of GET /ws. Each text message is an
+// ItemsCommand run as the matching POST, PUT or DELETE /tables/:tid/items request,
+// and is answered with a CommandResponse holding the status and body of that request.
+
+pub const WEBSOCKET_PATH: &str = "/ws";
+
+pub fn is_websocket(request: &Request) -> bool {
+    request.uri().path() == WEBSOCKET_PATH
+}
+
+// Resolves once the server starts shutting down, for the connections that stay open
+// until the client leaves: the websocket sessions and the event streams
+#[derive(Clone)]
+pub struct Closing(watch::Receiver<bool>);
+
+impl Closing {
+    pub async fn wait(mut self) {
+        self.0.wait_for(|closing| *closing).await.ok();
+    }
+}
+
+// The tasks serving the upgraded connections, which hyper no longer tracks, so that
+// the server can close them before shutting down the repositories
+pub struct Sessions {
+    closing: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self {
+            closing: watch::Sender::new(false),
+            tasks: Mutex::new(vec![]),
+        }
+    }
+
+    fn spawn<F>(&self, session: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(tokio::spawn(session));
+    }
+
+    // Fires with start_closing() or close()
+    pub fn closing(&self) -> Closing {
+        Closing(self.closing.subscribe())
+    }
+
+    // Asks every session to close once its current command is answered
+    pub fn start_closing(&self) {
+        self.closing.send_replace(true);
+    }
+
+    // Closes the sessions like start_closing() and waits for them, aborting the ones
+    // still running after the timeout. Returns whether they all closed.
+    pub async fn close(&self, timeout: Duration) -> bool {
+        self.start_closing();
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let aborts: Vec<_> = tasks.iter().map(JoinHandle::abort_handle).collect();
+        match tokio::time::timeout(timeout, futures::future::join_all(tasks)).await {
+            Ok(_) => true,
+            Err(_) => {
+                aborts.iter().for_each(|abort| abort.abort());
+                false
+            }
+        }
+    }
+}
+
+fn header_is(request: &Request, name: http::header::HeaderName, value: &str) -> bool {
+    request
+        .headers()
+        .get_all(name)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case(value))
+}
+
+/// Answers the websocket handshake and serves the commands of the connection.
+///
+/// # Arguments
+///
+/// * `request` - The upgrade request, its `X-Caller-Id` header applies to every command.
+/// * `sessions` - Tracks the task serving the connection, until `Sessions::close` closes it.
+/// * `dispatch` - Runs the request built for a command and returns its response.
+pub fn upgrade<D, Fut>(mut request: Request, sessions: &Sessions, dispatch: D) -> Response
+where
+    D: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    let is_upgrade = request.method() == http::Method::GET
+        && header_is(&request, http::header::CONNECTION, "upgrade")
+        && header_is(&request, http::header::UPGRADE, "websocket")
+        && header_is(&request, http::header::SEC_WEBSOCKET_VERSION, "13");
+    let key = match request.headers().get(http::header::SEC_WEBSOCKET_KEY) {
+        Some(key) if is_upgrade => key.clone(),
+        _ => {
+            let body = json_body(FailMsg {
+                msg: "Expected a websocket upgrade".to_string(),
+            });
+            return http::Response::builder()
+                .status(HttpStatus::UPGRADE_REQUIRED)
+                .header(http::header::UPGRADE, "websocket")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(hyper::Body::from(body.to_string()))
+                .unwrap();
+        }
+    };
+    let caller = request.headers().get(CALLER_HEADER).cloned();
+
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    let closing = sessions.closing().wait();
+    sessions.spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                serve_commands(socket, dispatch, caller, closing).await;
+            }
+            Err(err) => tracing::error!(error = %err, "websocket upgrade failed"),
+        }
+    });
+
+    http::Response::builder()
+        .status(HttpStatus::SWITCHING_PROTOCOLS)
+        .header(http::header::CONNECTION, "upgrade")
+        .header(http::header::UPGRADE, "websocket")
+        .header(
+            http::header::SEC_WEBSOCKET_ACCEPT,
+            derive_accept_key(key.as_bytes()),
+        )
+        .body(hyper::Body::empty())
+        .unwrap()
+}
+
+// Commands are run one at a time in the order they arrive, until the client or
+// closing ends the session
+async fn serve_commands<S, D, Fut>(
+    mut socket: WebSocketStream<S>,
+    dispatch: D,
+    caller: Option<HeaderValue>,
+    closing: impl Future<Output = ()>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    D: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    tokio::pin!(closing);
+    loop {
+        let message = tokio::select! {
+            message = socket.next() => message,
+            _ = &mut closing => {
+                socket.close(None).await.ok();
+                break;
+            }
+        };
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            // Pings are answered by the socket itself
+            Some(Ok(_)) => continue,
+        };
+        let response = run_command(&text, &dispatch, caller.as_ref()).await;
+        let reply = serde_json::to_string(&response).unwrap_or_default();
+        if socket.send(Message::Text(reply)).await.is_err() {
+            break;
+        }
+    }
+}
+
+// The id of a command that could not be read, when it has a valid one
+fn command_id(text: &str) -> Option<CommandId> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    serde_json::from_value(value.get("id")?.clone()).ok()
+}
+
+pub async fn run_command<D, Fut>(
+    text: &str,
+    dispatch: &D,
+    caller: Option<&HeaderValue>,
+) -> CommandResponse
+where
+    D: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let command: ItemsCommand = match serde_json::from_str(text) {
+        Ok(command) => command,
+        Err(err) => {
+            return CommandResponse {
+                id: command_id(text),
+                result: StatusWithBody {
+                    status: HttpStatus::BAD_REQUEST.as_u16(),
+                    body: json_body(FailMsg {
+                        msg: err.to_string(),
+                    }),
+                },
+            }
+        }
+    };
+
+    let method = match command.op {
+        CommandOp::Create => http::Method::POST,
+        CommandOp::Update => http::Method::PUT,
+        CommandOp::Delete => http::Method::DELETE,
+    };
+    let mut uri = format!("/tables/{}/items", command.table_id);
+    if command.atomic {
+        uri.push_str("?atomic=true");
+    }
+    let mut builder = http::Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, "application/json");
+    if let Some(caller) = caller {
+        builder = builder.header(CALLER_HEADER, caller);
+    }
+    if let Some(key) = &command.idempotency_key {
+        builder = builder.header(IDEMPOTENCY_KEY_HEADER, key.as_str());
+    }
+    let request = match builder.body(hyper::Body::from(command.body.to_string())) {
+        Ok(request) => request,
+        Err(err) => {
+            return CommandResponse {
+                id: Some(command.id),
+                result: StatusWithBody {
+                    status: HttpStatus::BAD_REQUEST.as_u16(),
+                    body: json_body(FailMsg {
+                        msg: err.to_string(),
+                    }),
+                },
+            }
+        }
+    };
+
+    let response = dispatch(request).await;
+    let status = response.status().as_u16();
+    let body = match hyper::body::to_bytes(response.into_body()).await {
+        // A body that isn't JSON, e.g. the message of a panic, is passed on as a string
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())
+        }),
+        Err(_) => serde_json::Value::Null,
+    };
+    CommandResponse {
+        id: Some(command.id),
+        result: StatusWithBody { status, body },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_command, serve_commands, upgrade, Sessions};
+    use crate::api::HttpStatus;
+    use crate::domain::prep_time::{FixedEstimator, PrepTimeEstimator};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::Repository;
+    use futures::StreamExt;
+    use hyper::http;
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::WebSocketStream;
+
+    use crate::handle;
+
+    async fn run(repo: &Arc<dyn Repository>, command: Value) -> Value {
+        let dispatch = |mut request: crate::api::Request| {
+            request.extensions_mut().insert(repo.clone());
+            request
+                .extensions_mut()
+                .insert::<Arc<dyn PrepTimeEstimator>>(Arc::new(FixedEstimator(10)));
+            handle(request)
+        };
+        let response = run_command(&command.to_string(), &dispatch, None).await;
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_run_commands_like_the_matching_requests() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let item = json!({"name": "Pizza", "notes": "", "quantity": 1});
+
+        let response = run(
+            &repo,
+            json!({"id": "a1", "op": "create", "table_id": 3, "body": {"1": item, "2": item}}),
+        )
+        .await;
+        assert_eq!(response["id"], "a1");
+        assert_eq!(response["status"], 207);
+        assert_eq!(response["body"]["1"]["status"], 201);
+        assert_eq!(response["body"]["2"]["body"]["version"], 1);
+
+        let update = json!({"name": "Pizza", "notes": "Spicy", "quantity": 2, "version": 1});
+        let response = run(
+            &repo,
+            json!({"id": 2, "op": "update", "table_id": 3, "body": {"1": update}}),
+        )
+        .await;
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"]["notes"], "Spicy");
+
+        let response = run(
+            &repo,
+            json!({"id": 3, "op": "delete", "table_id": 3, "body": {"ids": [2, 4]}}),
+        )
+        .await;
+        assert_eq!(response["body"]["2"]["status"], 200);
+        assert_eq!(response["body"]["4"]["status"], 404);
+    }
+
+    #[tokio::test]
+    async fn it_should_answer_invalid_commands_with_a_400() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+
+        let response = run(
+            &repo,
+            json!({"id": 7, "op": "eat", "table_id": 3, "body": {}}),
+        )
+        .await;
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["status"], 400);
+
+        let response = run(&repo, json!({"op": "create"})).await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["status"], 400);
+
+        // The body is checked by the request handler
+        let response = run(
+            &repo,
+            json!({"id": 8, "op": "delete", "table_id": 3, "body": {"items": [1]}}),
+        )
+        .await;
+        assert_eq!(response["id"], 8);
+        assert_eq!(response["status"], 400);
+    }
+
+    #[tokio::test]
+    async fn it_should_accept_only_websocket_upgrades() {
+        let dispatch = |request: crate::api::Request| handle(request);
+        let request = hyper::Request::builder()
+            .uri("/ws")
+            .header(http::header::CONNECTION, "keep-alive, Upgrade")
+            .header(http::header::UPGRADE, "websocket")
+            .header(http::header::SEC_WEBSOCKET_VERSION, "13")
+            .header(http::header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let sessions = Sessions::new();
+        let response = upgrade(request, &sessions, dispatch);
+        assert_eq!(response.status(), HttpStatus::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            response.headers()[http::header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let request = hyper::Request::builder()
+            .uri("/ws")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let response = upgrade(request, &sessions, dispatch);
+        assert_eq!(response.status(), HttpStatus::UPGRADE_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn it_should_close_the_open_sessions() {
+        let sessions = Sessions::new();
+        let (server, client) = tokio::io::duplex(1024);
+        let closing = sessions.closing().wait();
+        sessions.spawn(async move {
+            let socket = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+            serve_commands(socket, handle, None, closing).await;
+        });
+        let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+        assert!(sessions.close(Duration::from_secs(5)).await);
+        match client.next().await {
+            Some(Ok(Message::Close(_))) => {}
+            _ => unreachable!(),
+        }
+    }
+}
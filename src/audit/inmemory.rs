@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+use crate::audit::{AuditEntry, AuditError, AuditRecord, AuditRepository};
+use crate::domain::types::{IdType, TableId};
+
+// In memory audit log implementation based on Vec, records are kept in the order appended
+
+pub struct InMemoryAuditRepository {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl InMemoryAuditRepository {
+    pub fn new() -> Self {
+        Self {
+            records: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl AuditRepository for InMemoryAuditRepository {
+    fn append(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        let mut lock = match self.records.lock() {
+            Ok(lock) => lock,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        let id = lock.len() as IdType + 1;
+        lock.push(AuditRecord { id, entry });
+        Ok(())
+    }
+
+    fn fetch(
+        &self,
+        table_id: Option<TableId<IdType>>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<AuditRecord>, AuditError> {
+        let lock = match self.records.lock() {
+            Ok(lock) => lock,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        Ok(lock
+            .iter()
+            .filter(|record| table_id.is_none_or(|table_id| record.entry.table_id == table_id))
+            .filter(|record| since.is_none_or(|since| record.entry.at >= since))
+            .cloned()
+            .collect())
+    }
+
+    // Nothing to write out, the records go away with the repository
+    fn shutdown(&self) -> Result<(), AuditError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+pub mod inmemory;
+pub mod sqlite;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::domain::types::{
+    serialize_timestamp, IdType, Item, ItemId, ItemVersion, TableId, VersionType,
+};
+use crate::repository::Repository;
+
+// Append-only log of the item changes: who changed what and when. Every insert,
+// update, delete and restore made through a Repository for a caller adds a record,
+// written by the item repository together with the change.
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Insert,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Insert => "insert",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Restore => "restore",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "insert" => Ok(AuditAction::Insert),
+            "update" => Ok(AuditAction::Update),
+            "delete" => Ok(AuditAction::Delete),
+            "restore" => Ok(AuditAction::Restore),
+            _ => Err(format!("'{}' is not a valid audit action.", s)),
+        }
+    }
+}
+
+// Who asked for a change, taken from a request header
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Caller(String);
+
+impl Caller {
+    pub fn anonymous() -> Self {
+        Self("anonymous".to_string())
+    }
+}
+
+impl TryFrom<String> for Caller {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_empty = s.trim().is_empty();
+        let is_too_long = s.chars().count() > 100;
+        let has_control_characters = s.chars().any(|c| c.is_control());
+
+        if is_empty || is_too_long || has_control_characters {
+            Err(format!("'{}' is not a valid caller.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl From<Caller> for String {
+    fn from(c: Caller) -> Self {
+        c.0
+    }
+}
+
+// One change of an item, old is None for inserts and new is None for deletes
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+    pub action: AuditAction,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+    pub version: ItemVersion<VersionType>,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub at: DateTime<Utc>,
+    pub caller: Caller,
+}
+
+impl AuditEntry {
+    pub fn new(
+        table_id: TableId<IdType>,
+        action: AuditAction,
+        old: Option<&Item>,
+        new: Option<&Item>,
+        caller: Caller,
+    ) -> Option<Self> {
+        // The version the change left the item in, or the one it deleted
+        let item = new.or(old)?;
+        Some(Self {
+            table_id,
+            item_id: item.id,
+            action,
+            old: old.and_then(|item| serde_json::to_value(item).ok()),
+            new: new.and_then(|item| serde_json::to_value(item).ok()),
+            version: item.version.clone(),
+            at: Utc::now(),
+            caller,
+        })
+    }
+}
+
+// A stored entry, ids grow in the order entries were appended
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub id: IdType,
+    #[serde(flatten)]
+    pub entry: AuditEntry,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuditError {
+    Unknown,
+}
+
+pub trait AuditRepository: Send + Sync {
+    fn append(&self, entry: AuditEntry) -> Result<(), AuditError>;
+    // Oldest first, of one table or of all tables, from a time on or since the beginning
+    fn fetch(
+        &self,
+        table_id: Option<TableId<IdType>>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<AuditRecord>, AuditError>;
+    // Called once the server stopped serving, writes out what is still pending
+    fn shutdown(&self) -> Result<(), AuditError>;
+}
+
+// The entry to log for an item change, None when no caller is making it
+pub fn entry_for(
+    caller: Option<&Caller>,
+    table_id: TableId<IdType>,
+    action: AuditAction,
+    old: Option<&Item>,
+    new: Option<&Item>,
+) -> Option<AuditEntry> {
+    AuditEntry::new(table_id, action, old, new, caller?.clone())
+}
+
+// Shared by all requests, hands out a Repository per caller. The log is the one the
+// item repository appends to.
+pub struct Auditor {
+    log: Arc<dyn AuditRepository>,
+}
+
+impl Auditor {
+    pub fn new(log: Arc<dyn AuditRepository>) -> Self {
+        Self { log }
+    }
+
+    pub fn log(&self) -> Arc<dyn AuditRepository> {
+        self.log.clone()
+    }
+
+    pub fn audit(&self, repo: Arc<dyn Repository>, caller: Caller) -> Arc<dyn Repository> {
+        repo.for_caller(caller)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::types::{ItemName, ItemNotes, ItemQuantity, PrepTime};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+    use crate::repository::{BatchOp, InsertError};
+
+    #[test]
+    fn callers_are_validated() {
+        assert!(Caller::try_from("waiter-7".to_string()).is_ok());
+        assert!(Caller::try_from(" ".to_string()).is_err());
+        assert!(Caller::try_from("a\nb".to_string()).is_err());
+        assert!(Caller::try_from("a".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn audit_actions_round_trip_through_their_names() {
+        for action in [
+            AuditAction::Insert,
+            AuditAction::Update,
+            AuditAction::Delete,
+            AuditAction::Restore,
+        ] {
+            assert_eq!(
+                AuditAction::try_from(action.as_str().to_string()),
+                Ok(action)
+            );
+        }
+    }
+
+    fn caller() -> Caller {
+        Caller::try_from("waiter-7".to_string()).unwrap()
+    }
+
+    // A repository of every backend for the caller, with the log it appends to
+    fn audited() -> Vec<(Arc<dyn Repository>, Arc<dyn AuditRepository>)> {
+        let in_memory = InMemoryRepository::new();
+        let in_memory_log: Arc<dyn AuditRepository> = in_memory.audit_log();
+        let sqlite = SqliteRepository::try_new("", true).unwrap();
+        let sqlite_log: Arc<dyn AuditRepository> = Arc::new(sqlite.audit_log());
+        vec![
+            (in_memory.for_caller(caller()), in_memory_log),
+            (sqlite.for_caller(caller()), sqlite_log),
+        ]
+    }
+
+    fn item(item_id: IdType, name: ItemName) -> Item {
+        Item::new(
+            ItemId::from_int(item_id),
+            name,
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+    }
+
+    fn actions(log: &Arc<dyn AuditRepository>) -> Vec<(AuditAction, VersionType)> {
+        log.fetch(None, None)
+            .unwrap()
+            .into_iter()
+            .map(|record| (record.entry.action, VersionType::from(record.entry.version)))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_log_every_change_with_old_and_new_values() {
+        for (repo, log) in audited() {
+            let table_id = TableId::from_int(12);
+            let pizza = item(1, ItemName::pizza());
+            repo.insert(
+                table_id,
+                pizza.id,
+                pizza.name.clone(),
+                pizza.notes.clone(),
+                pizza.quantity.clone(),
+                false,
+                pizza.version.clone(),
+                pizza.prep_time,
+            )
+            .ok();
+            repo.update(
+                table_id,
+                pizza.id,
+                ItemName::pasta(),
+                pizza.notes.clone(),
+                pizza.quantity.clone(),
+                false,
+                ItemVersion::ver_one(),
+                pizza.prep_time,
+            )
+            .ok();
+            repo.delete(table_id, pizza.id, None).ok();
+            repo.restore(table_id, pizza.id).ok();
+            // Failed changes are not logged
+            repo.delete(table_id, ItemId::from_int(2), None).ok();
+
+            assert_eq!(
+                actions(&log),
+                vec![
+                    (AuditAction::Insert, 1),
+                    (AuditAction::Update, 2),
+                    (AuditAction::Delete, 2),
+                    (AuditAction::Restore, 4),
+                ]
+            );
+            let records = log.fetch(Some(table_id), None).unwrap();
+            assert_eq!(records[1].entry.old.as_ref().unwrap()["name"], "Some pizza");
+            assert_eq!(records[1].entry.new.as_ref().unwrap()["name"], "Some pasta");
+            assert_eq!(records[2].entry.new, None);
+            assert_eq!(records[3].entry.old.as_ref().unwrap()["deleted"], true);
+            assert!(records.iter().all(|record| record.entry.caller == caller()));
+        }
+    }
+
+    #[test]
+    fn it_should_log_each_operation_of_a_batch() {
+        for (repo, log) in audited() {
+            let table_id = TableId::from_int(12);
+            let ops = vec![
+                BatchOp::Insert(item(1, ItemName::pizza())),
+                BatchOp::Insert(item(2, ItemName::pasta())),
+                BatchOp::Delete(ItemId::from_int(1)),
+            ];
+            assert!(repo.apply_batch(table_id, ops).is_ok());
+
+            assert_eq!(
+                actions(&log),
+                vec![
+                    (AuditAction::Insert, 1),
+                    (AuditAction::Insert, 1),
+                    (AuditAction::Delete, 1),
+                ]
+            );
+            let records = log.fetch(None, None).unwrap();
+            assert_eq!(records[2].entry.item_id, ItemId::from_int(1));
+            assert_eq!(records[2].entry.old.as_ref().unwrap()["name"], "Some pizza");
+        }
+    }
+
+    #[test]
+    fn it_should_not_log_changes_made_without_a_caller() {
+        let repo = InMemoryRepository::new();
+        let pizza = item(1, ItemName::pizza());
+        repo.insert(
+            TableId::from_int(12),
+            pizza.id,
+            pizza.name,
+            pizza.notes,
+            pizza.quantity,
+            false,
+            pizza.version,
+            pizza.prep_time,
+        )
+        .ok();
+
+        assert!(repo.audit_log().fetch(None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_should_log_changes_made_on_another_thread() {
+        for (repo, log) in audited() {
+            let pizza = item(1, ItemName::pizza());
+            std::thread::spawn(move || {
+                repo.insert(
+                    TableId::from_int(12),
+                    pizza.id,
+                    pizza.name,
+                    pizza.notes,
+                    pizza.quantity,
+                    false,
+                    pizza.version,
+                    pizza.prep_time,
+                )
+                .ok();
+            })
+            .join()
+            .unwrap();
+
+            assert_eq!(actions(&log), vec![(AuditAction::Insert, 1)]);
+        }
+    }
+
+    #[test]
+    fn it_should_roll_the_change_back_when_the_log_refuses_it() {
+        let sqlite = SqliteRepository::try_new("", true).unwrap();
+        sqlite
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER audit_log_full BEFORE INSERT ON audit_log
+                BEGIN
+                    SELECT RAISE(ABORT, 'audit_log is full');
+                END;",
+            )
+            .unwrap();
+        let repo = sqlite.for_caller(caller());
+        let pizza = item(1, ItemName::pizza());
+
+        let result = repo.insert(
+            TableId::from_int(12),
+            pizza.id,
+            pizza.name,
+            pizza.notes,
+            pizza.quantity,
+            false,
+            pizza.version,
+            pizza.prep_time,
+        );
+
+        assert!(matches!(result, Err(InsertError::Unknown)));
+        assert!(sqlite.fetch_one(TableId::from_int(12), pizza.id).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row};
+
+use crate::audit::{AuditAction, AuditEntry, AuditError, AuditRecord, AuditRepository, Caller};
+use crate::domain::types::{to_rfc3339, IdType, ItemId, ItemVersion, TableId, VersionType};
+use crate::repository::sqlite::SqliteRepository;
+use crate::repository::{migrations, BUSY_TIMEOUT};
+
+// An Sqlite audit log implementation, the audit_log table is created by the migrations.
+// The item repositories append to it with append_entry() in their own transactions.
+
+pub struct SqliteAuditRepository {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteAuditRepository {
+    // Opens the database, applying pending migrations when apply_migrations is set
+    pub fn try_new(path: &str, apply_migrations: bool) -> Result<Self, ()> {
+        let mut connection = match Connection::open(path) {
+            Ok(connection) => connection,
+            _ => return Err(()),
+        };
+        if connection.busy_timeout(BUSY_TIMEOUT).is_err() {
+            return Err(());
+        }
+        match migrations::prepare(&mut connection, apply_migrations) {
+            Ok(_) => Ok(Self {
+                connection: Arc::new(Mutex::new(connection)),
+            }),
+            _ => Err(()),
+        }
+    }
+
+    // Reads through the connection of an item repository, an in-memory database
+    // can't be opened twice
+    pub fn sharing(connection: Arc<Mutex<Connection>>) -> Self {
+        Self { connection }
+    }
+}
+
+// Appends on the given connection, within the transaction it has open if any
+pub fn append_entry(connection: &Connection, entry: AuditEntry) -> Result<(), AuditError> {
+    match connection.execute(
+        "insert into audit_log (table_id, item_id, action, old_value, new_value, version, at, caller) values (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            IdType::from(entry.table_id),
+            IdType::from(entry.item_id),
+            entry.action.as_str(),
+            entry.old.map(|old| old.to_string()),
+            entry.new.map(|new| new.to_string()),
+            VersionType::from(entry.version),
+            to_rfc3339(&entry.at),
+            String::from(entry.caller)
+        ],
+    ) {
+        Ok(_) => Ok(()),
+        _ => Err(AuditError::Unknown),
+    }
+}
+
+fn to_json(value: Option<String>) -> Option<Option<serde_json::Value>> {
+    match value {
+        Some(value) => serde_json::from_str(&value).ok().map(Some),
+        None => Some(None),
+    }
+}
+
+fn to_audit_record(row: &Row) -> rusqlite::Result<Result<AuditRecord, String>> {
+    let id: IdType = row.get(0)?;
+    let table_id: IdType = row.get(1)?;
+    let item_id: IdType = row.get(2)?;
+    let action: String = row.get(3)?;
+    let old: Option<String> = row.get(4)?;
+    let new: Option<String> = row.get(5)?;
+    let version: VersionType = row.get(6)?;
+    let at: String = row.get(7)?;
+    let caller: String = row.get(8)?;
+    Ok(
+        match (
+            TableId::try_from(table_id.to_string()),
+            ItemId::try_from(item_id.to_string()),
+            AuditAction::try_from(action),
+            to_json(old),
+            to_json(new),
+            ItemVersion::try_from(version.to_string()),
+            DateTime::parse_from_rfc3339(&at),
+            Caller::try_from(caller),
+        ) {
+            (
+                Ok(table_id),
+                Ok(item_id),
+                Ok(action),
+                Some(old),
+                Some(new),
+                Ok(version),
+                Ok(at),
+                Ok(caller),
+            ) => Ok(AuditRecord {
+                id,
+                entry: AuditEntry {
+                    table_id,
+                    item_id,
+                    action,
+                    old,
+                    new,
+                    version,
+                    at: at.with_timezone(&Utc),
+                    caller,
+                },
+            }),
+            _ => Err(format!("Invalid audit_log row with id {}", id)),
+        },
+    )
+}
+
+impl AuditRepository for SqliteAuditRepository {
+    fn append(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        append_entry(&connection, entry)
+    }
+
+    fn fetch(
+        &self,
+        table_id: Option<TableId<IdType>>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<AuditRecord>, AuditError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        let mut query = String::from(
+            "select id, table_id, item_id, action, old_value, new_value, version, at, caller from audit_log where 1=1",
+        );
+        let mut params: Vec<Value> = vec![];
+        if let Some(table_id) = table_id {
+            query.push_str(" and table_id = ?");
+            params.push(Value::from(IdType::from(table_id)));
+        }
+        if let Some(since) = since {
+            query.push_str(" and at >= ?");
+            params.push(Value::from(to_rfc3339(&since)));
+        }
+        query.push_str(" order by id");
+
+        let mut stmt = match connection.prepare(&query) {
+            Ok(stmt) => stmt,
+            _ => return Err(AuditError::Unknown),
+        };
+        let rows = match stmt.query_map(params_from_iter(params), to_audit_record) {
+            Ok(rows) => rows,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        let mut records = vec![];
+        for row in rows {
+            match row {
+                Ok(Ok(record)) => records.push(record),
+                _ => return Err(AuditError::Unknown),
+            }
+        }
+        Ok(records)
+    }
+
+    // The connection itself is closed when the repository is dropped
+    fn shutdown(&self) -> Result<(), AuditError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(AuditError::Unknown),
+        };
+
+        match SqliteRepository::checkpoint(&connection) {
+            Ok(()) => Ok(()),
+            _ => Err(AuditError::Unknown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::types::{Item, ItemName, ItemNotes, ItemQuantity, PrepTime};
+
+    fn repo() -> SqliteAuditRepository {
+        match SqliteAuditRepository::try_new("", true) {
+            Ok(repo) => repo,
+            _ => panic!("Error while creating sqlite audit repo"),
+        }
+    }
+
+    fn entry(table_id: IdType, at: &str) -> AuditEntry {
+        let item = Item::new(
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        );
+        AuditEntry {
+            at: DateTime::parse_from_rfc3339(at)
+                .unwrap()
+                .with_timezone(&Utc),
+            ..AuditEntry::new(
+                TableId::from_int(table_id),
+                AuditAction::Insert,
+                None,
+                Some(&item),
+                Caller::anonymous(),
+            )
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn it_should_fetch_records_by_table_and_time() {
+        let repo = repo();
+        let first = entry(12, "2023-12-12T10:00:00Z");
+        repo.append(first.clone()).unwrap();
+        repo.append(entry(3, "2023-12-12T11:00:00Z")).unwrap();
+        repo.append(entry(12, "2023-12-12T12:00:00Z")).unwrap();
+
+        let all = repo.fetch(None, None).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            all[0],
+            AuditRecord {
+                id: 1,
+                entry: first
+            }
+        );
+
+        let table = repo.fetch(Some(TableId::from_int(12)), None).unwrap();
+        assert_eq!(
+            table.iter().map(|record| record.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+
+        let since = DateTime::parse_from_rfc3339("2023-12-12T11:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let recent = repo
+            .fetch(Some(TableId::from_int(12)), Some(since))
+            .unwrap();
+        assert_eq!(
+            recent.iter().map(|record| record.id).collect::<Vec<_>>(),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_to_change_or_remove_records() {
+        let repo = repo();
+        repo.append(entry(12, "2023-12-12T10:00:00Z")).unwrap();
+
+        let connection = repo.connection.lock().unwrap();
+        assert!(connection
+            .execute("update audit_log set caller = 'someone else'", [])
+            .is_err());
+        assert!(connection.execute("delete from audit_log", []).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use rand::Rng;
-use reqwest;
 use serde_json::json;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
@@ -9,14 +9,24 @@ use std::time::Duration;
 
 // Client of the restaurant API
 
-pub fn client_main(id: u16, base_url: &str, sleep_max_ms: u64, is_running: Arc<AtomicBool>) {
+pub fn client_main(
+    id: u16,
+    base_url: &str,
+    sleep_max_ms: u64,
+    table_ids: RangeInclusive<u32>,
+    is_running: Arc<AtomicBool>,
+) {
     tracing::info!("Spawned a client with id {}", id);
     while is_running.load(Ordering::Relaxed) {
 
-        let methods = vec!["GET", "POST", "PUT", "DELETE"];
+        let methods = ["GET", "POST", "PUT", "DELETE"];
         let mut rng = rand::thread_rng();
 
         for _ in 0..4 {
+            // Stop between two requests when the server shuts down
+            if !is_running.load(Ordering::Relaxed) {
+                break;
+            }
             let method = methods[rng.gen_range(0..methods.len())];
             let endpoint = match method {
                 "GET" => {
@@ -24,10 +34,10 @@ pub fn client_main(id: u16, base_url: &str, sleep_max_ms: u64, is_running: Arc<A
                         format!(
                             "{}/tables/{}/items",
                             base_url,
-                            gen_random_table_id(&mut rng)
+                            gen_random_table_id(&table_ids, &mut rng)
                         )
                     } else {
-                        let table_id = gen_random_table_id(&mut rng);
+                        let table_id = gen_random_table_id(&table_ids, &mut rng);
                         let item_id = gen_random_item_id(&mut rng);
                         format!("{}/tables/{}/items/{}", base_url, table_id, item_id)
                     }
@@ -35,14 +45,14 @@ pub fn client_main(id: u16, base_url: &str, sleep_max_ms: u64, is_running: Arc<A
                 "POST" => format!(
                     "{}/tables/{}/items",
                     base_url,
-                    gen_random_table_id(&mut rng)
+                    gen_random_table_id(&table_ids, &mut rng)
                 ),
                 "PUT" => format!(
                     "{}/tables/{}/items",
                     base_url,
-                    gen_random_table_id(&mut rng)
+                    gen_random_table_id(&table_ids, &mut rng)
                 ),
-                "DELETE" => gen_delete_url(base_url, &mut rng),
+                "DELETE" => gen_delete_url(base_url, &table_ids, &mut rng),
                 _ => panic!("Invalid method"),
             };
 
@@ -67,8 +77,8 @@ fn gen_random_number(rng: &mut rand::rngs::ThreadRng) -> usize {
     rng.gen_range(1..=100)
 }
 
-fn gen_random_table_id(rng: &mut rand::rngs::ThreadRng) -> usize {
-    gen_random_number(rng)
+fn gen_random_table_id(table_ids: &RangeInclusive<u32>, rng: &mut rand::rngs::ThreadRng) -> u32 {
+    rng.gen_range(table_ids.clone())
 }
 
 fn gen_random_item_id(rng: &mut rand::rngs::ThreadRng) -> usize {
@@ -124,11 +134,15 @@ fn gen_delete_body(url: &str, rng: &mut rand::rngs::ThreadRng) -> String {
         })
         .to_string();
     }
-    return String::from("");
+    String::from("")
 }
 
-fn gen_delete_url(base_url: &str, rng: &mut rand::rngs::ThreadRng) -> String {
-    let table_id = gen_random_table_id(rng);
+fn gen_delete_url(
+    base_url: &str,
+    table_ids: &RangeInclusive<u32>,
+    rng: &mut rand::rngs::ThreadRng,
+) -> String {
+    let table_id = gen_random_table_id(table_ids, rng);
     if rng.gen_bool(0.5) {
         format!("{}/tables/{}/items", base_url, table_id)
     } else {
@@ -1,19 +1,413 @@
 // config.rs
-// CLI options and logging setup
-use serde_derive::Deserialize;
-use structopt::StructOpt;
-use clap::Parser;
+// CLI options, configuration file and environment variables
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// The effective configuration is layered: the defaults below, then the TOML file
+// given with --config, then the RESTAURANT_* environment variables, then the CLI
+// flags. Every key of the file is the name of a flag with underscores, and every
+// variable is that name in upper case, e.g. max_body_bytes and RESTAURANT_MAX_BODY_BYTES.
 
 /// Restaurant API
-#[derive(Parser, Debug, Deserialize, StructOpt)]
-#[structopt(name = "restaurant-api")]
+#[derive(Parser, Debug)]
+#[command(name = "restaurant-api")]
 pub struct Opt {
-    /// Server address
-    #[structopt(short, long, default_value = "127.0.0.1")]
+    /// TOML file with settings, overridden by RESTAURANT_* variables and flags
+    #[arg(long, env = "RESTAURANT_CONFIG")]
+    pub config: Option<String>,
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    pub print_config: bool,
+    /// Server address [default: 127.0.0.1]
+    #[arg(short, long, env = "RESTAURANT_ADDRESS")]
+    pub address: Option<String>,
+    /// Server port 0-65535 [default: 3000]
+    #[arg(short, long, env = "RESTAURANT_PORT")]
+    pub port: Option<u16>,
+    /// Load generating clients to spawn [default: 10]
+    #[arg(short, long, env = "RESTAURANT_NUM_CLIENTS")]
+    pub num_clients: Option<u16>,
+    /// Where items, menu and audit log are stored: memory, sqlite:<path> or sqlite-pool:<path> [default: sqlite-pool:restaurant.sqlite]
+    #[arg(long, env = "RESTAURANT_STORAGE")]
+    pub storage: Option<Storage>,
+    /// Keep everything in memory when the sqlite database can't be opened, instead of exiting
+    #[arg(long, env = "RESTAURANT_FALLBACK_MEMORY", num_args = 0..=1, default_missing_value = "true")]
+    pub fallback_memory: Option<bool>,
+    /// Lowest valid table id [default: 1]
+    #[arg(long, env = "RESTAURANT_MIN_TABLE_ID")]
+    pub min_table_id: Option<u32>,
+    /// Highest valid table id [default: 100]
+    #[arg(long, env = "RESTAURANT_MAX_TABLE_ID")]
+    pub max_table_id: Option<u32>,
+    /// Shortest prep time estimate in minutes [default: 5]
+    #[arg(long, env = "RESTAURANT_MIN_PREP_MINUTES")]
+    pub min_prep_minutes: Option<u32>,
+    /// Longest prep time estimate in minutes [default: 15]
+    #[arg(long, env = "RESTAURANT_MAX_PREP_MINUTES")]
+    pub max_prep_minutes: Option<u32>,
+    /// Seed for the prep time estimates, random when not set
+    #[arg(long, env = "RESTAURANT_PREP_SEED")]
+    pub prep_seed: Option<u64>,
+    /// JSON or TOML file with prep minutes per menu item, unlisted items use the random range
+    #[arg(long, env = "RESTAURANT_MENU_CATALOG")]
+    pub menu_catalog: Option<String>,
+    /// Don't apply pending schema migrations on startup
+    #[arg(long, env = "RESTAURANT_SKIP_MIGRATIONS", num_args = 0..=1, default_missing_value = "true")]
+    pub skip_migrations: Option<bool>,
+    /// Reject ordered items whose name is not on the menu
+    #[arg(long, env = "RESTAURANT_STRICT_MENU", num_args = 0..=1, default_missing_value = "true")]
+    pub strict_menu: Option<bool>,
+    /// Tax added to bills, as a percentage with up to two decimals [default: 0]
+    #[arg(long, env = "RESTAURANT_TAX_PERCENT")]
+    pub tax_percent: Option<String>,
+    /// Minutes a response is replayed to retries with the same Idempotency-Key [default: 1440]
+    #[arg(long, env = "RESTAURANT_IDEMPOTENCY_TTL_MINUTES")]
+    pub idempotency_ttl_minutes: Option<u32>,
+    /// Hard delete items deleted more than this many days ago, in the background
+    #[arg(long, env = "RESTAURANT_PURGE_AFTER_DAYS")]
+    pub purge_after_days: Option<u32>,
+    /// Minutes between two background purges [default: 60]
+    #[arg(long, env = "RESTAURANT_PURGE_INTERVAL_MINUTES")]
+    pub purge_interval_minutes: Option<u32>,
+    /// Log filter, a level or per module directives like restaurant_api=debug [default: info]
+    #[arg(long, env = "RESTAURANT_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Log line layout [default: full]
+    #[arg(long, env = "RESTAURANT_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Largest accepted request body in bytes [default: 1048576]
+    #[arg(long, env = "RESTAURANT_MAX_BODY_BYTES")]
+    pub max_body_bytes: Option<u64>,
+    /// Seconds a client may take to send the request headers [default: 30]
+    #[arg(long, env = "RESTAURANT_HEADER_READ_TIMEOUT_SECONDS")]
+    pub header_read_timeout_seconds: Option<u64>,
+    /// Seconds the requests in flight at shutdown may take before they are dropped [default: 10]
+    #[arg(long, env = "RESTAURANT_DRAIN_TIMEOUT_SECONDS")]
+    pub drain_timeout_seconds: Option<u64>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage the sqlite schema migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Hard delete old deleted items once and compact the sqlite database
+    Purge {
+        /// Remove the items deleted more than this many days ago
+        #[arg(long)]
+        older_than_days: u32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// Show applied and pending migrations
+    Status,
+    /// Apply pending migrations, up to the latest one by default
+    Up {
+        #[arg(long)]
+        to: Option<u32>,
+    },
+    /// Revert migrations, the last applied one by default
+    Down {
+        #[arg(long)]
+        to: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Storage {
+    // Everything is lost on restart
+    Memory,
+    // One sqlite connection
+    Sqlite(String),
+    // A sqlite writer and a pool of readers in WAL mode
+    SqlitePool(String),
+}
+
+impl Storage {
+    // The database file, None when nothing is stored on disk
+    pub fn sqlite_path(&self) -> Option<&str> {
+        match self {
+            Storage::Memory => None,
+            Storage::Sqlite(path) | Storage::SqlitePool(path) => Some(path),
+        }
+    }
+}
+
+impl FromStr for Storage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let storage = match s.split_once(':') {
+            None if s == "memory" => Some(Storage::Memory),
+            Some(("sqlite", path)) if !path.is_empty() => Some(Storage::Sqlite(path.to_string())),
+            Some(("sqlite-pool", path)) if !path.is_empty() => {
+                Some(Storage::SqlitePool(path.to_string()))
+            }
+            _ => None,
+        };
+        storage.ok_or_else(|| {
+            format!(
+                "'{}' is not a valid storage, expected memory, sqlite:<path> or sqlite-pool:<path>.",
+                s
+            )
+        })
+    }
+}
+
+impl TryFrom<String> for Storage {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Storage::Memory => write!(f, "memory"),
+            Storage::Sqlite(path) => write!(f, "sqlite:{}", path),
+            Storage::SqlitePool(path) => write!(f, "sqlite-pool:{}", path),
+        }
+    }
+}
+
+impl From<Storage> for String {
+    fn from(storage: Storage) -> Self {
+        storage.to_string()
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Full,
+    Compact,
+    Pretty,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
     pub address: String,
-    /// Server port 0-65535
-    #[structopt(short, long, default_value = "3000")]
     pub port: u16,
-    #[structopt(short, long, default_value = "10")]
-    pub num_clients: u16
+    pub num_clients: u16,
+    pub storage: Storage,
+    pub fallback_memory: bool,
+    pub min_table_id: u32,
+    pub max_table_id: u32,
+    pub min_prep_minutes: u32,
+    pub max_prep_minutes: u32,
+    pub prep_seed: Option<u64>,
+    pub menu_catalog: Option<String>,
+    pub skip_migrations: bool,
+    pub strict_menu: bool,
+    pub tax_percent: String,
+    pub idempotency_ttl_minutes: u32,
+    pub purge_after_days: Option<u32>,
+    pub purge_interval_minutes: u32,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub max_body_bytes: u64,
+    pub header_read_timeout_seconds: u64,
+    pub drain_timeout_seconds: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_string(),
+            port: 3000,
+            num_clients: 10,
+            storage: Storage::SqlitePool("restaurant.sqlite".to_string()),
+            fallback_memory: false,
+            min_table_id: 1,
+            max_table_id: 100,
+            min_prep_minutes: 5,
+            max_prep_minutes: 15,
+            prep_seed: None,
+            menu_catalog: None,
+            skip_migrations: false,
+            strict_menu: false,
+            tax_percent: "0".to_string(),
+            idempotency_ttl_minutes: 1440,
+            purge_after_days: None,
+            purge_interval_minutes: 60,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Full,
+            max_body_bytes: 1024 * 1024,
+            header_read_timeout_seconds: 30,
+            drain_timeout_seconds: 10,
+        }
+    }
+}
+
+impl Config {
+    // The defaults overridden by the file given with --config, then by the options.
+    // clap already gives flags precedence over the environment variables.
+    pub fn load(opt: &Opt) -> Result<Self, String> {
+        let config = match &opt.config {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None => Self::default(),
+        };
+        Ok(config.overridden_by(opt))
+    }
+
+    // Keys missing from the file keep their default
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read config {}: {}", path.display(), err))?;
+        toml::from_str(&content)
+            .map_err(|err| format!("Cannot parse config {}: {}", path.display(), err))
+    }
+
+    pub fn overridden_by(mut self, opt: &Opt) -> Self {
+        fn set<T: Clone>(value: &mut T, option: &Option<T>) {
+            if let Some(option) = option {
+                *value = option.clone();
+            }
+        }
+        fn set_optional<T: Clone>(value: &mut Option<T>, option: &Option<T>) {
+            if option.is_some() {
+                *value = option.clone();
+            }
+        }
+
+        set(&mut self.address, &opt.address);
+        set(&mut self.port, &opt.port);
+        set(&mut self.num_clients, &opt.num_clients);
+        set(&mut self.storage, &opt.storage);
+        set(&mut self.fallback_memory, &opt.fallback_memory);
+        set(&mut self.min_table_id, &opt.min_table_id);
+        set(&mut self.max_table_id, &opt.max_table_id);
+        set(&mut self.min_prep_minutes, &opt.min_prep_minutes);
+        set(&mut self.max_prep_minutes, &opt.max_prep_minutes);
+        set_optional(&mut self.prep_seed, &opt.prep_seed);
+        set_optional(&mut self.menu_catalog, &opt.menu_catalog);
+        set(&mut self.skip_migrations, &opt.skip_migrations);
+        set(&mut self.strict_menu, &opt.strict_menu);
+        set(&mut self.tax_percent, &opt.tax_percent);
+        set(
+            &mut self.idempotency_ttl_minutes,
+            &opt.idempotency_ttl_minutes,
+        );
+        set_optional(&mut self.purge_after_days, &opt.purge_after_days);
+        set(
+            &mut self.purge_interval_minutes,
+            &opt.purge_interval_minutes,
+        );
+        set(&mut self.log_level, &opt.log_level);
+        set(&mut self.log_format, &opt.log_format);
+        set(&mut self.max_body_bytes, &opt.max_body_bytes);
+        set(
+            &mut self.header_read_timeout_seconds,
+            &opt.header_read_timeout_seconds,
+        );
+        set(&mut self.drain_timeout_seconds, &opt.drain_timeout_seconds);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn opt(args: &[&str]) -> Opt {
+        Opt::try_parse_from(["restaurant-api"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn the_file_overrides_the_defaults_and_the_flags_override_the_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "port = 4000\nstorage = \"memory\"\nmax_table_id = 20\nstrict_menu = true"
+        )
+        .unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let config = Config::load(&opt(&["--config", path, "--port", "5000"])).unwrap();
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.storage, Storage::Memory);
+        assert_eq!(config.max_table_id, 20);
+        assert!(config.strict_menu);
+        assert_eq!(config.min_table_id, 1);
+
+        let config = Config::load(&opt(&["--config", path, "--strict-menu=false"])).unwrap();
+        assert!(!config.strict_menu);
+    }
+
+    #[test]
+    fn an_unknown_key_in_the_file_is_rejected() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "prot = 4000").unwrap();
+
+        assert!(Config::from_file(file.path()).is_err());
+        assert!(Config::from_file(Path::new("/does/not/exist.toml")).is_err());
+    }
+
+    #[test]
+    fn the_printed_config_reads_back_the_same() {
+        let config = Config::load(&opt(&[
+            "--storage",
+            "sqlite:/tmp/a.sqlite",
+            "--log-format",
+            "compact",
+        ]))
+        .unwrap();
+        let printed = toml::to_string(&config).unwrap();
+
+        assert_eq!(toml::from_str::<Config>(&printed).unwrap(), config);
+    }
+
+    #[test]
+    fn environment_variables_sit_between_the_file_and_the_flags() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "header_read_timeout_seconds = 1").unwrap();
+        let path = file.path().to_str().unwrap();
+        // No other test reads this setting
+        std::env::set_var("RESTAURANT_HEADER_READ_TIMEOUT_SECONDS", "2");
+
+        let config = Config::load(&opt(&["--config", path])).unwrap();
+        assert_eq!(config.header_read_timeout_seconds, 2);
+        let config = Config::load(&opt(&[
+            "--config",
+            path,
+            "--header-read-timeout-seconds",
+            "3",
+        ]))
+        .unwrap();
+        assert_eq!(config.header_read_timeout_seconds, 3);
+
+        std::env::remove_var("RESTAURANT_HEADER_READ_TIMEOUT_SECONDS");
+    }
+
+    #[test]
+    fn storages_round_trip_through_their_names() {
+        for name in [
+            "memory",
+            "sqlite:restaurant.sqlite",
+            "sqlite-pool:/var/lib/r.sqlite",
+        ] {
+            assert_eq!(name.parse::<Storage>().unwrap().to_string(), name);
+        }
+        assert_eq!(
+            "sqlite:c:/db.sqlite".parse::<Storage>(),
+            Ok(Storage::Sqlite("c:/db.sqlite".to_string()))
+        );
+        for name in ["", "sqlite", "sqlite:", "memory:x", "postgres:db"] {
+            assert!(name.parse::<Storage>().is_err());
+        }
+    }
 }
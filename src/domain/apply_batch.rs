@@ -0,0 +1,290 @@
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::domain::events::{ItemEventKind, ItemEvents};
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{IdType, Item, ItemId, PrepTime, TableId};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{BatchError, BatchOp, DeleteError, InsertError, Repository, UpdateError};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call apply_batch(): every item is created, updated or
+// deleted, or none of them is
+
+pub struct BatchRequest {
+    pub table_id: TableId<IdType>,
+    pub ops: Vec<BatchOp>,
+}
+
+pub struct BatchResponse {
+    // The created or updated item of every operation, None for deletes
+    pub items: Vec<Option<Item>>,
+}
+
+// Every error but Unknown names the item that made the batch fail
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    Conflict(ItemId<IdType>),
+    UnknownItemId(ItemId<IdType>),
+    // Holds the stored item, which is newer than the updated one
+    VersionConflict(ItemId<IdType>, Item),
+}
+
+impl BatchRequest {
+    // Creates items estimating their prep time, like create_item does
+    pub fn inserts(
+        table_id: TableId<IdType>,
+        items: Vec<Item>,
+        estimator: &dyn PrepTimeEstimator,
+    ) -> Self {
+        let ops = items
+            .into_iter()
+            .map(|item| BatchOp::Insert(with_prep_time(estimator, item)))
+            .collect();
+        Self { table_id, ops }
+    }
+
+    // Updates items re-estimating their prep time, like update_item does
+    pub fn updates(
+        table_id: TableId<IdType>,
+        items: Vec<Item>,
+        estimator: &dyn PrepTimeEstimator,
+    ) -> Self {
+        let ops = items
+            .into_iter()
+            .map(|item| BatchOp::Update(with_prep_time(estimator, item)))
+            .collect();
+        Self { table_id, ops }
+    }
+
+    pub fn deletes(table_id: TableId<IdType>, item_ids: Vec<ItemId<IdType>>) -> Self {
+        let ops = item_ids.into_iter().map(BatchOp::Delete).collect();
+        Self { table_id, ops }
+    }
+}
+
+fn with_prep_time(estimator: &dyn PrepTimeEstimator, mut item: Item) -> Item {
+    item.prep_time = PrepTime::new(Utc::now(), estimator.estimate(&item));
+    item
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    events: ItemEvents,
+    req: BatchRequest,
+) -> Result<BatchResponse, Error> {
+    let changes: Vec<(ItemEventKind, ItemId<IdType>)> = req
+        .ops
+        .iter()
+        .map(|op| match op {
+            BatchOp::Insert(item) => (ItemEventKind::Created, item.id),
+            BatchOp::Update(item) => (ItemEventKind::Updated, item.id),
+            BatchOp::Delete(item_id) => (ItemEventKind::Deleted, *item_id),
+        })
+        .collect();
+    match AsyncRepository::new(repo)
+        .apply_batch(req.table_id, req.ops)
+        .await
+    {
+        Ok(items) => {
+            for ((kind, item_id), item) in changes.into_iter().zip(&items) {
+                match (kind, item) {
+                    (ItemEventKind::Created, Some(item)) => events.created(req.table_id, item),
+                    (ItemEventKind::Updated, Some(item)) => events.updated(req.table_id, item),
+                    _ => events.deleted(req.table_id, item_id),
+                }
+            }
+            Ok(BatchResponse { items })
+        }
+        Err(BatchError::Insert(id, InsertError::Conflict)) => Err(Error::Conflict(id)),
+        Err(BatchError::Update(_, UpdateError::UnknownTableId))
+        | Err(BatchError::Delete(_, DeleteError::UnknownTableId)) => Err(Error::UnknowTableId),
+        Err(BatchError::Update(id, UpdateError::UnknownItemId))
+        | Err(BatchError::Delete(id, DeleteError::UnknownItemId)) => Err(Error::UnknownItemId(id)),
+        Err(BatchError::Update(id, UpdateError::VersionConflict(item))) => {
+            Err(Error::VersionConflict(id, item))
+        }
+        // deletes of a batch are not bound to a version
+        Err(BatchError::Delete(_, DeleteError::VersionConflict))
+        | Err(BatchError::Insert(_, InsertError::Unknown))
+        | Err(BatchError::Update(_, UpdateError::Unknown))
+        | Err(BatchError::Delete(_, DeleteError::Unknown))
+        | Err(BatchError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::prep_time::FixedEstimator,
+        domain::types::{ItemFilter, ItemName, ItemNotes, ItemQuantity, ItemSort, ItemVersion},
+        repository::inmemory::InMemoryRepository,
+        repository::sqlite::SqliteRepository,
+    };
+
+    fn item(id: IdType) -> Item {
+        Item::new(
+            ItemId::from_int(id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::default(),
+        )
+    }
+
+    fn active_ids(repo: &Arc<dyn Repository>) -> Vec<ItemId<IdType>> {
+        match repo.fetch_all(
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        ) {
+            Ok(items) => items.iter().map(|it| it.id).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn repos() -> Vec<Arc<dyn Repository>> {
+        vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn it_should_apply_every_operation_of_a_batch() {
+        for repo in repos() {
+            let req = BatchRequest::inserts(
+                TableId::from_int(1),
+                vec![item(1), item(2)],
+                &FixedEstimator(7),
+            );
+            match execute(repo.clone(), ItemEvents::new(), req).await {
+                Ok(res) => {
+                    assert_eq!(res.items.len(), 2);
+                    assert_eq!(res.items[0].as_ref().unwrap().prep_time.prep_minutes, 7);
+                }
+                Err(_) => unreachable!(),
+            }
+
+            let req = BatchRequest {
+                table_id: TableId::from_int(1),
+                ops: vec![
+                    BatchOp::Update(item(1)),
+                    BatchOp::Delete(ItemId::from_int(2)),
+                ],
+            };
+            match execute(repo.clone(), ItemEvents::new(), req).await {
+                Ok(res) => {
+                    let version = &res.items[0].as_ref().unwrap().version;
+                    assert_eq!(*version, ItemVersion::from_int(2));
+                    assert!(res.items[1].is_none());
+                }
+                Err(_) => unreachable!(),
+            }
+            assert_eq!(active_ids(&repo), vec![ItemId::from_int(1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_apply_nothing_when_one_operation_fails() {
+        for repo in repos() {
+            let req =
+                BatchRequest::inserts(TableId::from_int(1), vec![item(1)], &FixedEstimator(7));
+            execute(repo.clone(), ItemEvents::new(), req).await.ok();
+
+            let req = BatchRequest {
+                table_id: TableId::from_int(1),
+                ops: vec![
+                    BatchOp::Insert(item(2)),
+                    BatchOp::Delete(ItemId::from_int(1)),
+                    BatchOp::Insert(item(1)),
+                    BatchOp::Insert(item(1)),
+                ],
+            };
+            match execute(repo.clone(), ItemEvents::new(), req).await {
+                Err(Error::Conflict(id)) => assert_eq!(id, ItemId::from_int(1)),
+                _ => unreachable!(),
+            }
+            assert_eq!(active_ids(&repo), vec![ItemId::from_int(1)]);
+
+            let req = BatchRequest::deletes(
+                TableId::from_int(1),
+                vec![ItemId::from_int(1), ItemId::from_int(3)],
+            );
+            match execute(repo.clone(), ItemEvents::new(), req).await {
+                Err(Error::UnknownItemId(id)) => assert_eq!(id, ItemId::from_int(3)),
+                _ => unreachable!(),
+            }
+            assert_eq!(active_ids(&repo), vec![ItemId::from_int(1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_stored_item_on_a_version_conflict() {
+        for repo in repos() {
+            let req =
+                BatchRequest::inserts(TableId::from_int(1), vec![item(1)], &FixedEstimator(7));
+            execute(repo.clone(), ItemEvents::new(), req).await.ok();
+            let req =
+                BatchRequest::updates(TableId::from_int(1), vec![item(1)], &FixedEstimator(7));
+            execute(repo.clone(), ItemEvents::new(), req).await.ok();
+
+            // item(1) is still in version 1 while the stored one is in version 2
+            let req =
+                BatchRequest::updates(TableId::from_int(1), vec![item(1)], &FixedEstimator(9));
+            match execute(repo.clone(), ItemEvents::new(), req).await {
+                Err(Error::VersionConflict(id, stored)) => {
+                    assert_eq!(id, ItemId::from_int(1));
+                    assert_eq!(stored.version, ItemVersion::from_int(2));
+                    assert_eq!(stored.prep_time.prep_minutes, 7);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_publish_an_event_per_operation_only_when_applied() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let events = ItemEvents::new();
+        let mut subscription = events.subscribe(None, None);
+
+        let req = BatchRequest::inserts(TableId::from_int(1), vec![item(1)], &FixedEstimator(7));
+        execute(repo.clone(), events.clone(), req).await.ok();
+        let req = BatchRequest {
+            table_id: TableId::from_int(1),
+            ops: vec![
+                BatchOp::Update(item(1)),
+                BatchOp::Delete(ItemId::from_int(1)),
+            ],
+        };
+        execute(repo.clone(), events.clone(), req).await.ok();
+        // Nothing is published for a failed batch
+        let req = BatchRequest::deletes(TableId::from_int(1), vec![ItemId::from_int(1)]);
+        execute(repo.clone(), events.clone(), req).await.ok();
+        events.deleted(TableId::from_int(2), ItemId::from_int(9));
+
+        let mut kinds = vec![];
+        while let Some(event) = subscription.next().await {
+            kinds.push((event.event, IdType::from(event.table_id)));
+            if event.table_id == TableId::from_int(2) {
+                break;
+            }
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                (ItemEventKind::Created, 1),
+                (ItemEventKind::Updated, 1),
+                (ItemEventKind::Deleted, 1),
+                (ItemEventKind::Deleted, 2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use crate::domain::events::ItemEvents;
+use crate::domain::read_bill::{self, ReadBillRequest};
+use crate::domain::types::{Bill, IdType, ItemName, ReceiptId, TableId, TaxRate};
+use crate::menu::MenuRepository;
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{CheckoutError, Repository};
+use serde::Serialize;
+
+// Here can be found request and response structs and function execute() to
+// bill a table and close it out with Repository call checkout()
+
+pub struct CheckoutRequest {
+    pub table_id: TableId<IdType>,
+    pub tax_rate: TaxRate,
+}
+
+#[derive(Serialize)]
+pub struct CheckoutResponse {
+    pub receipt_id: ReceiptId,
+    pub bill: Bill,
+}
+
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    UnpricedItems(Vec<ItemName>),
+    // The items of the table changed while the bill was computed
+    Conflict,
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    menu: Arc<dyn MenuRepository>,
+    events: ItemEvents,
+    req: CheckoutRequest,
+) -> Result<CheckoutResponse, Error> {
+    let bill = match read_bill::execute(
+        repo.clone(),
+        menu,
+        ReadBillRequest {
+            table_id: req.table_id,
+            tax_rate: req.tax_rate,
+        },
+    )
+    .await
+    {
+        Ok(res) => res.bill,
+        Err(read_bill::Error::UnknowTableId) => return Err(Error::UnknowTableId),
+        Err(read_bill::Error::UnpricedItems(names)) => return Err(Error::UnpricedItems(names)),
+        Err(read_bill::Error::Unknown) => return Err(Error::Unknown),
+    };
+
+    match AsyncRepository::new(repo).checkout(bill.clone()).await {
+        Ok(receipt_id) => {
+            for line in &bill.lines {
+                events.deleted(req.table_id, line.item_id);
+            }
+            Ok(CheckoutResponse { receipt_id, bill })
+        }
+        Err(CheckoutError::UnknownTableId) => Err(Error::UnknowTableId),
+        Err(CheckoutError::Conflict) => Err(Error::Conflict),
+        Err(CheckoutError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{
+        ItemFilter, ItemId, ItemNotes, ItemQuantity, ItemSort, ItemVersion, PrepTime,
+    };
+    use crate::menu::inmemory::InMemoryMenuRepository;
+    use crate::menu::MenuItem;
+    use crate::repository::inmemory::InMemoryRepository;
+
+    fn request() -> CheckoutRequest {
+        CheckoutRequest {
+            table_id: TableId::from_int(1),
+            tax_rate: TaxRate::try_from("10".to_string()).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_record_the_bill_and_clear_the_table() {
+        let repo = Arc::new(InMemoryRepository::new());
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::from_int(2),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        let menu = InMemoryMenuRepository::new();
+        menu.insert(MenuItem::new(1, "Some pizza", 950)).ok();
+        let menu: Arc<dyn MenuRepository> = Arc::new(menu);
+
+        match execute(repo.clone(), menu.clone(), ItemEvents::new(), request()).await {
+            Ok(res) => {
+                assert_eq!(res.receipt_id, ReceiptId::from(1));
+                assert_eq!(res.bill.total, 2090);
+            }
+            Err(_) => unreachable!(),
+        }
+        match repo.fetch_all(
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        ) {
+            Ok(items) => assert!(items.is_empty()),
+            Err(_) => unreachable!(),
+        }
+
+        match execute(repo, menu, ItemEvents::new(), request()).await {
+            Err(Error::UnknowTableId) => {}
+            _ => unreachable!(),
+        }
+    }
+}
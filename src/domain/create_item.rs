@@ -1,9 +1,13 @@
+use chrono::Utc;
 use std::sync::Arc;
 
-use crate::domain::types::{IdType, Item, TableId};
+use crate::domain::events::ItemEvents;
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{IdType, Item, PrepTime, TableId};
+use crate::repository::async_repository::AsyncRepository;
 use crate::repository::{InsertError, Repository};
 
-// Here can be found request and response structs and function execute() to 
+// Here can be found request and response structs and function execute() to
 // perform Repository call insert()
 
 pub struct CreateItemRequest {
@@ -20,23 +24,31 @@ pub enum Error {
     Unknown,
 }
 
-pub fn execute(
+pub async fn execute(
     repo: Arc<dyn Repository>,
+    estimator: Arc<dyn PrepTimeEstimator>,
+    events: ItemEvents,
     req: CreateItemRequest,
 ) -> Result<CreateItemResponse, Error> {
     let mut cloned_it = req.item.clone();
-    cloned_it.gen_time_to_prepare();
-    match repo.insert(
-        req.table_id,
-        cloned_it.id,
-        cloned_it.name,
-        cloned_it.notes,
-        cloned_it.quantity,
-        cloned_it.deleted,
-        cloned_it.version,
-        cloned_it.time_to_prepare,
-    ) {
-        Ok(item) => Ok(CreateItemResponse { item }),
+    cloned_it.prep_time = PrepTime::new(Utc::now(), estimator.estimate(&cloned_it));
+    match AsyncRepository::new(repo)
+        .insert(
+            req.table_id,
+            cloned_it.id,
+            cloned_it.name,
+            cloned_it.notes,
+            cloned_it.quantity,
+            cloned_it.deleted,
+            cloned_it.version,
+            cloned_it.prep_time,
+        )
+        .await
+    {
+        Ok(item) => {
+            events.created(req.table_id, &item);
+            Ok(CreateItemResponse { item })
+        }
         Err(InsertError::Conflict) => Err(Error::Conflict),
         Err(InsertError::Unknown) => Err(Error::Unknown),
     }
@@ -46,14 +58,15 @@ pub fn execute(
 mod tests {
     use super::*;
     use crate::{
+        domain::prep_time::FixedEstimator,
         domain::types::{
             ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, QuantityType, VersionType,
         },
         repository::inmemory::InMemoryRepository,
     };
 
-    #[test]
-    fn it_should_return_an_ok_when_request_is_valid() {
+    #[tokio::test]
+    async fn it_should_return_an_ok_when_request_is_valid() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = CreateItemRequest::new(
             TableId::id_one(),
@@ -63,10 +76,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Ok(_) => {}
@@ -74,8 +87,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_a_conflict_error_when_item_already_exists() {
+    #[tokio::test]
+    async fn it_should_return_a_conflict_error_when_item_already_exists() {
         let repo = Arc::new(InMemoryRepository::new());
         let same_table_id = 1;
         let same_item_id = 1;
@@ -87,7 +100,7 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
@@ -99,10 +112,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Err(Error::Conflict) => {}
@@ -110,8 +123,8 @@ mod tests {
         }
     }
 
-    #[test]
-    fn it_should_return_ok_when_deleted_item_exists() {
+    #[tokio::test]
+    async fn it_should_return_ok_when_deleted_item_exists() {
         let repo = Arc::new(InMemoryRepository::new());
         let table_id = 1;
         let item_id = 1;
@@ -123,7 +136,7 @@ mod tests {
             ItemQuantity::one(),
             true,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
@@ -135,18 +148,18 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Ok(_) => {}
             _ => unreachable!(),
         }
     }
-    #[test]
-    fn it_should_return_an_unknown_error_when_an_unexpected_error_happens() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_error_when_an_unexpected_error_happens() {
         let repo = Arc::new(InMemoryRepository::new().with_error());
         let req = CreateItemRequest::new(
             TableId::from_int(1),
@@ -156,10 +169,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Err(Error::Unknown) => {}
@@ -167,8 +180,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_an_item() {
+    #[tokio::test]
+    async fn it_should_return_an_item() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = CreateItemRequest::new(
             TableId::from_int(1),
@@ -178,10 +191,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            String::from("2023/12/12"),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Ok(res) => {
@@ -189,14 +202,16 @@ mod tests {
                 assert_eq!(res.item.name, ItemName::pizza());
                 assert_eq!(res.item.notes, ItemNotes::some_notes());
                 assert_eq!(res.item.quantity, ItemQuantity::one());
-                assert_eq!(res.item.deleted, false);
+                assert!(!res.item.deleted);
                 assert_eq!(res.item.version, ItemVersion::ver_one());
+                assert_eq!(res.item.prep_time.prep_minutes, 10);
             }
             _ => unreachable!(),
         };
     }
 
     impl CreateItemRequest {
+        #[allow(clippy::too_many_arguments)]
         fn new(
             table_id: TableId<IdType>,
             item_id: ItemId<IdType>,
@@ -205,19 +220,19 @@ mod tests {
             item_quantity: ItemQuantity<QuantityType>,
             item_deleted: bool,
             item_version: ItemVersion<VersionType>,
-            item_time_to_prepare: String,
+            item_prep_time: PrepTime,
         ) -> Self {
             Self {
-                table_id: table_id,
-                item: Item {
-                    id: item_id,
-                    name: item_name,
-                    notes: item_notes,
-                    quantity: item_quantity,
-                    deleted: item_deleted,
-                    version: item_version,
-                    time_to_prepare: item_time_to_prepare,
-                },
+                table_id,
+                item: Item::new(
+                    item_id,
+                    item_name,
+                    item_notes,
+                    item_quantity,
+                    item_deleted,
+                    item_version,
+                    item_prep_time,
+                ),
             }
         }
     }
@@ -1,15 +1,19 @@
 use super::types::ItemId;
-use crate::domain::types::{IdType, TableId};
-use crate::repository::{DeleteError, Repository};
+use crate::domain::events::ItemEvents;
+use crate::domain::types::{IdType, TableId, VersionMatch};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{DeleteError, FetchOneError, Repository};
 use serde::Serialize;
 use std::sync::Arc;
 
 pub struct DeleteOneRequest {
     pub table_id: TableId<IdType>,
     pub item_id: ItemId<IdType>,
+    // The If-Match condition the current version of the item must meet
+    pub if_match: Option<VersionMatch>,
 }
 
-// Here can be found request and response structs and function execute() to 
+// Here can be found request and response structs and function execute() to
 // perform Repository call delete()
 
 #[derive(Serialize)]
@@ -19,14 +23,33 @@ pub enum Error {
     Unknown,
     UnknowTableId,
     UnknownItemId,
+    PreconditionFailed,
 }
 
-pub fn execute(
+pub async fn execute(
     repo: Arc<dyn Repository>,
+    events: ItemEvents,
     req: DeleteOneRequest,
 ) -> Result<DeleteOneResponse, Error> {
-    match repo.delete(req.table_id, req.item_id) {
-        Ok(_) => Ok(DeleteOneResponse {}),
+    let repo = AsyncRepository::new(repo);
+    // The item is deleted only if it is still in the version that met the condition
+    let version = match req.if_match {
+        Some(if_match) => match repo.fetch_one(req.table_id, req.item_id).await {
+            Ok(item) if if_match.matches(&item.version) => Some(item.version),
+            Ok(_) => return Err(Error::PreconditionFailed),
+            Err(FetchOneError::UnknownItemId) => return Err(Error::UnknownItemId),
+            Err(FetchOneError::UnknownTableId) => return Err(Error::UnknowTableId),
+            Err(FetchOneError::Unknown) => return Err(Error::Unknown),
+        },
+        None => None,
+    };
+
+    match repo.delete(req.table_id, req.item_id, version).await {
+        Ok(_) => {
+            events.deleted(req.table_id, req.item_id);
+            Ok(DeleteOneResponse {})
+        }
+        Err(DeleteError::VersionConflict) => Err(Error::PreconditionFailed),
         Err(DeleteError::UnknownItemId) => Err(Error::UnknownItemId),
         Err(DeleteError::UnknownTableId) => Err(Error::UnknowTableId),
         Err(DeleteError::Unknown) => Err(Error::Unknown),
@@ -37,12 +60,12 @@ pub fn execute(
 mod tests {
     use super::*;
     use crate::{
-        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion},
+        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime},
         repository::inmemory::InMemoryRepository,
     };
 
-    #[test]
-    fn it_should_return_an_ok_when_request_is_valid() {
+    #[tokio::test]
+    async fn it_should_return_an_ok_when_request_is_valid() {
         let repo = Arc::new(InMemoryRepository::new());
         let table_id = TableId::from_int(1);
         let item_id = ItemId::from_int(1);
@@ -54,13 +77,13 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::from_int(1),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = DeleteOneRequest::new(table_id, item_id);
 
-        let res = execute(repo, req);
+        let res = execute(repo, ItemEvents::new(), req).await;
 
         match res {
             Ok(_) => {}
@@ -68,11 +91,11 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = DeleteOneRequest::new(TableId::from_int(1), ItemId::from_int(1));
-        let res = execute(repo, req);
+        let res = execute(repo, ItemEvents::new(), req).await;
 
         match res {
             Err(Error::UnknowTableId) => {}
@@ -80,8 +103,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_item_id_error_when_item_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_item_id_error_when_item_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -91,12 +114,12 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = DeleteOneRequest::new(TableId::from_int(1), ItemId::from_int(2));
-        let res = execute(repo, req);
+        let res = execute(repo, ItemEvents::new(), req).await;
 
         match res {
             Err(Error::UnknownItemId) => {}
@@ -104,8 +127,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_not_found_when_item_deleted() {
+    #[tokio::test]
+    async fn it_should_return_not_found_when_item_deleted() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -115,12 +138,12 @@ mod tests {
             ItemQuantity::one(),
             true,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = DeleteOneRequest::new(TableId::from_int(1), ItemId::from_int(1));
-        let res = execute(repo, req);
+        let res = execute(repo, ItemEvents::new(), req).await;
 
         match res {
             Err(Error::UnknownItemId) => {}
@@ -128,9 +151,43 @@ mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn it_should_delete_only_when_the_version_matches() {
+        let repo = Arc::new(InMemoryRepository::new());
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::from_int(2),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+
+        let mut req = DeleteOneRequest::new(TableId::from_int(1), ItemId::from_int(1));
+        req.if_match = Some(VersionMatch::Versions(vec![ItemVersion::from_int(1)]));
+        match execute(repo.clone(), ItemEvents::new(), req).await {
+            Err(Error::PreconditionFailed) => {}
+            _ => unreachable!(),
+        };
+
+        let mut req = DeleteOneRequest::new(TableId::from_int(1), ItemId::from_int(1));
+        req.if_match = Some(VersionMatch::Versions(vec![ItemVersion::from_int(2)]));
+        match execute(repo, ItemEvents::new(), req).await {
+            Ok(_) => {}
+            _ => unreachable!(),
+        };
+    }
+
     impl DeleteOneRequest {
         fn new(table_id: TableId<IdType>, item_id: ItemId<IdType>) -> Self {
-            Self { table_id, item_id }
+            Self {
+                table_id,
+                item_id,
+                if_match: None,
+            }
         }
     }
 }
@@ -0,0 +1,309 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::domain::types::{IdType, Item, ItemId, TableId};
+
+// Here can be found the item events the domain execute() functions publish
+// after a change, and the channel that hands them to the subscribed streams
+
+// Events kept to replay to a client resuming after a reconnect
+const HISTORY: usize = 1000;
+// Events a slow subscriber may fall behind before its stream is closed
+const CAPACITY: usize = 256;
+
+pub type EventId = u64;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ItemEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemEventKind::Created => "created",
+            ItemEventKind::Updated => "updated",
+            ItemEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+// One change of an item, item is None for deletes
+#[derive(Debug, Serialize, Clone)]
+pub struct ItemEvent {
+    pub id: EventId,
+    pub event: ItemEventKind,
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<Item>,
+}
+
+struct Log {
+    last_id: EventId,
+    recent: VecDeque<ItemEvent>,
+}
+
+// Shared by all requests, cloning it shares the same channel. Ids grow by one
+// with every event from the time the server started in microseconds, so a run
+// doesn't reuse the ids of an earlier one.
+#[derive(Clone)]
+pub struct ItemEvents {
+    sender: broadcast::Sender<ItemEvent>,
+    log: Arc<Mutex<Log>>,
+}
+
+impl Default for ItemEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ItemEvents {
+    pub fn new() -> Self {
+        Self::counting_from(Utc::now().timestamp_micros().max(0) as EventId)
+    }
+
+    // The first event gets the id after last_id
+    pub(crate) fn counting_from(last_id: EventId) -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self {
+            sender,
+            log: Arc::new(Mutex::new(Log {
+                last_id,
+                recent: VecDeque::with_capacity(HISTORY),
+            })),
+        }
+    }
+
+    fn lock_log(&self) -> MutexGuard<'_, Log> {
+        // The log is only appended to, a panic while holding it leaves nothing broken
+        self.log
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn publish(
+        &self,
+        event: ItemEventKind,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item: Option<Item>,
+    ) {
+        let mut log = self.lock_log();
+        log.last_id += 1;
+        let event = ItemEvent {
+            id: log.last_id,
+            event,
+            table_id,
+            item_id,
+            item,
+        };
+        if log.recent.len() == HISTORY {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(event.clone());
+        // Nobody listening is not an error
+        self.sender.send(event).ok();
+    }
+
+    pub fn created(&self, table_id: TableId<IdType>, item: &Item) {
+        self.publish(
+            ItemEventKind::Created,
+            table_id,
+            item.id,
+            Some(item.clone()),
+        );
+    }
+
+    pub fn updated(&self, table_id: TableId<IdType>, item: &Item) {
+        self.publish(
+            ItemEventKind::Updated,
+            table_id,
+            item.id,
+            Some(item.clone()),
+        );
+    }
+
+    pub fn deleted(&self, table_id: TableId<IdType>, item_id: ItemId<IdType>) {
+        self.publish(ItemEventKind::Deleted, table_id, item_id, None);
+    }
+
+    // Events of one table or of all tables, starting after last_event_id when
+    // it is still in the history, or with the next published event otherwise.
+    // A last_event_id older than the history or from another run can't be
+    // resumed from, the subscription then asks for a reset.
+    pub fn subscribe(
+        &self,
+        table_id: Option<TableId<IdType>>,
+        last_event_id: Option<EventId>,
+    ) -> Subscription {
+        // Holding the log lock, no event can be both replayed and received
+        let log = self.lock_log();
+        // Ids are consecutive, the history holds every event after the oldest one
+        let oldest = log.last_id - log.recent.len() as EventId;
+        let (replay, reset) = match last_event_id {
+            Some(last_event_id) if (oldest..=log.last_id).contains(&last_event_id) => (
+                log.recent
+                    .iter()
+                    .filter(|event| event.id > last_event_id)
+                    .cloned()
+                    .collect(),
+                None,
+            ),
+            Some(_) => (VecDeque::new(), Some(log.last_id)),
+            None => (VecDeque::new(), None),
+        };
+        Subscription {
+            table_id,
+            replay,
+            reset,
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+pub struct Subscription {
+    table_id: Option<TableId<IdType>>,
+    replay: VecDeque<ItemEvent>,
+    reset: Option<EventId>,
+    receiver: broadcast::Receiver<ItemEvent>,
+}
+
+impl Subscription {
+    // The last published id when the events missed since last_event_id are lost.
+    // The client has to reload the items, then resume from this id.
+    pub fn reset(&self) -> Option<EventId> {
+        self.reset
+    }
+
+    // None once the subscriber fell too far behind, it can resume from the last
+    // event it got
+    pub async fn next(&mut self) -> Option<ItemEvent> {
+        loop {
+            let event = match self.replay.pop_front() {
+                Some(event) => event,
+                None => match self.receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => return None,
+                },
+            };
+            if self
+                .table_id
+                .is_none_or(|table_id| event.table_id == table_id)
+            {
+                return Some(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime};
+
+    fn item(id: IdType) -> Item {
+        Item::new(
+            ItemId::from_int(id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_the_events_of_a_table() {
+        let events = ItemEvents::counting_from(0);
+        let mut table = events.subscribe(Some(TableId::from_int(1)), None);
+        let mut all = events.subscribe(None, None);
+
+        events.created(TableId::from_int(2), &item(1));
+        events.created(TableId::from_int(1), &item(1));
+        events.deleted(TableId::from_int(1), ItemId::from_int(1));
+
+        let event = table.next().await.unwrap();
+        assert_eq!((event.id, event.event), (2, ItemEventKind::Created));
+        let event = table.next().await.unwrap();
+        assert_eq!((event.id, event.event), (3, ItemEventKind::Deleted));
+        assert!(event.item.is_none());
+        assert_eq!(all.next().await.unwrap().id, 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_resume_after_the_last_event_id() {
+        let events = ItemEvents::counting_from(0);
+        for id in 1..=3 {
+            events.created(TableId::from_int(1), &item(id));
+        }
+
+        let mut resumed = events.subscribe(None, Some(1));
+        events.updated(TableId::from_int(1), &item(1));
+
+        let ids: Vec<EventId> = vec![
+            resumed.next().await.unwrap().id,
+            resumed.next().await.unwrap().id,
+            resumed.next().await.unwrap().id,
+        ];
+        assert_eq!(ids, vec![2, 3, 4]);
+        assert_eq!(resumed.reset(), None);
+        assert_eq!(events.subscribe(None, Some(4)).reset(), None);
+    }
+
+    #[tokio::test]
+    async fn it_should_reset_a_last_event_id_older_than_the_history() {
+        let events = ItemEvents::counting_from(0);
+        for id in 1..=HISTORY as IdType + 2 {
+            events.created(TableId::from_int(1), &item(id % 100 + 1));
+        }
+
+        // Event 2 is gone from the history, 3 is the oldest one kept
+        assert_eq!(events.subscribe(None, Some(1)).reset(), Some(1002));
+        let mut resumed = events.subscribe(None, Some(2));
+        assert_eq!(resumed.reset(), None);
+        assert_eq!(resumed.next().await.unwrap().id, 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_reset_a_last_event_id_of_another_run() {
+        let before = ItemEvents::new();
+        before.created(TableId::from_int(1), &item(1));
+        let last_id = before.lock_log().last_id;
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+
+        let restarted = ItemEvents::new();
+        restarted.created(TableId::from_int(1), &item(1));
+        restarted.created(TableId::from_int(1), &item(2));
+        let mut resumed = restarted.subscribe(None, Some(last_id));
+        assert!(resumed.reset().is_some());
+        restarted.deleted(TableId::from_int(1), ItemId::from_int(1));
+        // Only the events after the reset, not a replay
+        assert_eq!(resumed.next().await.unwrap().event, ItemEventKind::Deleted);
+
+        // Nor can an id ahead of this run be resumed from
+        assert!(ItemEvents::counting_from(0)
+            .subscribe(None, Some(last_id))
+            .reset()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn it_should_end_a_lagging_subscription() {
+        let events = ItemEvents::counting_from(0);
+        let mut slow = events.subscribe(None, None);
+        for id in 0..=CAPACITY as IdType {
+            events.created(TableId::from_int(1), &item(id % 100 + 1));
+        }
+
+        assert!(slow.next().await.is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use crate::domain::types::{IdempotencyKey, KeyClaim, StoredResponse};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::Repository;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+
+// Here can be found the policy and functions to claim, complete and release an
+// idempotency key with Repository calls of the same names
+
+// How long a stored response is replayed to retries of the same request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdempotencyPolicy {
+    pub ttl: Duration,
+}
+
+impl Default for IdempotencyPolicy {
+    fn default() -> Self {
+        IdempotencyPolicy {
+            ttl: Duration::hours(24),
+        }
+    }
+}
+
+impl IdempotencyPolicy {
+    pub fn from_minutes(minutes: u32) -> Self {
+        IdempotencyPolicy {
+            ttl: Duration::minutes(minutes as i64),
+        }
+    }
+}
+
+pub struct ClaimRequest {
+    pub key: IdempotencyKey,
+    pub request_hash: String,
+    pub policy: IdempotencyPolicy,
+}
+
+pub enum Error {
+    Unknown,
+}
+
+// Fingerprint of a request, a key reused for a different request is rejected
+pub fn request_hash(method: &str, path_and_query: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(path_and_query.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn claim(repo: Arc<dyn Repository>, req: ClaimRequest) -> Result<KeyClaim, Error> {
+    let now = Utc::now();
+    AsyncRepository::new(repo)
+        .claim_idempotency_key(req.key, req.request_hash, now, now + req.policy.ttl)
+        .await
+        .map_err(|_| Error::Unknown)
+}
+
+pub async fn complete(
+    repo: Arc<dyn Repository>,
+    key: IdempotencyKey,
+    response: StoredResponse,
+) -> Result<(), Error> {
+    AsyncRepository::new(repo)
+        .complete_idempotency_key(key, response)
+        .await
+        .map_err(|_| Error::Unknown)
+}
+
+pub async fn release(repo: Arc<dyn Repository>, key: IdempotencyKey) -> Result<(), Error> {
+    AsyncRepository::new(repo)
+        .release_idempotency_key(key)
+        .await
+        .map_err(|_| Error::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::inmemory::InMemoryRepository;
+
+    fn claim_request(body: &[u8], policy: IdempotencyPolicy) -> ClaimRequest {
+        ClaimRequest {
+            key: IdempotencyKey::try_from("order-1".to_string()).unwrap(),
+            request_hash: request_hash("POST", "/tables/1/items", body),
+            policy,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_replay_completed_keys_until_they_expire() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        let policy = IdempotencyPolicy::default();
+        let key = IdempotencyKey::try_from("order-1".to_string()).unwrap();
+        let response = StoredResponse {
+            status: 201,
+            body: "{}".to_string(),
+        };
+
+        assert!(matches!(
+            claim(repo.clone(), claim_request(b"a", policy)).await,
+            Ok(KeyClaim::Claimed)
+        ));
+        assert!(matches!(
+            claim(repo.clone(), claim_request(b"a", policy)).await,
+            Ok(KeyClaim::InProgress)
+        ));
+        assert!(complete(repo.clone(), key, response.clone()).await.is_ok());
+        match claim(repo.clone(), claim_request(b"a", policy)).await {
+            Ok(KeyClaim::Replay(stored)) => assert_eq!(stored, response),
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            claim(repo.clone(), claim_request(b"b", policy)).await,
+            Ok(KeyClaim::Mismatch)
+        ));
+
+        // A zero ttl expires the record as soon as it is written
+        let expired = IdempotencyPolicy::from_minutes(0);
+        let other = ClaimRequest {
+            key: IdempotencyKey::try_from("order-2".to_string()).unwrap(),
+            ..claim_request(b"a", expired)
+        };
+        assert!(matches!(
+            claim(repo.clone(), other).await,
+            Ok(KeyClaim::Claimed)
+        ));
+        let other = ClaimRequest {
+            key: IdempotencyKey::try_from("order-2".to_string()).unwrap(),
+            ..claim_request(b"a", expired)
+        };
+        assert!(matches!(claim(repo, other).await, Ok(KeyClaim::Claimed)));
+    }
+
+    #[test]
+    fn it_should_hash_method_path_and_body() {
+        let hash = request_hash("POST", "/tables/1/items", b"{}");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, request_hash("POST", "/tables/1/items", b"{}"));
+        assert_ne!(hash, request_hash("PUT", "/tables/1/items", b"{}"));
+        assert_ne!(hash, request_hash("POST", "/tables/2/items", b"{}"));
+    }
+}
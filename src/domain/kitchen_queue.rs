@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::domain::types::QueuedItem;
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{FetchAllError, Repository};
+use chrono::{Duration, Utc};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call fetch_active_across_tables()
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueFilter {
+    All,
+    // Ready time already passed
+    Overdue,
+    // Ready within the duration from now, overdue items included
+    DueWithin(Duration),
+}
+
+pub struct KitchenQueueRequest {
+    pub filter: QueueFilter,
+}
+
+pub struct KitchenQueueResponse {
+    pub items: Vec<QueuedItem>,
+}
+
+pub enum Error {
+    Unknown,
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    req: KitchenQueueRequest,
+) -> Result<KitchenQueueResponse, Error> {
+    let now = Utc::now();
+    let ready_by = match req.filter {
+        QueueFilter::All => None,
+        QueueFilter::Overdue => Some(now),
+        QueueFilter::DueWithin(duration) => Some(now + duration),
+    };
+    match AsyncRepository::new(repo)
+        .fetch_active_across_tables(ready_by)
+        .await
+    {
+        Ok(items) => Ok(KitchenQueueResponse { items }),
+        Err(FetchAllError::UnknownTableId) | Err(FetchAllError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{
+        IdType, ItemId, ItemName, ItemNotes, ItemQuantity, ItemState, ItemVersion, PrepTime,
+        TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+
+    // An item ordered now and ready in the given minutes, negative when overdue
+    fn insert(repo: &Arc<dyn Repository>, table_id: IdType, item_id: IdType, ready_in: i64) {
+        let ordered_at = Utc::now() + Duration::minutes(ready_in - 10);
+        repo.insert(
+            TableId::from_int(table_id),
+            ItemId::from_int(item_id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::new(ordered_at, 10),
+        )
+        .ok();
+    }
+
+    async fn queue(repo: &Arc<dyn Repository>, filter: QueueFilter) -> Vec<(IdType, IdType)> {
+        match execute(repo.clone(), KitchenQueueRequest { filter }).await {
+            Ok(res) => res
+                .items
+                .into_iter()
+                .map(|queued| (IdType::from(queued.table_id), IdType::from(queued.item.id)))
+                .collect(),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_queue_active_items_of_all_tables_by_ready_time() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos {
+            insert(&repo, 1, 1, 20);
+            insert(&repo, 2, 1, -5);
+            insert(&repo, 1, 2, 5);
+            insert(&repo, 3, 1, 1);
+            repo.delete(TableId::from_int(3), ItemId::from_int(1), None)
+                .ok();
+            insert(&repo, 4, 1, 2);
+            for state in [ItemState::Cooking, ItemState::Ready, ItemState::Served] {
+                repo.transition(TableId::from_int(4), ItemId::from_int(1), state, None)
+                    .ok();
+            }
+
+            assert_eq!(
+                queue(&repo, QueueFilter::All).await,
+                vec![(2, 1), (1, 2), (1, 1)]
+            );
+            assert_eq!(queue(&repo, QueueFilter::Overdue).await, vec![(2, 1)]);
+            assert_eq!(
+                queue(&repo, QueueFilter::DueWithin(Duration::minutes(10))).await,
+                vec![(2, 1), (1, 2)]
+            );
+        }
+    }
+}
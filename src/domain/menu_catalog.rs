@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::Deserialize;
+
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{Item, PrepMinutesType, QuantityType};
+
+// Prep time estimates per menu item, loaded from a JSON or TOML file such as
+// { "Margherita": { "base_minutes": 12, "per_unit_minutes": 2 } }
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogEntry {
+    pub base_minutes: PrepMinutesType,
+    // Added for every unit ordered beyond the first one
+    #[serde(default)]
+    pub per_unit_minutes: PrepMinutesType,
+}
+
+impl CatalogEntry {
+    pub fn minutes_for(&self, quantity: QuantityType) -> PrepMinutesType {
+        self.base_minutes.saturating_add(
+            self.per_unit_minutes
+                .saturating_mul(quantity.saturating_sub(1)),
+        )
+    }
+}
+
+// Names are matched case-insensitively
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MenuCatalog {
+    entries: HashMap<String, CatalogEntry>,
+}
+
+impl MenuCatalog {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(format!("Unable to read {}: {}", path.display(), err)),
+        };
+        let entries: HashMap<String, CatalogEntry> =
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => serde_json::from_str(&content).map_err(|err| err.to_string())?,
+                Some("toml") => toml::from_str(&content).map_err(|err| err.to_string())?,
+                _ => {
+                    return Err(format!(
+                        "{} is neither a .json nor a .toml file.",
+                        path.display()
+                    ))
+                }
+            };
+        Ok(Self {
+            entries: entries
+                .into_iter()
+                .map(|(name, entry)| (name.to_lowercase(), entry))
+                .collect(),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CatalogEntry> {
+        self.entries.get(&name.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+// Estimates listed items from the catalog and the others with the fallback
+pub struct CatalogEstimator {
+    path: Option<PathBuf>,
+    catalog: RwLock<MenuCatalog>,
+    fallback: Box<dyn PrepTimeEstimator>,
+}
+
+impl CatalogEstimator {
+    // Without a path every item is estimated by the fallback
+    pub fn try_new(
+        path: Option<PathBuf>,
+        fallback: Box<dyn PrepTimeEstimator>,
+    ) -> Result<Self, String> {
+        let catalog = match &path {
+            Some(path) => MenuCatalog::load(path)?,
+            None => MenuCatalog::default(),
+        };
+        Ok(Self {
+            path,
+            catalog: RwLock::new(catalog),
+            fallback,
+        })
+    }
+
+    // Re-reads the catalog file and returns the number of listed items.
+    // The current catalog is kept when the file can't be loaded.
+    pub fn reload(&self) -> Result<usize, String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Err("No menu catalog file is configured.".to_string()),
+        };
+        let catalog = MenuCatalog::load(path)?;
+        let len = catalog.len();
+        match self.catalog.write() {
+            Ok(mut current) => *current = catalog,
+            Err(poisoned) => *poisoned.into_inner() = catalog,
+        }
+        Ok(len)
+    }
+}
+
+impl PrepTimeEstimator for CatalogEstimator {
+    fn estimate(&self, item: &Item) -> PrepMinutesType {
+        let minutes = match self.catalog.read() {
+            Ok(catalog) => catalog
+                .get(&String::from(item.name.clone()))
+                .map(|entry| entry.minutes_for(QuantityType::from(item.quantity.clone()))),
+            Err(_) => None,
+        };
+        minutes.unwrap_or_else(|| self.fallback.estimate(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::prep_time::FixedEstimator;
+    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime};
+    use std::io::Write;
+
+    fn item(name: &str, quantity: u32) -> Item {
+        Item::new(
+            ItemId::from_int(1),
+            ItemName::from_str(name.to_string()),
+            ItemNotes::some_notes(),
+            ItemQuantity::from_int(quantity),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::default(),
+        )
+    }
+
+    fn write_file(dir: &tempfile::TempDir, name: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn it_should_estimate_listed_items_from_a_json_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            &dir,
+            "menu.json",
+            r#"{ "Margherita": { "base_minutes": 12, "per_unit_minutes": 2 }, "Tiramisu": { "base_minutes": 5 } }"#,
+        );
+        let estimator =
+            CatalogEstimator::try_new(Some(path), Box::new(FixedEstimator(30))).unwrap();
+
+        assert_eq!(estimator.estimate(&item("Margherita", 1)), 12);
+        assert_eq!(estimator.estimate(&item("margherita", 3)), 16);
+        assert_eq!(estimator.estimate(&item("Tiramisu", 4)), 5);
+        assert_eq!(estimator.estimate(&item("Lasagna", 1)), 30);
+    }
+
+    #[test]
+    fn it_should_load_a_toml_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            &dir,
+            "menu.toml",
+            "[\"Pad thai\"]\nbase_minutes = 9\nper_unit_minutes = 1\n",
+        );
+        let catalog = MenuCatalog::load(&path).unwrap();
+
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.get("PAD THAI").unwrap().minutes_for(2), 10);
+    }
+
+    #[test]
+    fn it_should_reject_invalid_catalogs() {
+        let dir = tempfile::tempdir().unwrap();
+        let bad_json = write_file(&dir, "bad.json", r#"{ "Margherita": { "minutes": 12 } }"#);
+        let bad_extension = write_file(&dir, "menu.txt", "");
+
+        assert!(MenuCatalog::load(&bad_json).is_err());
+        assert!(MenuCatalog::load(&bad_extension).is_err());
+        assert!(MenuCatalog::load(&dir.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn it_should_reload_the_catalog_and_keep_it_when_reload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            &dir,
+            "menu.json",
+            r#"{ "Margherita": { "base_minutes": 12 } }"#,
+        );
+        let estimator =
+            CatalogEstimator::try_new(Some(path.clone()), Box::new(FixedEstimator(30))).unwrap();
+
+        write_file(
+            &dir,
+            "menu.json",
+            r#"{ "Margherita": { "base_minutes": 8 }, "Tiramisu": { "base_minutes": 5 } }"#,
+        );
+        assert_eq!(estimator.reload(), Ok(2));
+        assert_eq!(estimator.estimate(&item("Margherita", 1)), 8);
+
+        write_file(&dir, "menu.json", "not json");
+        assert!(estimator.reload().is_err());
+        assert_eq!(estimator.estimate(&item("Margherita", 1)), 8);
+    }
+
+    #[test]
+    fn it_should_not_reload_without_a_catalog_file() {
+        let estimator = CatalogEstimator::try_new(None, Box::new(FixedEstimator(30))).unwrap();
+
+        assert!(estimator.reload().is_err());
+        assert_eq!(estimator.estimate(&item("Margherita", 1)), 30);
+    }
+}
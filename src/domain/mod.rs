@@ -1,6 +1,20 @@
+pub mod apply_batch;
+pub mod checkout;
 pub mod create_item;
 pub mod delete_item;
+pub mod events;
+pub mod idempotency;
+pub mod kitchen_queue;
+pub mod menu_catalog;
+pub mod patch_item;
+pub mod prep_time;
+pub mod purge;
+pub mod read_audit;
+pub mod read_bill;
+pub mod read_history;
 pub mod read_item;
 pub mod read_items;
+pub mod restore_item;
+pub mod transition_item;
 pub mod types;
 pub mod update_item;
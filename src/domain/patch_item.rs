@@ -0,0 +1,168 @@
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::domain::events::ItemEvents;
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{
+    IdType, Item, ItemId, ItemPatch, ItemVersion, PrepTime, TableId, VersionMatch, VersionType,
+};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{FetchOneError, Repository, UpdateError};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call update_partial()
+
+pub struct PatchRequest {
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+    pub patch: ItemPatch,
+    // The version the client read, the stored one must not be newer
+    pub version: Option<ItemVersion<VersionType>>,
+    // The If-Match condition the current version of the item must meet
+    pub if_match: Option<VersionMatch>,
+}
+
+pub struct PatchResponse {
+    pub item: Item,
+}
+
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    UnknownItemId,
+    // Holds the stored item, which is newer than the patched one
+    VersionConflict(Item),
+    PreconditionFailed,
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    estimator: Arc<dyn PrepTimeEstimator>,
+    events: ItemEvents,
+    req: PatchRequest,
+) -> Result<PatchResponse, Error> {
+    let repo = AsyncRepository::new(repo);
+    let current = match repo.fetch_one(req.table_id, req.item_id).await {
+        Ok(item) => item,
+        Err(FetchOneError::UnknownItemId) => return Err(Error::UnknownItemId),
+        Err(FetchOneError::UnknownTableId) => return Err(Error::UnknowTableId),
+        Err(FetchOneError::Unknown) => return Err(Error::Unknown),
+    };
+    let conditional = req.if_match.is_some();
+    if req
+        .if_match
+        .is_some_and(|if_match| !if_match.matches(&current.version))
+    {
+        return Err(Error::PreconditionFailed);
+    }
+
+    // Without a version from the client the item must not change before it is written
+    let version = req.version.unwrap_or_else(|| current.version.clone());
+    let mut patch = req.patch;
+    if patch.changes_order() {
+        let estimate = estimator.estimate(&patch.apply_to(&current));
+        patch.prep_time = Some(PrepTime::new(Utc::now(), estimate));
+    }
+
+    match repo
+        .update_partial(req.table_id, req.item_id, patch, version)
+        .await
+    {
+        Ok(item) => {
+            events.updated(req.table_id, &item);
+            Ok(PatchResponse { item })
+        }
+        Err(UpdateError::Unknown) => Err(Error::Unknown),
+        Err(UpdateError::UnknownItemId) => Err(Error::UnknownItemId),
+        Err(UpdateError::UnknownTableId) => Err(Error::UnknowTableId),
+        // The item changed since it met the condition
+        Err(UpdateError::VersionConflict(_)) if conditional => Err(Error::PreconditionFailed),
+        Err(UpdateError::VersionConflict(item)) => Err(Error::VersionConflict(item)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::prep_time::FixedEstimator,
+        domain::types::{ItemName, ItemNotes, ItemQuantity},
+        repository::inmemory::InMemoryRepository,
+        repository::sqlite::SqliteRepository,
+    };
+
+    fn repos() -> Vec<Arc<dyn Repository>> {
+        vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ]
+    }
+
+    fn request(patch: ItemPatch, version: Option<u32>) -> PatchRequest {
+        PatchRequest {
+            table_id: TableId::from_int(1),
+            item_id: ItemId::from_int(1),
+            patch,
+            version: version.map(ItemVersion::from_int),
+            if_match: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_change_only_the_patched_fields() {
+        for repo in repos() {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(1),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+
+            let patch = ItemPatch {
+                notes: Some(ItemNotes::from_str("No cheese".to_string())),
+                ..ItemPatch::default()
+            };
+            match execute(
+                repo.clone(),
+                Arc::new(FixedEstimator(10)),
+                ItemEvents::new(),
+                request(patch, None),
+            )
+            .await
+            {
+                Ok(res) => {
+                    assert_eq!(res.item.name, ItemName::pizza());
+                    assert_eq!(res.item.notes, ItemNotes::from_str("No cheese".to_string()));
+                    assert_eq!(res.item.version, ItemVersion::from_int(2));
+                    // the order is the same, so is its estimate
+                    assert_eq!(res.item.prep_time, PrepTime::from_date("2023-12-12"));
+                }
+                Err(_) => unreachable!(),
+            }
+
+            let patch = ItemPatch {
+                quantity: Some(ItemQuantity::from_int(3)),
+                ..ItemPatch::default()
+            };
+            match execute(
+                repo,
+                Arc::new(FixedEstimator(10)),
+                ItemEvents::new(),
+                request(patch, Some(1)),
+            )
+            .await
+            {
+                Err(Error::VersionConflict(item)) => {
+                    assert_eq!(item.version, ItemVersion::from_int(2));
+                    assert_eq!(item.quantity, ItemQuantity::one());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::domain::types::{Item, PrepMinutesType};
+
+// Estimators deciding how many minutes an item takes to prepare. They are
+// passed to the create and update use cases, so tests can pin the estimate.
+
+pub trait PrepTimeEstimator: Send + Sync {
+    fn estimate(&self, item: &Item) -> PrepMinutesType;
+}
+
+// Picks a uniformly random estimate in the inclusive [min, max] range
+pub struct RandomEstimator {
+    min: PrepMinutesType,
+    max: PrepMinutesType,
+    rng: Mutex<StdRng>,
+}
+
+impl RandomEstimator {
+    // A seed makes the sequence of estimates reproducible
+    pub fn try_new(
+        min: PrepMinutesType,
+        max: PrepMinutesType,
+        seed: Option<u64>,
+    ) -> Result<Self, String> {
+        if min > max {
+            return Err(format!(
+                "Min prep time {} is greater than max prep time {}.",
+                min, max
+            ));
+        }
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ok(Self {
+            min,
+            max,
+            rng: Mutex::new(rng),
+        })
+    }
+}
+
+impl PrepTimeEstimator for RandomEstimator {
+    fn estimate(&self, _item: &Item) -> PrepMinutesType {
+        match self.rng.lock() {
+            Ok(mut rng) => rng.gen_range(self.min..=self.max),
+            // A poisoned rng is still usable, it holds no invariant
+            Err(poisoned) => poisoned.into_inner().gen_range(self.min..=self.max),
+        }
+    }
+}
+
+// Always returns the same estimate
+#[cfg(test)]
+pub struct FixedEstimator(pub PrepMinutesType);
+
+#[cfg(test)]
+impl PrepTimeEstimator for FixedEstimator {
+    fn estimate(&self, _item: &Item) -> PrepMinutesType {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime};
+
+    fn item() -> Item {
+        Item::new(
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::default(),
+        )
+    }
+
+    #[test]
+    fn random_estimates_stay_within_the_configured_range() {
+        let estimator = RandomEstimator::try_new(5, 15, None).unwrap();
+        for _ in 0..1000 {
+            let minutes = estimator.estimate(&item());
+            assert!((5..=15).contains(&minutes));
+        }
+    }
+
+    #[test]
+    fn seeded_estimators_are_deterministic() {
+        let first = RandomEstimator::try_new(5, 15, Some(42)).unwrap();
+        let second = RandomEstimator::try_new(5, 15, Some(42)).unwrap();
+        let first: Vec<PrepMinutesType> = (0..20).map(|_| first.estimate(&item())).collect();
+        let second: Vec<PrepMinutesType> = (0..20).map(|_| second.estimate(&item())).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn an_inverted_range_is_rejected() {
+        assert!(RandomEstimator::try_new(15, 5, None).is_err());
+        assert_eq!(
+            RandomEstimator::try_new(7, 7, None)
+                .unwrap()
+                .estimate(&item()),
+            7
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{PurgeError, Repository};
+use chrono::{Duration, Utc};
+
+// Here can be found the retention policy, request and response structs and
+// function execute() to perform Repository call purge_deleted()
+
+// How long deleted items are kept before they are removed for good
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetentionPolicy {
+    pub retention: Duration,
+}
+
+impl RetentionPolicy {
+    pub fn from_days(days: u32) -> Self {
+        RetentionPolicy {
+            retention: Duration::days(days as i64),
+        }
+    }
+}
+
+pub struct PurgeRequest {
+    pub policy: RetentionPolicy,
+}
+
+pub struct PurgeResponse {
+    pub removed: usize,
+}
+
+pub enum Error {
+    Unknown,
+}
+
+pub async fn execute(repo: Arc<dyn Repository>, req: PurgeRequest) -> Result<PurgeResponse, Error> {
+    let deleted_before = Utc::now() - req.policy.retention;
+    match AsyncRepository::new(repo)
+        .purge_deleted(deleted_before)
+        .await
+    {
+        Ok(removed) => Ok(PurgeResponse { removed }),
+        Err(PurgeError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+// Purges once every period for as long as the task runs
+pub async fn run_every(
+    repo: Arc<dyn Repository>,
+    policy: RetentionPolicy,
+    period: std::time::Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match execute(repo.clone(), PurgeRequest { policy }).await {
+            Ok(res) => tracing::info!(removed = res.removed, "purged deleted items"),
+            Err(Error::Unknown) => tracing::error!("unable to purge deleted items"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{
+        IdType, ItemFilter, ItemId, ItemName, ItemNotes, ItemQuantity, ItemSort, ItemVersion,
+        PrepTime, TableId,
+    };
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+
+    fn insert(repo: &Arc<dyn Repository>, item_id: IdType) {
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(item_id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+    }
+
+    fn all_items(repo: &Arc<dyn Repository>) -> usize {
+        match repo.fetch_all(
+            TableId::from_int(1),
+            true,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        ) {
+            Ok(items) => items.len(),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_remove_only_items_deleted_before_the_retention() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos {
+            insert(&repo, 1);
+            insert(&repo, 2);
+            insert(&repo, 3);
+            repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                .ok();
+            repo.delete(TableId::from_int(1), ItemId::from_int(2), None)
+                .ok();
+
+            // A week of retention keeps what was just deleted
+            let policy = RetentionPolicy::from_days(7);
+            match execute(repo.clone(), PurgeRequest { policy }).await {
+                Ok(res) => assert_eq!(res.removed, 0),
+                Err(_) => unreachable!(),
+            }
+            assert_eq!(all_items(&repo), 3);
+
+            let policy = RetentionPolicy {
+                retention: Duration::seconds(-1),
+            };
+            match execute(repo.clone(), PurgeRequest { policy }).await {
+                Ok(res) => assert_eq!(res.removed, 2),
+                Err(_) => unreachable!(),
+            }
+            assert_eq!(all_items(&repo), 1);
+            assert!(repo
+                .fetch_one(TableId::from_int(1), ItemId::from_int(3))
+                .is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_purge_errors() {
+        let repo = Arc::new(InMemoryRepository::new().with_error());
+        let policy = RetentionPolicy::from_days(7);
+        match execute(repo, PurgeRequest { policy }).await {
+            Err(Error::Unknown) => {}
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use crate::audit::{AuditError, AuditRecord, AuditRepository};
+use crate::domain::types::{IdType, TableId};
+use chrono::{DateTime, Utc};
+
+// Here can be found request and response structs and function execute() to
+// perform AuditRepository call fetch()
+
+pub struct ReadAuditRequest {
+    pub table_id: Option<TableId<IdType>>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+pub struct ReadAuditResponse {
+    pub records: Vec<AuditRecord>,
+}
+
+pub enum Error {
+    Unknown,
+}
+
+pub async fn execute(
+    log: Arc<dyn AuditRepository>,
+    req: ReadAuditRequest,
+) -> Result<ReadAuditResponse, Error> {
+    match tokio::task::spawn_blocking(move || log.fetch(req.table_id, req.since)).await {
+        Ok(Ok(records)) => Ok(ReadAuditResponse { records }),
+        Ok(Err(AuditError::Unknown)) | Err(_) => Err(Error::Unknown),
+    }
+}
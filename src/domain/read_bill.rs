@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use crate::domain::types::{Bill, IdType, ItemFilter, ItemName, ItemSort, TableId, TaxRate};
+use crate::menu::{run, MenuPrices, MenuRepository};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{FetchAllError, Repository};
+
+// Here can be found request and response structs and function execute() to
+// price the active items of a table with the menu, without changing them
+
+pub struct ReadBillRequest {
+    pub table_id: TableId<IdType>,
+    pub tax_rate: TaxRate,
+}
+
+pub struct ReadBillResponse {
+    pub bill: Bill,
+}
+
+pub enum Error {
+    Unknown,
+    // The table has no active items
+    UnknowTableId,
+    // Names of the ordered items missing from the menu
+    UnpricedItems(Vec<ItemName>),
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    menu: Arc<dyn MenuRepository>,
+    req: ReadBillRequest,
+) -> Result<ReadBillResponse, Error> {
+    let items = match AsyncRepository::new(repo)
+        .fetch_all(
+            req.table_id,
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        )
+        .await
+    {
+        Ok(items) if items.is_empty() => return Err(Error::UnknowTableId),
+        Ok(items) => items,
+        Err(FetchAllError::UnknownTableId) => return Err(Error::UnknowTableId),
+        Err(FetchAllError::Unknown) => return Err(Error::Unknown),
+    };
+    let prices = match run(menu, |menu| menu.fetch_all()).await {
+        Ok(menu_items) => MenuPrices::from_items(&menu_items),
+        Err(_) => return Err(Error::Unknown),
+    };
+
+    match Bill::compute(req.table_id, &items, &prices, req.tax_rate) {
+        Ok(bill) => Ok(ReadBillResponse { bill }),
+        Err(names) => Err(Error::UnpricedItems(names)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{
+        IdType, ItemId, ItemNotes, ItemQuantity, ItemVersion, PrepTime, QuantityType,
+    };
+    use crate::menu::inmemory::InMemoryMenuRepository;
+    use crate::menu::MenuItem;
+    use crate::repository::inmemory::InMemoryRepository;
+
+    fn repo_with(items: &[(IdType, &str, QuantityType)]) -> Arc<InMemoryRepository> {
+        let repo = Arc::new(InMemoryRepository::new());
+        for (id, name, quantity) in items {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(*id),
+                ItemName::from_str(name.to_string()),
+                ItemNotes::some_notes(),
+                ItemQuantity::from_int(*quantity),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        repo
+    }
+
+    fn menu() -> Arc<dyn MenuRepository> {
+        let menu = InMemoryMenuRepository::new();
+        menu.insert(MenuItem::new(1, "Pizza", 950)).ok();
+        menu.insert(MenuItem::new(2, "Tiramisu", 500)).ok();
+        Arc::new(menu)
+    }
+
+    fn request(tax: &str) -> ReadBillRequest {
+        ReadBillRequest {
+            table_id: TableId::from_int(1),
+            tax_rate: TaxRate::try_from(tax.to_string()).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_compute_line_items_and_totals() {
+        let repo = repo_with(&[(2, "tiramisu", 1), (1, "Pizza", 2)]);
+
+        match execute(repo, menu(), request("8.25")).await {
+            Ok(res) => {
+                let amounts: Vec<_> = res.bill.lines.iter().map(|line| line.amount).collect();
+                assert_eq!(amounts, vec![1900, 500]);
+                assert_eq!(res.bill.subtotal, 2400);
+                assert_eq!(res.bill.tax, 198);
+                assert_eq!(res.bill.total, 2598);
+            }
+            Err(_) => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_items_missing_from_the_menu() {
+        let repo = repo_with(&[(1, "Pizza", 1), (2, "Lasagna", 1)]);
+
+        match execute(repo, menu(), request("0")).await {
+            Err(Error::UnpricedItems(names)) => {
+                assert_eq!(names, vec![ItemName::from_str("Lasagna".to_string())])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_return_an_unknown_table_id_error_when_table_is_empty() {
+        let repo = repo_with(&[]);
+
+        match execute(repo, menu(), request("0")).await {
+            Err(Error::UnknowTableId) => {}
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::domain::types::{IdType, Item, ItemId, TableId};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{FetchOneError, Repository};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call fetch_history()
+
+pub struct ReadHistoryRequest {
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+}
+
+pub struct ReadHistoryResponse {
+    pub items: Vec<Item>,
+}
+
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    UnknownItemId,
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    req: ReadHistoryRequest,
+) -> Result<ReadHistoryResponse, Error> {
+    match AsyncRepository::new(repo)
+        .fetch_history(req.table_id, req.item_id)
+        .await
+    {
+        Ok(items) => Ok(ReadHistoryResponse { items }),
+        Err(FetchOneError::UnknownItemId) => Err(Error::UnknownItemId),
+        Err(FetchOneError::UnknownTableId) => Err(Error::UnknowTableId),
+        Err(FetchOneError::Unknown) => Err(Error::Unknown),
+    }
+}
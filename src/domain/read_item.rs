@@ -1,14 +1,14 @@
 use std::sync::Arc;
 
 use crate::domain::types::{IdType, Item, TableId};
+use crate::repository::async_repository::AsyncRepository;
 use crate::repository::{FetchOneError, Repository};
 
 use super::types::ItemId;
 
-// Here can be found request and response structs and function execute() to 
+// Here can be found request and response structs and function execute() to
 // perform Repository call fetch_one()
 
-
 pub struct ReadRequest {
     pub table_id: TableId<IdType>,
     pub item_id: ItemId<IdType>,
@@ -24,8 +24,11 @@ pub enum Error {
     UnknownItemId,
 }
 
-pub fn execute(repo: Arc<dyn Repository>, req: ReadRequest) -> Result<ReadResponse, Error> {
-    match repo.fetch_one(req.table_id, req.item_id) {
+pub async fn execute(repo: Arc<dyn Repository>, req: ReadRequest) -> Result<ReadResponse, Error> {
+    match AsyncRepository::new(repo)
+        .fetch_one(req.table_id, req.item_id)
+        .await
+    {
         Ok(item) => Ok(ReadResponse { item }),
         Err(FetchOneError::UnknownItemId) => Err(Error::UnknownItemId),
         Err(FetchOneError::UnknownTableId) => Err(Error::UnknowTableId),
@@ -37,12 +40,12 @@ pub fn execute(repo: Arc<dyn Repository>, req: ReadRequest) -> Result<ReadRespon
 mod tests {
     use super::*;
     use crate::{
-        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion},
+        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime},
         repository::inmemory::InMemoryRepository,
     };
 
-    #[test]
-    fn it_should_return_an_ok_when_request_is_valid() {
+    #[tokio::test]
+    async fn it_should_return_an_ok_when_request_is_valid() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -52,13 +55,13 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::from_int(1),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = ReadRequest::new(TableId::from_int(1), ItemId::from_int(1));
 
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Ok(res) => {
@@ -66,18 +69,18 @@ mod tests {
                 assert_eq!(res.item.name, ItemName::pizza());
                 assert_eq!(res.item.notes, ItemNotes::some_notes());
                 assert_eq!(res.item.quantity, ItemQuantity::one());
-                assert_eq!(res.item.deleted, false);
+                assert!(!res.item.deleted);
                 assert_eq!(res.item.version, ItemVersion::from_int(1));
             }
             Err(_) => unreachable!(),
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = ReadRequest::new(TableId::from_int(1), ItemId::from_int(1));
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Err(Error::UnknowTableId) => {}
@@ -85,8 +88,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_item_id_error_when_item_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_item_id_error_when_item_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -96,12 +99,12 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = ReadRequest::new(TableId::from_int(1), ItemId::from_int(2));
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Err(Error::UnknownItemId) => {}
@@ -109,8 +112,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_not_found_when_item_deleted() {
+    #[tokio::test]
+    async fn it_should_return_not_found_when_item_deleted() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -120,12 +123,12 @@ mod tests {
             ItemQuantity::one(),
             true,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = ReadRequest::new(TableId::from_int(1), ItemId::from_int(1));
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Err(Error::UnknownItemId) => {}
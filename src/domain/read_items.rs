@@ -1,20 +1,23 @@
 use std::sync::Arc;
 
-use crate::domain::types::{IdType, Item, TableId};
+use crate::domain::types::{IdType, Item, ItemCursor, ItemFilter, ItemPage, ItemSort, TableId};
+use crate::repository::async_repository::AsyncRepository;
 use crate::repository::{FetchAllError, Repository};
 
-// Here can be found request and response structs and function execute() to 
+// Here can be found request and response structs and function execute() to
 // perform Repository call fetch_all()
 
 pub struct ReadAllRequest {
     pub table_id: TableId<IdType>,
     pub include_deleted: bool,
-    pub filter: String,
-    pub sort_by: String,
+    pub filter: ItemFilter,
+    pub sort_by: ItemSort,
+    pub page: Option<ItemPage>,
 }
 
 pub struct ReadAllResponse {
     pub items: Vec<Item>,
+    pub next_cursor: Option<ItemCursor>,
 }
 
 pub enum Error {
@@ -22,9 +25,36 @@ pub enum Error {
     UnknowTableId,
 }
 
-pub fn execute(repo: Arc<dyn Repository>, req: ReadAllRequest) -> Result<ReadAllResponse, Error> {
-    match repo.fetch_all(req.table_id, req.include_deleted) {
-        Ok(items) => Ok(ReadAllResponse { items }),
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    req: ReadAllRequest,
+) -> Result<ReadAllResponse, Error> {
+    // Fetch one extra item to find out whether there is a next page
+    let limit = req.page.as_ref().map(|page| page.limit);
+    let page = req.page.map(|page| ItemPage {
+        after: page.after,
+        limit: page.limit + 1,
+    });
+    match AsyncRepository::new(repo)
+        .fetch_all(
+            req.table_id,
+            req.include_deleted,
+            req.filter,
+            req.sort_by,
+            page,
+        )
+        .await
+    {
+        Ok(mut items) => {
+            let next_cursor = match limit {
+                Some(limit) if items.len() > limit => {
+                    items.truncate(limit);
+                    items.last().map(ItemCursor::from_item)
+                }
+                _ => None,
+            };
+            Ok(ReadAllResponse { items, next_cursor })
+        }
         Err(FetchAllError::UnknownTableId) => Err(Error::UnknowTableId),
         Err(FetchAllError::Unknown) => Err(Error::Unknown),
     }
@@ -34,12 +64,12 @@ pub fn execute(repo: Arc<dyn Repository>, req: ReadAllRequest) -> Result<ReadAll
 mod tests {
     use super::*;
     use crate::{
-        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion},
-        repository::inmemory::InMemoryRepository,
+        domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, PrepTime},
+        repository::{inmemory::InMemoryRepository, sqlite::SqliteRepository},
     };
 
-    #[test]
-    fn it_should_return_an_ok_when_request_is_valid() {
+    #[tokio::test]
+    async fn it_should_return_an_ok_when_request_is_valid() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -49,18 +79,19 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::from_int(1),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = ReadAllRequest::new(
             TableId::from_int(1),
             false,
-            String::from(""),
-            String::from(""),
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Ok(res) => {
@@ -69,23 +100,24 @@ mod tests {
                 assert_eq!(res.items[0].name, ItemName::pizza());
                 assert_eq!(res.items[0].notes, ItemNotes::some_notes());
                 assert_eq!(res.items[0].quantity, ItemQuantity::one());
-                assert_eq!(res.items[0].deleted, false);
+                assert!(!res.items[0].deleted);
                 assert_eq!(res.items[0].version, ItemVersion::from_int(1));
             }
             Err(_) => unreachable!(),
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_table_id_error_when_table_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = ReadAllRequest::new(
             TableId::from_int(1),
             false,
-            String::from(""),
-            String::from(""),
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
         );
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Err(Error::UnknowTableId) => {}
@@ -93,8 +125,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_only_not_deleted_items_when_not_include_deleted() {
+    #[tokio::test]
+    async fn it_should_return_only_not_deleted_items_when_not_include_deleted() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -104,7 +136,7 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
         repo.insert(
@@ -115,17 +147,18 @@ mod tests {
             ItemQuantity::one(),
             true,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
         let req = ReadAllRequest::new(
             TableId::from_int(1),
             false,
-            String::from(""),
-            String::from(""),
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
         );
-        let res = execute(repo, req);
+        let res = execute(repo, req).await;
 
         match res {
             Ok(res) => {
@@ -134,25 +167,200 @@ mod tests {
                 assert_eq!(res.items[0].name, ItemName::pizza());
                 assert_eq!(res.items[0].notes, ItemNotes::some_notes());
                 assert_eq!(res.items[0].quantity, ItemQuantity::one());
-                assert_eq!(res.items[0].deleted, false);
+                assert!(!res.items[0].deleted);
                 assert_eq!(res.items[0].version, ItemVersion::from_int(1));
             }
             _ => unreachable!(),
         };
     }
 
+    #[tokio::test]
+    async fn it_should_return_filtered_items_in_requested_order() {
+        let repo = Arc::new(InMemoryRepository::new());
+        for (id, quantity) in [(1, 1), (2, 3), (3, 2)] {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::from_int(quantity),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+
+        let req = ReadAllRequest::new(
+            TableId::from_int(1),
+            false,
+            ItemFilter {
+                name_contains: Some("pizza".to_string()),
+                min_quantity: Some(ItemQuantity::from_int(2)),
+                max_quantity: None,
+            },
+            ItemSort::try_from("-quantity".to_string()).unwrap(),
+            None,
+        );
+        let res = execute(repo, req).await;
+
+        match res {
+            Ok(res) => {
+                assert!(res.items.len() == 2);
+                assert_eq!(res.items[0].id, ItemId::from_int(2));
+                assert_eq!(res.items[1].id, ItemId::from_int(3));
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[tokio::test]
+    async fn it_should_return_a_next_cursor_until_the_last_page() {
+        let repo = Arc::new(InMemoryRepository::new());
+        for id in 1..=3 {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+
+        let page = ItemPage {
+            after: None,
+            limit: 2,
+        };
+        let req = ReadAllRequest::new(
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            Some(page),
+        );
+        let next_cursor = match execute(repo.clone(), req).await {
+            Ok(res) => {
+                assert!(res.items.len() == 2);
+                assert_eq!(res.items[1].id, ItemId::from_int(2));
+                res.next_cursor
+            }
+            _ => unreachable!(),
+        };
+        assert!(next_cursor.is_some());
+
+        let page = ItemPage {
+            after: next_cursor,
+            limit: 2,
+        };
+        let req = ReadAllRequest::new(
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            Some(page),
+        );
+        match execute(repo, req).await {
+            Ok(res) => {
+                assert!(res.items.len() == 1);
+                assert_eq!(res.items[0].id, ItemId::from_int(3));
+                assert!(res.next_cursor.is_none());
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[tokio::test]
+    async fn it_should_page_through_the_deleted_copies_of_an_item() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos {
+            for _ in 0..3 {
+                insert(&repo, 1);
+                repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                    .ok();
+            }
+            insert(&repo, 1);
+            insert(&repo, 2);
+
+            let mut listed = vec![];
+            let mut after = None;
+            loop {
+                let req = ReadAllRequest::new(
+                    TableId::from_int(1),
+                    true,
+                    ItemFilter::default(),
+                    ItemSort::default(),
+                    Some(ItemPage { after, limit: 1 }),
+                );
+                let res = match execute(repo.clone(), req).await {
+                    Ok(res) => res,
+                    _ => unreachable!(),
+                };
+                listed.extend(
+                    res.items
+                        .iter()
+                        .map(|item| (item.id, item.version.clone(), item.deleted)),
+                );
+                after = match res.next_cursor {
+                    Some(cursor) => Some(cursor),
+                    None => break,
+                };
+            }
+
+            let copy = |id, version, deleted| {
+                (
+                    ItemId::from_int(id),
+                    ItemVersion::from_int(version),
+                    deleted,
+                )
+            };
+            assert_eq!(
+                listed,
+                vec![
+                    copy(1, 2, true),
+                    copy(1, 4, true),
+                    copy(1, 6, true),
+                    copy(1, 7, false),
+                    copy(2, 1, false),
+                ]
+            );
+        }
+    }
+
+    fn insert(repo: &Arc<dyn Repository>, id: IdType) {
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(id),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+    }
+
     impl ReadAllRequest {
         fn new(
             table_id: TableId<IdType>,
             include_deleted: bool,
-            filter: String,
-            sort_by: String,
+            filter: ItemFilter,
+            sort_by: ItemSort,
+            page: Option<ItemPage>,
         ) -> Self {
             Self {
                 table_id,
                 include_deleted,
                 filter,
                 sort_by,
+                page,
             }
         }
     }
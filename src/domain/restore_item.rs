@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use crate::domain::events::ItemEvents;
+use crate::domain::types::{IdType, Item, ItemId, TableId};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{Repository, RestoreError};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call restore()
+
+pub struct RestoreRequest {
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+}
+
+pub struct RestoreResponse {
+    pub item: Item,
+}
+
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    // No deleted copy of the item exists
+    UnknownItemId,
+    AlreadyActive,
+    // The item was checked out with its table
+    Paid,
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    events: ItemEvents,
+    req: RestoreRequest,
+) -> Result<RestoreResponse, Error> {
+    match AsyncRepository::new(repo)
+        .restore(req.table_id, req.item_id)
+        .await
+    {
+        // The item is back among the active ones of its table
+        Ok(item) => {
+            events.created(req.table_id, &item);
+            Ok(RestoreResponse { item })
+        }
+        Err(RestoreError::UnknownItemId) => Err(Error::UnknownItemId),
+        Err(RestoreError::UnknownTableId) => Err(Error::UnknowTableId),
+        Err(RestoreError::AlreadyActive) => Err(Error::AlreadyActive),
+        Err(RestoreError::Paid) => Err(Error::Paid),
+        Err(RestoreError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::read_history::{self, ReadHistoryRequest};
+    use crate::domain::types::{
+        Bill, ItemFilter, ItemName, ItemNotes, ItemQuantity, ItemSort, ItemState, ItemVersion,
+        PrepTime, TaxRate,
+    };
+    use crate::menu::{MenuItem, MenuPrices};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+
+    fn insert(repo: &Arc<dyn Repository>, name: ItemName) {
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            name,
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+    }
+
+    fn request() -> RestoreRequest {
+        RestoreRequest {
+            table_id: TableId::from_int(1),
+            item_id: ItemId::from_int(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_restore_the_latest_deleted_copy() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        for repo in repos {
+            insert(&repo, ItemName::pizza());
+            repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                .ok();
+            insert(&repo, ItemName::pasta());
+
+            match execute(repo.clone(), ItemEvents::new(), request()).await {
+                Err(Error::AlreadyActive) => {}
+                _ => unreachable!(),
+            }
+            repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                .ok();
+
+            match execute(repo.clone(), ItemEvents::new(), request()).await {
+                Ok(res) => {
+                    assert_eq!(res.item.name, ItemName::pasta());
+                    assert!(!res.item.deleted);
+                    // pizza 1, deleted 2, pasta 3, deleted 4
+                    assert_eq!(res.item.version, ItemVersion::from_int(5));
+                }
+                _ => unreachable!(),
+            }
+
+            let req = ReadHistoryRequest {
+                table_id: TableId::from_int(1),
+                item_id: ItemId::from_int(1),
+            };
+            match read_history::execute(repo, req).await {
+                Ok(res) => {
+                    let names: Vec<(ItemName, bool)> = res
+                        .items
+                        .into_iter()
+                        .map(|it| (it.name, it.deleted))
+                        .collect();
+                    assert_eq!(
+                        names,
+                        vec![(ItemName::pizza(), true), (ItemName::pasta(), false)]
+                    );
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_not_restore_a_paid_item() {
+        let repos: Vec<Arc<dyn Repository>> = vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ];
+        let prices = MenuPrices::from_items(&[MenuItem::new(1, "Some pizza", 950)]);
+        for repo in repos {
+            insert(&repo, ItemName::pizza());
+            let items = match repo.fetch_all(
+                TableId::from_int(1),
+                false,
+                ItemFilter::default(),
+                ItemSort::default(),
+                None,
+            ) {
+                Ok(items) => items,
+                Err(_) => unreachable!(),
+            };
+            let bill =
+                match Bill::compute(TableId::from_int(1), &items, &prices, TaxRate::default()) {
+                    Ok(bill) => bill,
+                    Err(_) => unreachable!(),
+                };
+            assert!(repo.checkout(bill).is_ok());
+
+            match execute(repo.clone(), ItemEvents::new(), request()).await {
+                Err(Error::Paid) => {}
+                _ => unreachable!(),
+            }
+            let req = ReadHistoryRequest {
+                table_id: TableId::from_int(1),
+                item_id: ItemId::from_int(1),
+            };
+            match read_history::execute(repo, req).await {
+                Ok(res) => {
+                    let states: Vec<(ItemState, bool)> = res
+                        .items
+                        .into_iter()
+                        .map(|it| (it.state, it.deleted))
+                        .collect();
+                    assert_eq!(states, vec![(ItemState::Paid, true)]);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_not_restore_an_item_never_deleted() {
+        let repo: Arc<dyn Repository> = Arc::new(InMemoryRepository::new());
+        insert(&repo, ItemName::pizza());
+        let req = RestoreRequest {
+            table_id: TableId::from_int(1),
+            item_id: ItemId::from_int(2),
+        };
+        match execute(repo, ItemEvents::new(), req).await {
+            Err(Error::UnknownItemId) => {}
+            _ => unreachable!(),
+        }
+    }
+}
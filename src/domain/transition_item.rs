@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use crate::domain::events::ItemEvents;
+use crate::domain::types::{IdType, Item, ItemId, ItemState, ItemVersion, TableId, VersionType};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{Repository, TransitionError};
+
+// Here can be found request and response structs and function execute() to
+// perform Repository call transition()
+
+pub struct TransitionRequest {
+    pub table_id: TableId<IdType>,
+    pub item_id: ItemId<IdType>,
+    pub state: ItemState,
+    pub version: Option<ItemVersion<VersionType>>,
+}
+
+pub struct TransitionResponse {
+    pub item: Item,
+}
+
+pub enum Error {
+    Unknown,
+    UnknowTableId,
+    UnknownItemId,
+    VersionConflict(Item),
+    // The item's current state can't move to the requested one
+    InvalidTransition(Item),
+}
+
+pub async fn execute(
+    repo: Arc<dyn Repository>,
+    events: ItemEvents,
+    req: TransitionRequest,
+) -> Result<TransitionResponse, Error> {
+    match AsyncRepository::new(repo)
+        .transition(req.table_id, req.item_id, req.state, req.version)
+        .await
+    {
+        Ok(item) => {
+            match item.state {
+                ItemState::Cancelled => events.deleted(req.table_id, item.id),
+                _ => events.updated(req.table_id, &item),
+            }
+            Ok(TransitionResponse { item })
+        }
+        Err(TransitionError::UnknownItemId) => Err(Error::UnknownItemId),
+        Err(TransitionError::UnknownTableId) => Err(Error::UnknowTableId),
+        Err(TransitionError::VersionConflict(item)) => Err(Error::VersionConflict(item)),
+        Err(TransitionError::Invalid(item)) => Err(Error::InvalidTransition(item)),
+        Err(TransitionError::Unknown) => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{ItemName, ItemNotes, ItemQuantity, PrepTime};
+    use crate::repository::inmemory::InMemoryRepository;
+    use crate::repository::sqlite::SqliteRepository;
+
+    fn insert(repo: &Arc<dyn Repository>) {
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+    }
+
+    fn request(state: ItemState, version: Option<VersionType>) -> TransitionRequest {
+        TransitionRequest {
+            table_id: TableId::from_int(1),
+            item_id: ItemId::from_int(1),
+            state,
+            version: version.map(ItemVersion::from_int),
+        }
+    }
+
+    fn repos() -> Vec<Arc<dyn Repository>> {
+        vec![
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(SqliteRepository::try_new("", true).unwrap()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn it_should_move_an_item_through_the_kitchen() {
+        for repo in repos() {
+            insert(&repo);
+
+            for (state, version) in [
+                (ItemState::Cooking, 2),
+                (ItemState::Ready, 3),
+                (ItemState::Served, 4),
+            ] {
+                match execute(
+                    repo.clone(),
+                    ItemEvents::new(),
+                    request(state, Some(version - 1)),
+                )
+                .await
+                {
+                    Ok(res) => {
+                        assert_eq!(res.item.state, state);
+                        assert_eq!(res.item.version, ItemVersion::from_int(version));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
+                Ok(item) => assert_eq!(item.state, ItemState::Served),
+                _ => unreachable!(),
+            }
+
+            // Served dishes don't go back to the kitchen
+            match execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Cooking, None),
+            )
+            .await
+            {
+                Err(Error::InvalidTransition(item)) => assert_eq!(item.state, ItemState::Served),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_stale_version() {
+        for repo in repos() {
+            insert(&repo);
+            execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Cooking, None),
+            )
+            .await
+            .ok();
+
+            match execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Ready, Some(1)),
+            )
+            .await
+            {
+                Err(Error::VersionConflict(item)) => {
+                    assert_eq!(item.version, ItemVersion::from_int(2))
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_cancel_like_a_delete() {
+        for repo in repos() {
+            insert(&repo);
+            execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Cooking, None),
+            )
+            .await
+            .ok();
+
+            let deleted_at = match execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Cancelled, Some(2)),
+            )
+            .await
+            {
+                Ok(res) => {
+                    assert!(res.item.deleted);
+                    assert_eq!(res.item.state, ItemState::Cancelled);
+                    assert_eq!(res.item.version, ItemVersion::from_int(3));
+                    res.item.deleted_at
+                }
+                _ => unreachable!(),
+            };
+            assert!(repo
+                .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+                .is_err());
+            // The returned item is the one stored
+            match repo.fetch_history(TableId::from_int(1), ItemId::from_int(1)) {
+                Ok(items) => assert_eq!(items[0].deleted_at, deleted_at),
+                _ => unreachable!(),
+            }
+            match execute(
+                repo.clone(),
+                ItemEvents::new(),
+                request(ItemState::Ordered, None),
+            )
+            .await
+            {
+                Err(Error::UnknownItemId) => {}
+                _ => unreachable!(),
+            }
+
+            // Restoring puts the order back at the start
+            match repo.restore(TableId::from_int(1), ItemId::from_int(1)) {
+                Ok(item) => assert_eq!(item.state, ItemState::Ordered),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_mark_deleted_items_as_cancelled() {
+        for repo in repos() {
+            insert(&repo);
+            repo.delete(TableId::from_int(1), ItemId::from_int(1), None)
+                .ok();
+
+            match repo.fetch_history(TableId::from_int(1), ItemId::from_int(1)) {
+                Ok(items) => assert_eq!(items[0].state, ItemState::Cancelled),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use chrono::{Duration, Utc};
-use rand::Rng;
-use serde::{Deserialize, Serialize};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, SecondsFormat, Timelike, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::fmt;
-use std::ops::{Add, AddAssign};
+use std::ops::{Add, AddAssign, RangeInclusive};
+use std::sync::OnceLock;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::menu::{MenuPrices, PriceType};
+
 // Contains all structs and traits to satisfy all validation constraints
 
 // Id type for TableId and ItemId
@@ -88,7 +93,7 @@ impl TryFrom<String> for ItemNotes {
     }
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Clone, PartialEq, PartialOrd)]
 pub struct ItemQuantity<T>(T);
 
 impl TryFrom<String> for ItemQuantity<QuantityType> {
@@ -157,6 +162,202 @@ impl ItemVersion<VersionType> {
     pub fn from_int(number: u32) -> Self {
         Self(number)
     }
+
+    // Entity tag of the item in this version, e.g. "3" with the quotes
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.0)
+    }
+}
+
+// Versions listed in an If-Match or If-None-Match header, "*" matches any version
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionMatch {
+    Any,
+    Versions(Vec<ItemVersion<VersionType>>),
+}
+
+impl TryFrom<String> for VersionMatch {
+    type Error = String;
+
+    // Weak tags (W/"3") are compared like strong ones, all of them come from versions
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s, true)
+    }
+}
+
+impl VersionMatch {
+    // If-Match only compares strong tags, a weak one is refused
+    pub fn try_strong(s: String) -> Result<Self, String> {
+        Self::parse(s, false)
+    }
+
+    fn parse(s: String, accept_weak: bool) -> Result<Self, String> {
+        if s.trim() == "*" {
+            return Ok(VersionMatch::Any);
+        }
+
+        s.split(',')
+            .map(|tag| {
+                let tag = tag.trim();
+                let strong = match tag.strip_prefix("W/") {
+                    Some(strong) if accept_weak => strong,
+                    Some(_) => return Err(format!("'{}' is a weak entity tag.", tag)),
+                    None => tag,
+                };
+                strong
+                    .strip_prefix('"')
+                    .and_then(|tag| tag.strip_suffix('"'))
+                    .and_then(|version| version.parse::<VersionType>().ok())
+                    .map(ItemVersion::from_int)
+                    .ok_or(format!("'{}' is not a valid entity tag.", tag))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(VersionMatch::Versions)
+    }
+
+    pub fn matches(&self, version: &ItemVersion<VersionType>) -> bool {
+        match self {
+            VersionMatch::Any => true,
+            VersionMatch::Versions(versions) => versions.contains(version),
+        }
+    }
+}
+
+// Type for the number of minutes an item takes to prepare
+pub type PrepMinutesType = u32;
+
+// Preparation estimate of an item: when it was ordered, how long it takes and
+// when it will be ready. Timestamps are UTC with second precision and are
+// written as RFC 3339 (e.g. "2023-12-12T10:15:00Z") in JSON and in sqlite.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrepTime {
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub ordered_at: DateTime<Utc>,
+    pub prep_minutes: PrepMinutesType,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub ready_at: DateTime<Utc>,
+}
+
+impl PrepTime {
+    pub fn new(ordered_at: DateTime<Utc>, prep_minutes: PrepMinutesType) -> Self {
+        let ordered_at = ordered_at.with_nanosecond(0).unwrap_or(ordered_at);
+        Self {
+            ordered_at,
+            prep_minutes,
+            ready_at: ordered_at + Duration::minutes(prep_minutes.into()),
+        }
+    }
+
+    pub fn try_from_rfc3339(
+        ordered_at: &str,
+        prep_minutes: PrepMinutesType,
+    ) -> Result<Self, String> {
+        match DateTime::parse_from_rfc3339(ordered_at) {
+            Ok(ordered_at) => Ok(Self::new(ordered_at.with_timezone(&Utc), prep_minutes)),
+            Err(_) => Err(format!(
+                "'{}' is not a valid RFC 3339 timestamp.",
+                ordered_at
+            )),
+        }
+    }
+}
+
+pub fn to_rfc3339(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// The current time at the second, as to_rfc3339() stores it
+pub fn now_in_seconds() -> DateTime<Utc> {
+    let now = Utc::now();
+    now.with_nanosecond(0).unwrap_or(now)
+}
+
+pub fn serialize_timestamp<S: Serializer>(
+    timestamp: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_rfc3339(timestamp))
+}
+
+fn serialize_optional_timestamp<S: Serializer>(
+    timestamp: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match timestamp {
+        Some(timestamp) => serialize_timestamp(timestamp, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Where an item is between the order and the table. Cancelled and paid items are
+// the deleted ones, only paid items are gone for good.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemState {
+    Ordered,
+    Cooking,
+    Ready,
+    Served,
+    Cancelled,
+    // Checked out with its table, only checkout() gets an item there
+    Paid,
+}
+
+impl ItemState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemState::Ordered => "ordered",
+            ItemState::Cooking => "cooking",
+            ItemState::Ready => "ready",
+            ItemState::Served => "served",
+            ItemState::Cancelled => "cancelled",
+            ItemState::Paid => "paid",
+        }
+    }
+
+    // Ordered, cooking and ready items are still waited for in the kitchen
+    pub fn in_kitchen(&self) -> bool {
+        matches!(
+            self,
+            ItemState::Ordered | ItemState::Cooking | ItemState::Ready
+        )
+    }
+
+    // Items move forward one state at a time and never come back from served
+    pub fn can_become(&self, next: ItemState) -> bool {
+        matches!(
+            (self, next),
+            (ItemState::Ordered, ItemState::Cooking)
+                | (ItemState::Cooking, ItemState::Ready)
+                | (ItemState::Ready, ItemState::Served)
+                | (
+                    ItemState::Ordered | ItemState::Cooking | ItemState::Ready | ItemState::Served,
+                    ItemState::Cancelled
+                )
+        )
+    }
+}
+
+impl TryFrom<String> for ItemState {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "ordered" => Ok(ItemState::Ordered),
+            "cooking" => Ok(ItemState::Cooking),
+            "ready" => Ok(ItemState::Ready),
+            "served" => Ok(ItemState::Served),
+            "cancelled" => Ok(ItemState::Cancelled),
+            "paid" => Ok(ItemState::Paid),
+            _ => Err(format!("'{}' is not a valid item state.", s)),
+        }
+    }
+}
+
+impl fmt::Display for ItemState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -166,8 +367,16 @@ pub struct Item {
     pub notes: ItemNotes,
     pub quantity: ItemQuantity<QuantityType>,
     pub deleted: bool,
+    pub state: ItemState,
     pub version: ItemVersion<VersionType>,
-    pub time_to_prepare: String,
+    #[serde(flatten)]
+    pub prep_time: PrepTime,
+    // When the item was deleted, only set on deleted copies
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_timestamp"
+    )]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Item {
@@ -178,7 +387,7 @@ impl Item {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Self {
         Self {
             id: item_id,
@@ -186,19 +395,400 @@ impl Item {
             notes: item_notes,
             quantity: item_quantity,
             deleted: item_deleted,
+            // A new item is ordered, a deleted one was cancelled
+            state: match item_deleted {
+                true => ItemState::Cancelled,
+                false => ItemState::Ordered,
+            },
             version: item_version,
-            time_to_prepare: item_time_to_prepare,
+            prep_time: item_prep_time,
+            deleted_at: None,
+        }
+    }
+}
+
+// An active item of the kitchen queue, with the table it was ordered for
+#[derive(Debug, Serialize, Clone)]
+pub struct QueuedItem {
+    pub table_id: TableId<IdType>,
+    #[serde(flatten)]
+    pub item: Item,
+}
+
+// Fields changed by a partial update, None keeps the stored value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItemPatch {
+    pub name: Option<ItemName>,
+    pub notes: Option<ItemNotes>,
+    pub quantity: Option<ItemQuantity<QuantityType>>,
+    pub prep_time: Option<PrepTime>,
+}
+
+impl ItemPatch {
+    // The item with the patched fields, its version is left as is
+    pub fn apply_to(&self, item: &Item) -> Item {
+        Item {
+            name: self.name.clone().unwrap_or_else(|| item.name.clone()),
+            notes: self.notes.clone().unwrap_or_else(|| item.notes.clone()),
+            quantity: self
+                .quantity
+                .clone()
+                .unwrap_or_else(|| item.quantity.clone()),
+            prep_time: self.prep_time.unwrap_or(item.prep_time),
+            ..item.clone()
+        }
+    }
+
+    // The preparation estimate depends on what is ordered, not on the notes
+    pub fn changes_order(&self) -> bool {
+        self.name.is_some() || self.quantity.is_some()
+    }
+}
+
+// Filter applied to the items of a table when listing them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItemFilter {
+    pub name_contains: Option<String>,
+    pub min_quantity: Option<ItemQuantity<QuantityType>>,
+    pub max_quantity: Option<ItemQuantity<QuantityType>>,
+}
+
+impl ItemFilter {
+    // Name matching is ASCII case-insensitive to behave like sqlite lower()
+    pub fn matches(&self, item: &Item) -> bool {
+        let name_matches = match &self.name_contains {
+            Some(needle) => item
+                .name
+                .0
+                .to_ascii_lowercase()
+                .contains(&needle.to_ascii_lowercase()),
+            None => true,
+        };
+        let min_matches = match &self.min_quantity {
+            Some(min) => item.quantity >= *min,
+            None => true,
+        };
+        let max_matches = match &self.max_quantity {
+            Some(max) => item.quantity <= *max,
+            None => true,
+        };
+        name_matches && min_matches && max_matches
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ItemSortField {
+    #[default]
+    Id,
+    Name,
+    Quantity,
+    Version,
+    ReadyAt,
+}
+
+// Sort order of listed items, parsed from e.g. "name" or "-ready_at"
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ItemSort {
+    pub field: ItemSortField,
+    pub descending: bool,
+}
+
+impl TryFrom<String> for ItemSort {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (descending, name) = match s.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, s.as_str()),
+        };
+        let field = match name {
+            "id" => ItemSortField::Id,
+            "name" => ItemSortField::Name,
+            "quantity" => ItemSortField::Quantity,
+            "version" => ItemSortField::Version,
+            // time_to_prepare is the name the ready time was listed under before
+            "ready_at" | "time_to_prepare" => ItemSortField::ReadyAt,
+            _ => return Err(format!("'{}' is not a valid sort field.", s)),
+        };
+        Ok(Self { field, descending })
+    }
+}
+
+impl ItemSort {
+    // Items with equal sort keys are ordered by id to keep listings stable
+    pub fn compare(&self, a: &Item, b: &Item) -> Ordering {
+        let ordering = match self.field {
+            ItemSortField::Id => a.id.0.cmp(&b.id.0),
+            ItemSortField::Name => a.name.0.cmp(&b.name.0),
+            ItemSortField::Quantity => a.quantity.0.cmp(&b.quantity.0),
+            ItemSortField::Version => a.version.0.cmp(&b.version.0),
+            ItemSortField::ReadyAt => a.prep_time.ready_at.cmp(&b.prep_time.ready_at),
+        };
+        let ordering = match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        };
+        ordering.then(a.id.0.cmp(&b.id.0))
+    }
+}
+
+// Opaque position in an items listing, encoded as base64 of "item_id:version"
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemCursor {
+    pub item_id: ItemId<IdType>,
+    pub version: ItemVersion<VersionType>,
+}
+
+impl ItemCursor {
+    pub fn from_item(item: &Item) -> Self {
+        Self {
+            item_id: item.id,
+            version: item.version.clone(),
+        }
+    }
+
+    // Keyset comparison used by repositories to resume a listing after the cursor
+    pub fn is_before(&self, item: &Item) -> bool {
+        (item.id.0, item.version.0) > (self.item_id.0, self.version.0)
+    }
+}
+
+impl TryFrom<String> for ItemCursor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let err = || format!("'{}' is not a valid cursor.", s);
+        let decoded = URL_SAFE_NO_PAD.decode(&s).map_err(|_| err())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| err())?;
+        let (item_id, version) = decoded.split_once(':').ok_or_else(err)?;
+        Ok(Self {
+            item_id: ItemId::try_from(item_id.to_string()).map_err(|_| err())?,
+            version: ItemVersion::try_from(version.to_string()).map_err(|_| err())?,
+        })
+    }
+}
+
+impl fmt::Display for ItemCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw = format!("{}:{}", self.item_id, self.version);
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(raw))
+    }
+}
+
+// Number of items returned per page, 1-100
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageLimit(usize);
+
+impl TryFrom<String> for PageLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.parse::<usize>() {
+            Ok(val) if (1..=100).contains(&val) => Ok(Self(val)),
+            _ => Err(format!("'{}' is not a valid page limit.", s)),
+        }
+    }
+}
+
+impl Default for PageLimit {
+    fn default() -> Self {
+        Self(50)
+    }
+}
+
+impl From<PageLimit> for usize {
+    fn from(value: PageLimit) -> Self {
+        value.0
+    }
+}
+
+// Keyset page of a listing: items strictly after the cursor ordered by (item_id, version).
+// Deleting bumps the version, so the deleted copies of an item have versions of their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemPage {
+    pub after: Option<ItemCursor>,
+    pub limit: usize,
+}
+
+// Tax applied to a bill, in basis points (1/100 of a percent)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TaxRate(u32);
+
+impl TryFrom<String> for TaxRate {
+    type Error = String;
+
+    // Accepts a percentage with up to two decimals, e.g. "8.25"
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (units, decimals) = s.split_once('.').unwrap_or((&s, ""));
+        let is_valid = !units.is_empty()
+            && decimals.len() <= 2
+            && units
+                .chars()
+                .chain(decimals.chars())
+                .all(|c| c.is_ascii_digit());
+        let basis_points = match is_valid {
+            true => format!("{}{:0<2}", units, decimals).parse::<u32>().ok(),
+            false => None,
+        };
+        match basis_points {
+            Some(basis_points) if basis_points <= 10_000 => Ok(Self(basis_points)),
+            _ => Err(format!("'{}' is not a valid tax percentage.", s)),
+        }
+    }
+}
+
+impl TaxRate {
+    // Tax on an amount in cents, rounded half up
+    pub fn tax_on(&self, amount: PriceType) -> PriceType {
+        ((u64::from(amount) * u64::from(self.0) + 5_000) / 10_000) as PriceType
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Copy, Clone)]
+pub struct ReceiptId(IdType);
+
+impl From<IdType> for ReceiptId {
+    fn from(value: IdType) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct BillLine {
+    pub item_id: ItemId<IdType>,
+    // Version the line was billed at, checkout fails if the item changed since
+    #[serde(skip)]
+    pub version: ItemVersion<VersionType>,
+    pub name: ItemName,
+    pub quantity: ItemQuantity<QuantityType>,
+    pub unit_price: PriceType,
+    pub amount: PriceType,
+}
+
+// Amounts are in cents, like menu prices
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Bill {
+    pub table_id: TableId<IdType>,
+    pub lines: Vec<BillLine>,
+    pub subtotal: PriceType,
+    pub tax: PriceType,
+    pub total: PriceType,
+}
+
+impl Bill {
+    // Prices the active items of a table, fails with the names missing from the menu
+    pub fn compute(
+        table_id: TableId<IdType>,
+        items: &[Item],
+        prices: &MenuPrices,
+        tax_rate: TaxRate,
+    ) -> Result<Self, Vec<ItemName>> {
+        let mut lines = vec![];
+        let mut unpriced = vec![];
+        for item in items.iter().filter(|item| !item.deleted) {
+            match prices.price_of(&item.name) {
+                Some(unit_price) => lines.push(BillLine {
+                    item_id: item.id,
+                    version: item.version.clone(),
+                    name: item.name.clone(),
+                    quantity: item.quantity.clone(),
+                    unit_price,
+                    amount: unit_price.saturating_mul(QuantityType::from(item.quantity.clone())),
+                }),
+                None => unpriced.push(item.name.clone()),
+            }
+        }
+        if !unpriced.is_empty() {
+            return Err(unpriced);
         }
+        lines.sort_by_key(|line| IdType::from(line.item_id));
+
+        let subtotal = lines.iter().map(|line| line.amount).sum();
+        let tax = tax_rate.tax_on(subtotal);
+        Ok(Self {
+            table_id,
+            lines,
+            subtotal,
+            tax,
+            total: subtotal + tax,
+        })
     }
-    pub fn gen_time_to_prepare(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.time_to_prepare = (Utc::now() + Duration::minutes(rng.gen_range(1..16))).to_string();
+
+    // (item_id, version) of the billed items, ordered by item id
+    pub fn item_versions(&self) -> Vec<(IdType, VersionType)> {
+        self.lines
+            .iter()
+            .map(|line| {
+                (
+                    IdType::from(line.item_id),
+                    VersionType::from(line.version.clone()),
+                )
+            })
+            .collect()
+    }
+}
+
+// Client chosen key of a retried request, see the Idempotency-Key header
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_empty_or_too_long = s.is_empty() || s.len() > 255;
+        let is_visible_ascii = s.bytes().all(|b| b.is_ascii_graphic());
+
+        if is_empty_or_too_long || !is_visible_ascii {
+            Err(format!("'{}' is not a valid idempotency key.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl From<IdempotencyKey> for String {
+    fn from(key: IdempotencyKey) -> Self {
+        key.0
     }
 }
 
+// Response produced for an idempotency key, replayed to retries
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+// Outcome of claiming an idempotency key for a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyClaim {
+    // The key was free, the request runs and its response is stored
+    Claimed,
+    // The first request with this key is still running
+    InProgress,
+    // The key was used before with the same request
+    Replay(StoredResponse),
+    // The key was used before with a different request
+    Mismatch,
+}
+
 #[derive(PartialEq, Hash, Eq, Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct TableId<T>(T);
 
+// The table ids TableId accepts, set once at startup from the configuration
+static TABLE_ID_RANGE: OnceLock<RangeInclusive<IdType>> = OnceLock::new();
+const DEFAULT_TABLE_ID_RANGE: RangeInclusive<IdType> = 1..=100;
+
+pub fn set_table_id_range(first: IdType, last: IdType) -> Result<(), String> {
+    if first == 0 || first > last {
+        return Err(format!("{}-{} is not a valid table id range.", first, last));
+    }
+    TABLE_ID_RANGE
+        .set(first..=last)
+        .map_err(|_| "The table id range is already set.".to_string())
+}
+
 impl TryFrom<String> for TableId<IdType> {
     type Error = String;
 
@@ -207,7 +797,7 @@ impl TryFrom<String> for TableId<IdType> {
         let mut is_in_range = true;
         if is_numeric {
             let val = s.parse::<IdType>().unwrap();
-            let allowed_table_id_range = 1..101;
+            let allowed_table_id_range = TABLE_ID_RANGE.get().unwrap_or(&DEFAULT_TABLE_ID_RANGE);
             is_in_range = allowed_table_id_range.contains(&val);
         }
         if is_numeric && is_in_range {
@@ -311,9 +901,20 @@ impl ItemVersion<VersionType> {
     }
 }
 
+#[cfg(test)]
+impl PrepTime {
+    // Ordered at midnight UTC of a "YYYY-MM-DD" date, ready 10 minutes later
+    pub fn from_date(date: &str) -> Self {
+        Self::try_from_rfc3339(&format!("{}T00:00:00Z", date), 10).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::domain::types::{ItemId, ItemName, ItemNotes, TableId};
+    use crate::domain::types::{
+        set_table_id_range, ItemCursor, ItemId, ItemName, ItemNotes, ItemSort, ItemSortField,
+        ItemState, ItemVersion, PageLimit, PrepTime, TableId, TaxRate, VersionMatch,
+    };
     use claim::{assert_err, assert_ok};
 
     #[test]
@@ -396,6 +997,12 @@ mod tests {
         assert_err!(TableId::try_from(table_id));
     }
 
+    #[test]
+    fn an_invalid_table_id_range_is_rejected() {
+        assert_err!(set_table_id_range(0, 10));
+        assert_err!(set_table_id_range(10, 9));
+    }
+
     #[test]
     fn a_valid_item_id_is_parsed_successfully() {
         let item_id = "1".to_string();
@@ -409,4 +1016,124 @@ mod tests {
         item_id = "0".to_string();
         assert_err!(ItemId::try_from(item_id));
     }
+
+    #[test]
+    fn a_valid_sort_is_parsed_successfully() {
+        let sort = ItemSort::try_from("-ready_at".to_string()).unwrap();
+        assert_eq!(sort.field, ItemSortField::ReadyAt);
+        assert!(sort.descending);
+
+        let sort = ItemSort::try_from("name".to_string()).unwrap();
+        assert_eq!(sort.field, ItemSortField::Name);
+        assert!(!sort.descending);
+    }
+
+    #[test]
+    fn an_invalid_sort_is_rejected() {
+        for sort in &["", "-", "notes", "--name", "Name"] {
+            assert_err!(ItemSort::try_from(sort.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_cursor_survives_a_round_trip() {
+        let cursor = ItemCursor {
+            item_id: ItemId::from_int(12),
+            version: ItemVersion::from_int(3),
+        };
+        assert_eq!(ItemCursor::try_from(cursor.to_string()), Ok(cursor));
+    }
+
+    #[test]
+    fn an_invalid_cursor_is_rejected() {
+        for cursor in &["", "not base64!", "MTI", "MDoz", "YWJjOjE"] {
+            assert_err!(ItemCursor::try_from(cursor.to_string()));
+        }
+    }
+
+    #[test]
+    fn an_invalid_page_limit_is_rejected() {
+        assert_ok!(PageLimit::try_from("100".to_string()));
+        for limit in &["0", "101", "-1", "abc"] {
+            assert_err!(PageLimit::try_from(limit.to_string()));
+        }
+    }
+
+    #[test]
+    fn prep_time_is_serialized_as_rfc3339_timestamps() {
+        let prep_time = PrepTime::try_from_rfc3339("2023-12-12T10:15:30.250+01:00", 7).unwrap();
+        let json = serde_json::to_value(prep_time).unwrap();
+        assert_eq!(json["ordered_at"], "2023-12-12T09:15:30Z");
+        assert_eq!(json["prep_minutes"], 7);
+        assert_eq!(json["ready_at"], "2023-12-12T09:22:30Z");
+    }
+
+    #[test]
+    fn invalid_prep_time_timestamps_are_rejected() {
+        assert_err!(PrepTime::try_from_rfc3339("2023/12/12", 7));
+    }
+
+    #[test]
+    fn tax_rates_are_percentages_with_up_to_two_decimals() {
+        for (rate, tax) in [
+            ("0", 0),
+            ("10", 240),
+            ("8.25", 198),
+            ("8.5", 204),
+            ("100", 2400),
+        ] {
+            assert_eq!(
+                TaxRate::try_from(rate.to_string()).unwrap().tax_on(2400),
+                tax
+            );
+        }
+        for rate in ["", "-1", "8.125", "100.01", "1e2", ".5"] {
+            assert_err!(TaxRate::try_from(rate.to_string()));
+        }
+    }
+
+    #[test]
+    fn entity_tags_are_parsed_into_versions() {
+        let tags = VersionMatch::try_from(r#""2", W/"3""#.to_string()).unwrap();
+        assert!(tags.matches(&ItemVersion::from_int(2)));
+        assert!(tags.matches(&ItemVersion::from_int(3)));
+        assert!(!tags.matches(&ItemVersion::from_int(4)));
+        assert!(VersionMatch::try_from("*".to_string())
+            .unwrap()
+            .matches(&ItemVersion::from_int(4)));
+        assert_eq!(ItemVersion::from_int(2).etag(), r#""2""#);
+        for tag in ["", "2", r#""two""#, r#""2"x"#] {
+            assert_err!(VersionMatch::try_from(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn weak_entity_tags_are_refused_where_strong_ones_are_expected() {
+        let tags = VersionMatch::try_strong(r#""2", "3""#.to_string()).unwrap();
+        assert!(tags.matches(&ItemVersion::from_int(3)));
+        assert_ok!(VersionMatch::try_strong("*".to_string()));
+        for tag in [r#"W/"3""#, r#""2", W/"3""#] {
+            assert_err!(VersionMatch::try_strong(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn item_states_move_forward_or_get_cancelled() {
+        assert!(ItemState::Ordered.can_become(ItemState::Cooking));
+        assert!(ItemState::Cooking.can_become(ItemState::Ready));
+        assert!(ItemState::Ready.can_become(ItemState::Served));
+        assert!(ItemState::Served.can_become(ItemState::Cancelled));
+
+        assert!(!ItemState::Served.can_become(ItemState::Cooking));
+        assert!(!ItemState::Ordered.can_become(ItemState::Served));
+        assert!(!ItemState::Ready.can_become(ItemState::Ready));
+        assert!(!ItemState::Cancelled.can_become(ItemState::Ordered));
+        assert!(!ItemState::Served.can_become(ItemState::Paid));
+        assert!(!ItemState::Paid.can_become(ItemState::Cancelled));
+        assert_eq!(
+            ItemState::try_from("ready".to_string()),
+            Ok(ItemState::Ready)
+        );
+        assert!(ItemState::try_from("eaten".to_string()).is_err());
+    }
 }
@@ -1,15 +1,21 @@
+use chrono::Utc;
 use std::sync::Arc;
 
-use crate::domain::types::{IdType, Item, TableId};
-use crate::repository::{Repository, UpdateError};
+use crate::domain::events::ItemEvents;
+use crate::domain::prep_time::PrepTimeEstimator;
+use crate::domain::types::{IdType, Item, PrepTime, TableId, VersionMatch};
+use crate::repository::async_repository::AsyncRepository;
+use crate::repository::{FetchOneError, Repository, UpdateError};
 
-// Here can be found request and response structs and function execute() to 
+// Here can be found request and response structs and function execute() to
 // perform Repository update()
 
-
 pub struct CreateOrUpdateRequest {
     pub table_id: TableId<IdType>,
     pub item: Item,
+    // The If-Match condition the current version of the item must meet, it
+    // replaces the version of the item
+    pub if_match: Option<VersionMatch>,
 }
 
 pub struct CreateOrUpdateResponse {
@@ -20,30 +26,53 @@ pub enum Error {
     Unknown,
     UnknowTableId,
     UnknownItemId,
-    VersionConflict,
+    // Holds the stored item, which is newer than the updated one
+    VersionConflict(Item),
+    PreconditionFailed,
 }
 
-pub fn execute(
+pub async fn execute(
     repo: Arc<dyn Repository>,
+    estimator: Arc<dyn PrepTimeEstimator>,
+    events: ItemEvents,
     req: CreateOrUpdateRequest,
 ) -> Result<CreateOrUpdateResponse, Error> {
+    let repo = AsyncRepository::new(repo);
     let mut cloned_it = req.item.clone();
-    cloned_it.gen_time_to_prepare();
-    match repo.update(
-        req.table_id,
-        cloned_it.id,
-        cloned_it.name,
-        cloned_it.notes,
-        cloned_it.quantity,
-        cloned_it.deleted,
-        cloned_it.version,
-        cloned_it.time_to_prepare,
-    ) {
-        Ok(item) => Ok(CreateOrUpdateResponse { item }),
+    let conditional = req.if_match.is_some();
+    if let Some(if_match) = req.if_match {
+        match repo.fetch_one(req.table_id, cloned_it.id).await {
+            Ok(item) if if_match.matches(&item.version) => cloned_it.version = item.version,
+            Ok(_) => return Err(Error::PreconditionFailed),
+            Err(FetchOneError::UnknownItemId) => return Err(Error::UnknownItemId),
+            Err(FetchOneError::UnknownTableId) => return Err(Error::UnknowTableId),
+            Err(FetchOneError::Unknown) => return Err(Error::Unknown),
+        }
+    }
+    cloned_it.prep_time = PrepTime::new(Utc::now(), estimator.estimate(&cloned_it));
+    match repo
+        .update(
+            req.table_id,
+            cloned_it.id,
+            cloned_it.name,
+            cloned_it.notes,
+            cloned_it.quantity,
+            cloned_it.deleted,
+            cloned_it.version,
+            cloned_it.prep_time,
+        )
+        .await
+    {
+        Ok(item) => {
+            events.updated(req.table_id, &item);
+            Ok(CreateOrUpdateResponse { item })
+        }
         Err(UpdateError::Unknown) => Err(Error::Unknown),
         Err(UpdateError::UnknownItemId) => Err(Error::UnknownItemId),
         Err(UpdateError::UnknownTableId) => Err(Error::UnknowTableId),
-        Err(UpdateError::VersionConflict) => Err(Error::VersionConflict),
+        // The item changed since it met the condition
+        Err(UpdateError::VersionConflict(_)) if conditional => Err(Error::PreconditionFailed),
+        Err(UpdateError::VersionConflict(item)) => Err(Error::VersionConflict(item)),
     }
 }
 
@@ -51,14 +80,15 @@ pub fn execute(
 mod tests {
     use super::*;
     use crate::{
+        domain::prep_time::FixedEstimator,
         domain::types::{
             ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, QuantityType, VersionType,
         },
         repository::inmemory::InMemoryRepository,
     };
 
-    #[test]
-    fn it_should_return_an_ok_when_request_is_valid() {
+    #[tokio::test]
+    async fn it_should_return_an_ok_when_request_is_valid() {
         let repo = Arc::new(InMemoryRepository::new());
         let same_table_id = 1;
         let same_item_id = 1;
@@ -70,7 +100,7 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::from_int(1),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
@@ -82,10 +112,10 @@ mod tests {
             ItemQuantity::from_int(2),
             false,
             ItemVersion::from_int(2),
-            "".to_string(),
+            PrepTime::default(),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Ok(res) => {
@@ -96,15 +126,15 @@ mod tests {
                 );
                 assert_eq!(res.item.notes, ItemNotes::from_str("New notes".to_string()));
                 assert_eq!(res.item.quantity, ItemQuantity::from_int(2));
-                assert_eq!(res.item.deleted, false);
+                assert!(!res.item.deleted);
                 assert_eq!(res.item.version, ItemVersion::from_int(2));
             }
             Err(_) => unreachable!(),
         };
     }
 
-    #[test]
-    fn it_should_return_a_conflict_error_when_item_version_is_lower_than_in_storage() {
+    #[tokio::test]
+    async fn it_should_return_a_conflict_error_when_item_version_is_lower_than_in_storage() {
         let repo = Arc::new(InMemoryRepository::new());
         let same_table_id = 1;
         let same_item_id = 1;
@@ -116,7 +146,7 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::from_int(2),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
@@ -128,19 +158,19 @@ mod tests {
             ItemQuantity::from_int(2),
             false,
             ItemVersion::from_int(1),
-            "".to_string(),
+            PrepTime::default(),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
-            Err(Error::VersionConflict) => {}
+            Err(Error::VersionConflict(item)) => assert_eq!(item.version, ItemVersion::from_int(2)),
             _ => unreachable!(),
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_error_when_table_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_error_when_table_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         let req = CreateOrUpdateRequest::new(
             TableId::from_int(1),
@@ -150,10 +180,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Err(Error::UnknowTableId) => {}
@@ -161,8 +191,8 @@ mod tests {
         };
     }
 
-    #[test]
-    fn it_should_return_an_unknown_error_when_item_id_not_found() {
+    #[tokio::test]
+    async fn it_should_return_an_unknown_error_when_item_id_not_found() {
         let repo = Arc::new(InMemoryRepository::new());
         repo.insert(
             TableId::from_int(1),
@@ -172,7 +202,7 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         )
         .ok();
 
@@ -184,10 +214,10 @@ mod tests {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         );
 
-        let res = execute(repo, req);
+        let res = execute(repo, Arc::new(FixedEstimator(10)), ItemEvents::new(), req).await;
 
         match res {
             Err(Error::UnknownItemId) => {}
@@ -195,7 +225,50 @@ mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn it_should_fail_the_precondition_of_another_version() {
+        let repo = Arc::new(InMemoryRepository::new());
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::from_int(2),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+
+        // A version ahead of the stored one fails as well
+        for version in [1, 3] {
+            let mut req = CreateOrUpdateRequest::new(
+                TableId::from_int(1),
+                ItemId::from_int(1),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::from_int(2),
+                false,
+                ItemVersion::from_int(3),
+                PrepTime::default(),
+            );
+            req.if_match = Some(VersionMatch::Versions(vec![ItemVersion::from_int(version)]));
+            match execute(
+                repo.clone(),
+                Arc::new(FixedEstimator(10)),
+                ItemEvents::new(),
+                req,
+            )
+            .await
+            {
+                Err(Error::PreconditionFailed) => {}
+                _ => unreachable!(),
+            };
+        }
+    }
+
     impl CreateOrUpdateRequest {
+        #[allow(clippy::too_many_arguments)]
         fn new(
             table_id: TableId<IdType>,
             item_id: ItemId<IdType>,
@@ -204,19 +277,20 @@ mod tests {
             item_quantity: ItemQuantity<QuantityType>,
             item_deleted: bool,
             item_version: ItemVersion<VersionType>,
-            item_time_to_prepare: String,
+            item_prep_time: PrepTime,
         ) -> Self {
             Self {
-                table_id: table_id,
-                item: Item {
-                    id: item_id,
-                    name: item_name,
-                    notes: item_notes,
-                    quantity: item_quantity,
-                    deleted: item_deleted,
-                    version: item_version,
-                    time_to_prepare: item_time_to_prepare,
-                },
+                table_id,
+                item: Item::new(
+                    item_id,
+                    item_name,
+                    item_notes,
+                    item_quantity,
+                    item_deleted,
+                    item_version,
+                    item_prep_time,
+                ),
+                if_match: None,
             }
         }
     }
@@ -1,90 +1,348 @@
 use anyhow::Result;
 use clap::Parser;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tokio::signal;
 
+mod api;
+mod audit;
+mod client;
 mod config;
 mod domain;
-use config::Opt;
-mod client;
-
+mod menu;
 mod repository;
+
+use api::handle;
+use api::server::{serve, ServerLimits};
+use audit::sqlite::SqliteAuditRepository;
+use audit::{AuditRepository, Auditor};
+use config::{Command, Config, LogFormat, MigrateCommand, Opt, Storage};
+use domain::events::ItemEvents;
+use domain::idempotency::IdempotencyPolicy;
+use domain::menu_catalog::CatalogEstimator;
+use domain::prep_time::{PrepTimeEstimator, RandomEstimator};
+use domain::purge::{self, PurgeRequest, RetentionPolicy};
+use domain::types::{set_table_id_range, TaxRate};
+use menu::inmemory::InMemoryMenuRepository;
+use menu::sqlite::SqliteMenuRepository;
+use menu::{MenuPolicy, MenuRepository};
 use repository::inmemory::InMemoryRepository;
+use repository::migrations;
 use repository::sqlite::SqliteRepository;
+use repository::sqlite_pool::{SqlitePoolRepository, DEFAULT_READERS};
 use repository::Repository;
 
-mod api;
-use api::handle;
-use api::server::serve;
-
-pub fn init_logging() -> tracing_appender::non_blocking::WorkerGuard {
+pub fn init_logging(
+    level: &str,
+    format: LogFormat,
+) -> Result<tracing_appender::non_blocking::WorkerGuard> {
+    let filter = tracing_subscriber::EnvFilter::try_new(level).map_err(|err| {
+        anyhow::Error::msg(format!("'{}' is not a valid log level: {}", level, err))
+    })?;
     let (non_blocking, guard) = tracing_appender::non_blocking(std::io::stdout());
 
-    tracing_subscriber::fmt().with_writer(non_blocking).init();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(non_blocking)
+        .with_env_filter(filter);
+    match format {
+        LogFormat::Full => subscriber.init(),
+        LogFormat::Compact => subscriber.compact().init(),
+        LogFormat::Pretty => subscriber.pretty().init(),
+    }
 
-    guard
+    Ok(guard)
 }
 #[tokio::main]
-async fn app(args: Opt) -> Result<()> {
-    let _guard = init_logging();
+async fn app(args: Config) -> Result<()> {
+    let _guard = init_logging(&args.log_level, args.log_format)?;
 
     let addr = std::net::SocketAddr::new(args.address.parse()?, args.port);
+    set_table_id_range(args.min_table_id, args.max_table_id).map_err(anyhow::Error::msg)?;
 
-    let context: Arc<dyn Repository> = build_repo(Option::Some("restaurant.sqlite"));
-    let s_ctx = context.clone();
-    tokio::spawn(async move { serve(addr, s_ctx, handle).await });
-
+    let stores = match build_stores(&args.storage, !args.skip_migrations) {
+        Ok(stores) => stores,
+        Err(err) if args.fallback_memory => {
+            tracing::warn!("{}, falling back to in-memory storage", err);
+            Stores::in_memory()
+        }
+        Err(err) => return Err(anyhow::Error::msg(err)),
+    };
+    let context = stores.items;
+    let menu_repo = stores.menu;
+    let auditor = Arc::new(Auditor::new(stores.audit_log));
+    // Shut down once the server is done with them, like the items
+    let other_stores = (menu_repo.clone(), auditor.log());
+    let menu_policy = MenuPolicy {
+        strict: args.strict_menu,
+    };
+    let random_estimator =
+        RandomEstimator::try_new(args.min_prep_minutes, args.max_prep_minutes, args.prep_seed)
+            .map_err(anyhow::Error::msg)?;
+    let catalog = Arc::new(
+        CatalogEstimator::try_new(
+            args.menu_catalog.as_ref().map(PathBuf::from),
+            Box::new(random_estimator),
+        )
+        .map_err(anyhow::Error::msg)?,
+    );
+    let estimator: Arc<dyn PrepTimeEstimator> = catalog.clone();
+    let tax_rate = TaxRate::try_from(args.tax_percent.clone()).map_err(anyhow::Error::msg)?;
+    let idempotency_policy = IdempotencyPolicy::from_minutes(args.idempotency_ttl_minutes);
+    let events = ItemEvents::new();
+    if let Some(days) = args.purge_after_days {
+        if args.purge_interval_minutes == 0 {
+            return Err(anyhow::Error::msg(
+                "purge interval must be at least one minute",
+            ));
+        }
+        let period = Duration::from_secs(args.purge_interval_minutes as u64 * 60);
+        tokio::spawn(purge::run_every(
+            context.clone(),
+            RetentionPolicy::from_days(days),
+            period,
+        ));
+    }
     let running = Arc::new(AtomicBool::new(true));
+    let stop_clients = running.clone();
+    let shutdown = async move {
+        match signal::ctrl_c().await {
+            Ok(()) => tracing::info!("Shutting down..."),
+            Err(err) => tracing::info!("Unable to listen for shutdown signal: {}", err),
+        }
+        stop_clients.store(false, Ordering::SeqCst);
+    };
+    let limits = ServerLimits {
+        max_body_bytes: args.max_body_bytes,
+        header_read_timeout: Duration::from_secs(args.header_read_timeout_seconds),
+        drain_timeout: Duration::from_secs(args.drain_timeout_seconds),
+    };
+    let server = tokio::spawn(serve(
+        addr,
+        context,
+        move |mut request| {
+            request.extensions_mut().insert(estimator.clone());
+            request.extensions_mut().insert(catalog.clone());
+            request.extensions_mut().insert(menu_repo.clone());
+            request.extensions_mut().insert(menu_policy);
+            request.extensions_mut().insert(tax_rate);
+            request.extensions_mut().insert(idempotency_policy);
+            request.extensions_mut().insert(auditor.clone());
+            request.extensions_mut().insert(events.clone());
+            handle(request)
+        },
+        shutdown,
+        limits,
+    ));
+
     let mut handles = vec![];
 
     let base_url = format!("http://{}:{}", &args.address, args.port);
+    let table_ids = args.min_table_id..=args.max_table_id;
     // Spawn 10 threads with client_fn
     for id in 1..=args.num_clients {
         let c = running.clone();
         let url = base_url.clone();
+        let table_ids = table_ids.clone();
         let handle = thread::spawn(move || {
-            client::client_main(
-                id,
-                &url,
-                1000,
-                c.clone(),
-            );
+            client::client_main(id, &url, 1000, table_ids, c.clone());
         });
 
         handles.push(handle);
     }
 
-    match signal::ctrl_c().await {
-        Ok(()) => {
-            tracing::info!("Shutting down...");
-            running.store(false, Ordering::SeqCst);
-            thread::sleep(Duration::from_millis(3000));
-            //context.shutdown();
-        }
-        Err(err) => {
-            tracing::info!("Unable to listen for shutdown signal: {}", err);
-        }
+    // Returns once the requests in flight are drained, the websocket sessions are closed
+    // and the repository is shut down
+    let served = server.await;
+    let (menu_repo, audit_log) = other_stores;
+    match tokio::task::spawn_blocking(move || (menu_repo.shutdown(), audit_log.shutdown())).await {
+        Ok((Ok(()), Ok(()))) => tracing::info!("menu and audit log shut down"),
+        _ => tracing::error!("menu or audit log shutdown failed"),
     }
+    // Also when the server failed, the clients finish their current request and stop
+    running.store(false, Ordering::SeqCst);
+    tokio::task::spawn_blocking(move || {
+        for handle in handles {
+            if handle.join().is_err() {
+                tracing::error!("A client panicked");
+            }
+        }
+    })
+    .await?;
+    served??;
     tracing::info!("Shutted down");
     Ok(())
 }
 
-fn build_repo(sqlite_value: Option<&str>) -> Arc<dyn Repository> {
-    if let Some(path) = sqlite_value {
-        match SqliteRepository::try_new(path) {
-            Ok(repo) => return Arc::new(repo),
-            _ => panic!("Error while creating sqlite repo, using in-memory repo"),
+// The items, the menu and the audit log, all kept on the same storage
+struct Stores {
+    items: Arc<dyn Repository>,
+    menu: Arc<dyn MenuRepository>,
+    audit_log: Arc<dyn AuditRepository>,
+}
+
+impl Stores {
+    // The item repositories append to their audit log with every change
+    fn in_memory() -> Self {
+        let items = InMemoryRepository::new();
+        Self {
+            audit_log: items.audit_log(),
+            items: Arc::new(items),
+            menu: Arc::new(InMemoryMenuRepository::new()),
         }
     }
 
-    Arc::new(InMemoryRepository::new())
+    // An in-memory database can't be shared between connections, so it can't be pooled
+    // and the audit log is read through the connection of the items
+    fn in_memory_sqlite(apply_migrations: bool) -> Result<Self, String> {
+        let items = match SqliteRepository::try_new(":memory:", apply_migrations) {
+            Ok(items) => items,
+            _ => return Err(open_error(":memory:", apply_migrations)),
+        };
+        Ok(Self {
+            audit_log: Arc::new(items.audit_log()),
+            items: Arc::new(items),
+            menu: build_menu_repo(":memory:", apply_migrations)?,
+        })
+    }
+}
+
+fn build_stores(storage: &Storage, apply_migrations: bool) -> Result<Stores, String> {
+    let path = match storage.sqlite_path() {
+        Some(path) => path,
+        None => return Ok(Stores::in_memory()),
+    };
+    if path == ":memory:" {
+        return Stores::in_memory_sqlite(apply_migrations);
+    }
+    Ok(Stores {
+        items: build_repo(storage, apply_migrations)?,
+        menu: build_menu_repo(path, apply_migrations)?,
+        audit_log: build_audit_log(path, apply_migrations)?,
+    })
+}
+
+// try_new fails the same way for a file that can't be opened and for a schema left behind
+fn open_error(path: &str, apply_migrations: bool) -> String {
+    match apply_migrations {
+        true => format!("Unable to open the sqlite database {}", path),
+        false => format!(
+            "Unable to open the sqlite database {}, or it has pending migrations",
+            path
+        ),
+    }
+}
+
+fn build_repo(storage: &Storage, apply_migrations: bool) -> Result<Arc<dyn Repository>, String> {
+    match storage {
+        Storage::Memory => Ok(Arc::new(InMemoryRepository::new())),
+        Storage::Sqlite(path) => match SqliteRepository::try_new(path, apply_migrations) {
+            Ok(repo) => Ok(Arc::new(repo)),
+            _ => Err(open_error(path, apply_migrations)),
+        },
+        Storage::SqlitePool(path) => {
+            match SqlitePoolRepository::try_new(path, DEFAULT_READERS, apply_migrations) {
+                Ok(repo) => Ok(Arc::new(repo)),
+                _ => Err(open_error(path, apply_migrations)),
+            }
+        }
+    }
+}
+
+fn build_menu_repo(path: &str, apply_migrations: bool) -> Result<Arc<dyn MenuRepository>, String> {
+    match SqliteMenuRepository::try_new(path, apply_migrations) {
+        Ok(repo) => Ok(Arc::new(repo)),
+        _ => Err(open_error(path, apply_migrations)),
+    }
+}
+
+fn build_audit_log(path: &str, apply_migrations: bool) -> Result<Arc<dyn AuditRepository>, String> {
+    match SqliteAuditRepository::try_new(path, apply_migrations) {
+        Ok(repo) => Ok(Arc::new(repo)),
+        _ => Err(open_error(path, apply_migrations)),
+    }
+}
+
+fn migrate(path: &str, command: MigrateCommand) -> Result<()> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    let result = match command {
+        MigrateCommand::Status => {
+            for status in migrations::status(&connection)? {
+                match status.applied_at {
+                    Some(applied_at) => println!(
+                        "{:>4}  {:<40} applied {}",
+                        status.version, status.description, applied_at
+                    ),
+                    None => println!("{:>4}  {:<40} pending", status.version, status.description),
+                }
+            }
+            Ok(())
+        }
+        MigrateCommand::Up { to } => migrations::migrate_up(&mut connection, to).map(|applied| {
+            applied
+                .iter()
+                .for_each(|version| println!("Applied migration {}", version))
+        }),
+        MigrateCommand::Down { to } => {
+            let current = migrations::current_version(&connection)?;
+            let target = to.unwrap_or(current.saturating_sub(1));
+            migrations::migrate_down(&mut connection, target).map(|reverted| {
+                reverted
+                    .iter()
+                    .for_each(|version| println!("Reverted migration {}", version))
+            })
+        }
+    };
+    result?;
+    println!(
+        "Schema version {} (latest {})",
+        migrations::current_version(&connection)?,
+        migrations::latest_version()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn purge(path: &str, older_than_days: u32, apply_migrations: bool) -> Result<()> {
+    let repo = match SqliteRepository::try_new(path, apply_migrations) {
+        Ok(repo) => Arc::new(repo),
+        _ => return Err(anyhow::Error::msg("Unable to open the sqlite database")),
+    };
+    let policy = RetentionPolicy::from_days(older_than_days);
+    match purge::execute(repo.clone(), PurgeRequest { policy }).await {
+        Ok(res) => println!("Removed {} deleted items", res.removed),
+        Err(purge::Error::Unknown) => {
+            return Err(anyhow::Error::msg("Unable to purge deleted items"))
+        }
+    }
+    match repo.vacuum() {
+        Ok(_) => println!("Compacted {}", path),
+        Err(_) => return Err(anyhow::Error::msg("Unable to vacuum the sqlite database")),
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Opt::parse();
-    app(args)?;
+    let opt = Opt::parse();
+    let config = Config::load(&opt).map_err(anyhow::Error::msg)?;
+    if opt.print_config {
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+    let sqlite_path = || {
+        config
+            .storage
+            .sqlite_path()
+            .ok_or_else(|| anyhow::Error::msg("This command needs a sqlite storage"))
+    };
+    match opt.command {
+        Some(Command::Migrate { command }) => migrate(sqlite_path()?, command)?,
+        Some(Command::Purge { older_than_days }) => {
+            purge(sqlite_path()?, older_than_days, !config.skip_migrations)?
+        }
+        None => app(config)?,
+    }
     Ok(())
 }
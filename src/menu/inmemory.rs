@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::domain::types::IdType;
+use crate::menu::{MenuError, MenuItem, MenuItemId, MenuRepository};
+
+// In memory menu implementation based on BTreeMap, keeps dishes ordered by id
+
+pub struct InMemoryMenuRepository {
+    items: Mutex<BTreeMap<IdType, MenuItem>>,
+}
+
+impl InMemoryMenuRepository {
+    pub fn new() -> Self {
+        Self {
+            items: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+fn name_taken(items: &BTreeMap<IdType, MenuItem>, item: &MenuItem) -> bool {
+    let key = MenuItem::name_key(&item.name);
+    items
+        .values()
+        .any(|other| other.id != item.id && MenuItem::name_key(&other.name) == key)
+}
+
+impl MenuRepository for InMemoryMenuRepository {
+    fn insert(&self, item: MenuItem) -> Result<MenuItem, MenuError> {
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        if lock.contains_key(&IdType::from(item.id)) || name_taken(&lock, &item) {
+            return Err(MenuError::Conflict);
+        }
+        lock.insert(IdType::from(item.id), item.clone());
+        Ok(item)
+    }
+
+    fn update(&self, item: MenuItem) -> Result<MenuItem, MenuError> {
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        if !lock.contains_key(&IdType::from(item.id)) {
+            return Err(MenuError::UnknownItemId);
+        }
+        if name_taken(&lock, &item) {
+            return Err(MenuError::Conflict);
+        }
+        lock.insert(IdType::from(item.id), item.clone());
+        Ok(item)
+    }
+
+    fn fetch_all(&self) -> Result<Vec<MenuItem>, MenuError> {
+        match self.items.lock() {
+            Ok(lock) => Ok(lock.values().cloned().collect()),
+            _ => Err(MenuError::Unknown),
+        }
+    }
+
+    fn fetch_one(&self, id: MenuItemId) -> Result<MenuItem, MenuError> {
+        let lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match lock.get(&IdType::from(id)) {
+            Some(item) => Ok(item.clone()),
+            None => Err(MenuError::UnknownItemId),
+        }
+    }
+
+    fn delete(&self, id: MenuItemId) -> Result<(), MenuError> {
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match lock.remove(&IdType::from(id)) {
+            Some(_) => Ok(()),
+            None => Err(MenuError::UnknownItemId),
+        }
+    }
+
+    // Nothing to write out, the dishes go away with the repository
+    fn shutdown(&self) -> Result<(), MenuError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_keep_ids_and_names_unique() {
+        let repo = InMemoryMenuRepository::new();
+        assert!(repo.insert(MenuItem::new(1, "Margherita", 950)).is_ok());
+
+        assert_eq!(
+            repo.insert(MenuItem::new(1, "Tiramisu", 500)),
+            Err(MenuError::Conflict)
+        );
+        assert_eq!(
+            repo.insert(MenuItem::new(2, "MARGHERITA", 500)),
+            Err(MenuError::Conflict)
+        );
+    }
+
+    #[test]
+    fn it_should_update_fetch_and_delete_items() {
+        let repo = InMemoryMenuRepository::new();
+        repo.insert(MenuItem::new(2, "Tiramisu", 500)).unwrap();
+        repo.insert(MenuItem::new(1, "Margherita", 950)).unwrap();
+
+        let mut updated = MenuItem::new(1, "Margherita", 1050);
+        updated.available = false;
+        assert_eq!(repo.update(updated.clone()), Ok(updated.clone()));
+        assert_eq!(
+            repo.update(MenuItem::new(3, "Lasagna", 1200)),
+            Err(MenuError::UnknownItemId)
+        );
+
+        let ids: Vec<MenuItemId> = repo.fetch_all().unwrap().iter().map(|it| it.id).collect();
+        assert_eq!(ids, vec![MenuItemId::from_int(1), MenuItemId::from_int(2)]);
+        assert_eq!(repo.fetch_one(MenuItemId::from_int(1)), Ok(updated));
+
+        assert_eq!(repo.delete(MenuItemId::from_int(1)), Ok(()));
+        assert_eq!(
+            repo.fetch_one(MenuItemId::from_int(1)),
+            Err(MenuError::UnknownItemId)
+        );
+        assert_eq!(
+            repo.delete(MenuItemId::from_int(1)),
+            Err(MenuError::UnknownItemId)
+        );
+    }
+}
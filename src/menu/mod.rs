@@ -0,0 +1,252 @@
+pub mod inmemory;
+pub mod sqlite;
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::domain::types::{IdType, ItemName};
+
+// Menu of the restaurant: the dishes that can be ordered with their category,
+// price and availability. Dish names are unique, compared case-insensitively.
+
+// Prices are kept in cents
+pub type PriceType = u32;
+
+#[derive(PartialEq, Hash, Eq, Debug, Serialize, Copy, Clone, PartialOrd, Ord)]
+pub struct MenuItemId(IdType);
+
+impl TryFrom<String> for MenuItemId {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.parse::<IdType>() {
+            Ok(id) if id > 0 => Ok(Self(id)),
+            _ => Err(format!("'{}' is not a valid menu item id.", s)),
+        }
+    }
+}
+
+impl From<MenuItemId> for IdType {
+    fn from(value: MenuItemId) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for MenuItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MenuCategory(String);
+
+impl TryFrom<String> for MenuCategory {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_empty_or_whitespace = s.trim().is_empty();
+        let is_too_long = s.graphemes(true).count() > 50;
+
+        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+
+        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
+            Err(format!("'{}' is not a valid menu category.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl From<MenuCategory> for String {
+    fn from(c: MenuCategory) -> Self {
+        c.0
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct MenuPrice(PriceType);
+
+impl TryFrom<String> for MenuPrice {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.parse::<PriceType>() {
+            Ok(cents) => Ok(Self(cents)),
+            _ => Err(format!("'{}' is not a valid price in cents.", s)),
+        }
+    }
+}
+
+impl From<MenuPrice> for PriceType {
+    fn from(value: MenuPrice) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MenuItem {
+    pub id: MenuItemId,
+    pub name: ItemName,
+    pub category: MenuCategory,
+    pub price: MenuPrice,
+    pub available: bool,
+}
+
+impl MenuItem {
+    // Key used to compare dish names
+    pub fn name_key(name: &ItemName) -> String {
+        String::from(name.clone()).to_ascii_lowercase()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MenuError {
+    // Another dish already has this id or name
+    Conflict,
+    UnknownItemId,
+    Unknown,
+}
+
+pub trait MenuRepository: Send + Sync {
+    fn insert(&self, item: MenuItem) -> Result<MenuItem, MenuError>;
+    fn update(&self, item: MenuItem) -> Result<MenuItem, MenuError>;
+    // Ordered by id
+    fn fetch_all(&self) -> Result<Vec<MenuItem>, MenuError>;
+    fn fetch_one(&self, id: MenuItemId) -> Result<MenuItem, MenuError>;
+    fn delete(&self, id: MenuItemId) -> Result<(), MenuError>;
+    // Called once the server stopped serving, writes out what is still pending
+    fn shutdown(&self) -> Result<(), MenuError>;
+}
+
+// Runs a menu repository call on the blocking pool, as AsyncRepository does for items
+pub async fn run<T, F>(repo: Arc<dyn MenuRepository>, call: F) -> Result<T, MenuError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn MenuRepository) -> Result<T, MenuError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || call(repo.as_ref()))
+        .await
+        .unwrap_or(Err(MenuError::Unknown))
+}
+
+// Whether ordered item names must be on the menu
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MenuPolicy {
+    pub strict: bool,
+}
+
+// Names of the dishes that can be ordered, used to validate orders in strict mode
+#[derive(Debug, Default)]
+pub struct MenuNames(HashSet<String>);
+
+impl MenuNames {
+    pub fn from_items(items: &[MenuItem]) -> Self {
+        Self(
+            items
+                .iter()
+                .filter(|item| item.available)
+                .map(|item| MenuItem::name_key(&item.name))
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, name: &ItemName) -> bool {
+        self.0.contains(&MenuItem::name_key(name))
+    }
+}
+
+// Prices of the dishes on the menu by name, used to bill a table
+#[derive(Debug, Default)]
+pub struct MenuPrices(HashMap<String, PriceType>);
+
+impl MenuPrices {
+    pub fn from_items(items: &[MenuItem]) -> Self {
+        Self(
+            items
+                .iter()
+                .map(|item| (MenuItem::name_key(&item.name), PriceType::from(item.price)))
+                .collect(),
+        )
+    }
+
+    pub fn price_of(&self, name: &ItemName) -> Option<PriceType> {
+        self.0.get(&MenuItem::name_key(name)).copied()
+    }
+}
+
+// Names to validate ordered items against, None unless the policy is strict
+pub async fn names_for_orders(
+    policy: MenuPolicy,
+    repo: Option<Arc<dyn MenuRepository>>,
+) -> Result<Option<MenuNames>, MenuError> {
+    match (policy.strict, repo) {
+        (false, _) => Ok(None),
+        (true, Some(repo)) => {
+            let items = run(repo, |repo| repo.fetch_all()).await?;
+            Ok(Some(MenuNames::from_items(&items)))
+        }
+        (true, None) => Err(MenuError::Unknown),
+    }
+}
+
+#[cfg(test)]
+impl MenuItem {
+    pub fn new(id: IdType, name: &str, price: PriceType) -> Self {
+        Self {
+            id: MenuItemId(id),
+            name: ItemName::from_str(name.to_string()),
+            category: MenuCategory("Mains".to_string()),
+            price: MenuPrice(price),
+            available: true,
+        }
+    }
+}
+
+#[cfg(test)]
+impl MenuItemId {
+    pub fn from_int(number: IdType) -> Self {
+        Self(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn menu_item_ids_must_be_positive_numbers() {
+        assert_ok!(MenuItemId::try_from("3".to_string()));
+        for id in &["0", "-1", "abc"] {
+            assert_err!(MenuItemId::try_from(id.to_string()));
+        }
+    }
+
+    #[test]
+    fn menu_categories_are_validated() {
+        assert_ok!(MenuCategory::try_from("Desserts".to_string()));
+        assert_err!(MenuCategory::try_from(" ".to_string()));
+        assert_err!(MenuCategory::try_from("a".repeat(51)));
+    }
+
+    #[test]
+    fn menu_names_are_compared_case_insensitively() {
+        let names = MenuNames::from_items(&[MenuItem::new(1, "Margherita", 950)]);
+        assert!(names.contains(&ItemName::from_str("margherita".to_string())));
+        assert!(!names.contains(&ItemName::from_str("Margarita".to_string())));
+    }
+
+    #[test]
+    fn unavailable_dishes_are_not_orderable() {
+        let mut sold_out = MenuItem::new(2, "Calzone", 1100);
+        sold_out.available = false;
+        let names = MenuNames::from_items(&[MenuItem::new(1, "Margherita", 950), sold_out]);
+        assert!(names.contains(&ItemName::from_str("Margherita".to_string())));
+        assert!(!names.contains(&ItemName::from_str("Calzone".to_string())));
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, Error::SqliteFailure, ErrorCode, Row};
+
+use crate::domain::types::{IdType, ItemName};
+use crate::menu::{
+    MenuCategory, MenuError, MenuItem, MenuItemId, MenuPrice, MenuRepository, PriceType,
+};
+use crate::repository::sqlite::SqliteRepository;
+use crate::repository::{migrations, BUSY_TIMEOUT};
+
+// An Sqlite menu implementation, the menu_item table is created by the migrations
+
+pub struct SqliteMenuRepository {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteMenuRepository {
+    // Opens the database, applying pending migrations when apply_migrations is set
+    pub fn try_new(path: &str, apply_migrations: bool) -> Result<Self, ()> {
+        let mut connection = match Connection::open(path) {
+            Ok(connection) => connection,
+            _ => return Err(()),
+        };
+        if connection.busy_timeout(BUSY_TIMEOUT).is_err() {
+            return Err(());
+        }
+        match migrations::prepare(&mut connection, apply_migrations) {
+            Ok(_) => Ok(Self {
+                connection: Mutex::new(connection),
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+fn to_menu_item(row: &Row) -> rusqlite::Result<Result<MenuItem, String>> {
+    let id: IdType = row.get(0)?;
+    let name: String = row.get(1)?;
+    let category: String = row.get(2)?;
+    let price: PriceType = row.get(3)?;
+    let available: bool = row.get(4)?;
+    Ok(
+        match (
+            MenuItemId::try_from(id.to_string()),
+            ItemName::try_from(name),
+            MenuCategory::try_from(category),
+            MenuPrice::try_from(price.to_string()),
+        ) {
+            (Ok(id), Ok(name), Ok(category), Ok(price)) => Ok(MenuItem {
+                id,
+                name,
+                category,
+                price,
+                available,
+            }),
+            _ => Err(format!("Invalid menu item row with id {}", id)),
+        },
+    )
+}
+
+fn write_error(err: rusqlite::Error) -> MenuError {
+    match err {
+        SqliteFailure(failure, _) if failure.code == ErrorCode::ConstraintViolation => {
+            MenuError::Conflict
+        }
+        _ => MenuError::Unknown,
+    }
+}
+
+impl MenuRepository for SqliteMenuRepository {
+    fn insert(&self, item: MenuItem) -> Result<MenuItem, MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match connection.execute(
+            "insert into menu_item (id, name, category, price, available) values (?, ?, ?, ?, ?)",
+            params![
+                IdType::from(item.id),
+                String::from(item.name.clone()),
+                String::from(item.category.clone()),
+                PriceType::from(item.price),
+                item.available
+            ],
+        ) {
+            Ok(_) => Ok(item),
+            Err(err) => Err(write_error(err)),
+        }
+    }
+
+    fn update(&self, item: MenuItem) -> Result<MenuItem, MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match connection.execute(
+            "update menu_item set name = ?, category = ?, price = ?, available = ? where id = ?",
+            params![
+                String::from(item.name.clone()),
+                String::from(item.category.clone()),
+                PriceType::from(item.price),
+                item.available,
+                IdType::from(item.id)
+            ],
+        ) {
+            Ok(0) => Err(MenuError::UnknownItemId),
+            Ok(_) => Ok(item),
+            Err(err) => Err(write_error(err)),
+        }
+    }
+
+    fn fetch_all(&self) -> Result<Vec<MenuItem>, MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        let mut stmt = match connection
+            .prepare("select id, name, category, price, available from menu_item order by id")
+        {
+            Ok(stmt) => stmt,
+            _ => return Err(MenuError::Unknown),
+        };
+        let rows = match stmt.query_map([], to_menu_item) {
+            Ok(rows) => rows,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        let mut items = vec![];
+        for row in rows {
+            match row {
+                Ok(Ok(item)) => items.push(item),
+                _ => return Err(MenuError::Unknown),
+            }
+        }
+        Ok(items)
+    }
+
+    fn fetch_one(&self, id: MenuItemId) -> Result<MenuItem, MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match connection.query_row(
+            "select id, name, category, price, available from menu_item where id = ?",
+            params![IdType::from(id)],
+            to_menu_item,
+        ) {
+            Ok(Ok(item)) => Ok(item),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(MenuError::UnknownItemId),
+            _ => Err(MenuError::Unknown),
+        }
+    }
+
+    fn delete(&self, id: MenuItemId) -> Result<(), MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match connection.execute(
+            "delete from menu_item where id = ?",
+            params![IdType::from(id)],
+        ) {
+            Ok(0) => Err(MenuError::UnknownItemId),
+            Ok(_) => Ok(()),
+            _ => Err(MenuError::Unknown),
+        }
+    }
+
+    // The connection itself is closed when the repository is dropped
+    fn shutdown(&self) -> Result<(), MenuError> {
+        let connection = match self.connection.lock() {
+            Ok(connection) => connection,
+            _ => return Err(MenuError::Unknown),
+        };
+
+        match SqliteRepository::checkpoint(&connection) {
+            Ok(()) => Ok(()),
+            _ => Err(MenuError::Unknown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn repo() -> SqliteMenuRepository {
+        match SqliteMenuRepository::try_new("", true) {
+            Ok(repo) => repo,
+            _ => panic!("Error while creating sqlite menu repo"),
+        }
+    }
+
+    #[test]
+    fn it_should_keep_ids_and_names_unique() {
+        let repo = repo();
+        assert!(repo.insert(MenuItem::new(1, "Margherita", 950)).is_ok());
+
+        assert_eq!(
+            repo.insert(MenuItem::new(1, "Tiramisu", 500)),
+            Err(MenuError::Conflict)
+        );
+        assert_eq!(
+            repo.insert(MenuItem::new(2, "MARGHERITA", 500)),
+            Err(MenuError::Conflict)
+        );
+    }
+
+    #[test]
+    fn it_should_update_fetch_and_delete_items() {
+        let repo = repo();
+        repo.insert(MenuItem::new(2, "Tiramisu", 500)).unwrap();
+        repo.insert(MenuItem::new(1, "Margherita", 950)).unwrap();
+
+        let mut updated = MenuItem::new(1, "Margherita", 1050);
+        updated.available = false;
+        assert_eq!(repo.update(updated.clone()), Ok(updated.clone()));
+        assert_eq!(
+            repo.update(MenuItem::new(1, "tiramisu", 500)),
+            Err(MenuError::Conflict)
+        );
+        assert_eq!(
+            repo.update(MenuItem::new(3, "Lasagna", 1200)),
+            Err(MenuError::UnknownItemId)
+        );
+
+        let ids: Vec<MenuItemId> = repo.fetch_all().unwrap().iter().map(|it| it.id).collect();
+        assert_eq!(ids, vec![MenuItemId::from_int(1), MenuItemId::from_int(2)]);
+        assert_eq!(repo.fetch_one(MenuItemId::from_int(1)), Ok(updated));
+
+        assert_eq!(repo.delete(MenuItemId::from_int(1)), Ok(()));
+        assert_eq!(
+            repo.fetch_one(MenuItemId::from_int(1)),
+            Err(MenuError::UnknownItemId)
+        );
+        assert_eq!(
+            repo.delete(MenuItemId::from_int(1)),
+            Err(MenuError::UnknownItemId)
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::domain::types::{
+    Bill, IdType, IdempotencyKey, Item, ItemFilter, ItemId, ItemName, ItemNotes, ItemPage,
+    ItemPatch, ItemQuantity, ItemSort, ItemState, ItemVersion, KeyClaim, PrepTime, QuantityType,
+    QueuedItem, ReceiptId, StoredResponse, TableId, VersionType,
+};
+use crate::repository::*;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+// An async adapter over the synchronous Repository. Every call runs on tokio's
+// blocking thread pool, so a slow storage call doesn't stall the runtime workers
+// serving other requests. A panicking call is reported as an unknown error.
+
+#[derive(Clone)]
+pub struct AsyncRepository {
+    inner: Arc<dyn Repository>,
+}
+
+impl AsyncRepository {
+    pub fn new(inner: Arc<dyn Repository>) -> Self {
+        Self { inner }
+    }
+
+    async fn run<T, F>(&self, call: F) -> Option<T>
+    where
+        F: FnOnce(&dyn Repository) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || call(inner.as_ref()))
+            .await
+            .ok()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, InsertError> {
+        self.run(move |repo| {
+            repo.insert(
+                table_id,
+                item_id,
+                item_name,
+                item_notes,
+                item_quantity,
+                item_deleted,
+                item_version,
+                item_prep_time,
+            )
+        })
+        .await
+        .unwrap_or(Err(InsertError::Unknown))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, UpdateError> {
+        self.run(move |repo| {
+            repo.update(
+                table_id,
+                item_id,
+                item_name,
+                item_notes,
+                item_quantity,
+                item_deleted,
+                item_version,
+                item_prep_time,
+            )
+        })
+        .await
+        .unwrap_or(Err(UpdateError::Unknown))
+    }
+
+    pub async fn fetch_all(
+        &self,
+        table_id: TableId<IdType>,
+        include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
+    ) -> Result<Vec<Item>, FetchAllError> {
+        self.run(move |repo| repo.fetch_all(table_id, include_deleted, filter, sort_by, page))
+            .await
+            .unwrap_or(Err(FetchAllError::Unknown))
+    }
+
+    pub async fn fetch_active_across_tables(
+        &self,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError> {
+        self.run(move |repo| repo.fetch_active_across_tables(ready_by))
+            .await
+            .unwrap_or(Err(FetchAllError::Unknown))
+    }
+
+    pub async fn fetch_one(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, FetchOneError> {
+        self.run(move |repo| repo.fetch_one(table_id, item_id))
+            .await
+            .unwrap_or(Err(FetchOneError::Unknown))
+    }
+
+    pub async fn update_partial(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError> {
+        self.run(move |repo| repo.update_partial(table_id, item_id, patch, item_version))
+            .await
+            .unwrap_or(Err(UpdateError::Unknown))
+    }
+
+    pub async fn fetch_history(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError> {
+        self.run(move |repo| repo.fetch_history(table_id, item_id))
+            .await
+            .unwrap_or(Err(FetchOneError::Unknown))
+    }
+
+    pub async fn restore(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError> {
+        self.run(move |repo| repo.restore(table_id, item_id))
+            .await
+            .unwrap_or(Err(RestoreError::Unknown))
+    }
+
+    pub async fn transition(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError> {
+        self.run(move |repo| repo.transition(table_id, item_id, state, item_version))
+            .await
+            .unwrap_or(Err(TransitionError::Unknown))
+    }
+
+    pub async fn delete(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<(), DeleteError> {
+        self.run(move |repo| repo.delete(table_id, item_id, item_version))
+            .await
+            .unwrap_or(Err(DeleteError::Unknown))
+    }
+
+    pub async fn apply_batch(
+        &self,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError> {
+        self.run(move |repo| repo.apply_batch(table_id, ops))
+            .await
+            .unwrap_or(Err(BatchError::Unknown))
+    }
+
+    pub async fn claim_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError> {
+        self.run(move |repo| repo.claim_idempotency_key(key, request_hash, now, expires_at))
+            .await
+            .unwrap_or(Err(IdempotencyError::Unknown))
+    }
+
+    pub async fn complete_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError> {
+        self.run(move |repo| repo.complete_idempotency_key(key, response))
+            .await
+            .unwrap_or(Err(IdempotencyError::Unknown))
+    }
+
+    pub async fn release_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+    ) -> Result<(), IdempotencyError> {
+        self.run(move |repo| repo.release_idempotency_key(key))
+            .await
+            .unwrap_or(Err(IdempotencyError::Unknown))
+    }
+
+    pub async fn checkout(&self, bill: Bill) -> Result<ReceiptId, CheckoutError> {
+        self.run(move |repo| repo.checkout(bill))
+            .await
+            .unwrap_or(Err(CheckoutError::Unknown))
+    }
+
+    pub async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<usize, PurgeError> {
+        self.run(move |repo| repo.purge_deleted(deleted_before))
+            .await
+            .unwrap_or(Err(PurgeError::Unknown))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repository::inmemory::InMemoryRepository;
+
+    #[tokio::test]
+    async fn it_should_not_block_the_runtime_during_a_slow_call() {
+        let repo = AsyncRepository::new(Arc::new(
+            InMemoryRepository::new().with_delay(std::time::Duration::from_millis(300)),
+        ));
+
+        // On a current thread runtime the ticker only advances if the slow call yields
+        let ticker = tokio::spawn(async {
+            let mut ticks = 0;
+            while ticks < 5 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                ticks += 1;
+            }
+            ticks
+        });
+        let res = repo
+            .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+            .await;
+
+        assert!(ticker.is_finished());
+        assert_eq!(ticker.await.unwrap(), 5);
+        match res {
+            Err(FetchOneError::UnknownTableId) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_forward_calls_to_the_inner_repository() {
+        let repo = AsyncRepository::new(Arc::new(InMemoryRepository::new()));
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .await
+        .ok();
+
+        match repo
+            .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+            .await
+        {
+            Ok(item) => assert_eq!(item.name, ItemName::pizza()),
+            _ => unreachable!(),
+        }
+    }
+}
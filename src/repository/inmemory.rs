@@ -1,24 +1,71 @@
+use crate::audit::inmemory::InMemoryAuditRepository;
+use crate::audit::{self, AuditAction, AuditError, AuditRepository, Caller};
 use crate::domain::types::{
-    IdType, Item, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, QuantityType, TableId,
-    VersionType,
+    now_in_seconds, Bill, IdType, IdempotencyKey, Item, ItemFilter, ItemId, ItemName, ItemNotes,
+    ItemPage, ItemPatch, ItemQuantity, ItemSort, ItemState, ItemVersion, KeyClaim, PrepTime,
+    QuantityType, QueuedItem, ReceiptId, StoredResponse, TableId, VersionType,
 };
 use crate::repository::*;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 // In memory repository implementation based on HashMap
 
+// Items of every table, deleted ones included
+type Tables = HashMap<TableId<IdType>, Vec<Item>>;
+
 pub struct InMemoryRepository {
     error: bool,
-    items: Mutex<HashMap<TableId<IdType>, Vec<Item>>>,
+    delay: Option<Duration>,
+    items: Arc<Mutex<Tables>>,
+    // Recorded bills, the receipt id is the position in the list plus one
+    bills: Arc<Mutex<Vec<Bill>>>,
+    idempotency_keys: Arc<Mutex<HashMap<IdempotencyKey, KeyRecord>>>,
+    audit_log: Arc<InMemoryAuditRepository>,
+    // Who the changes are logged as made by, nothing is logged without one
+    caller: Option<Caller>,
+}
+
+// The response is None while the first request with the key runs
+struct KeyRecord {
+    request_hash: String,
+    response: Option<StoredResponse>,
+    expires_at: DateTime<Utc>,
 }
 
 impl InMemoryRepository {
     pub fn new() -> Self {
-        let items: Mutex<HashMap<TableId<IdType>, Vec<Item>>> = Mutex::new(HashMap::new());
+        let items: Mutex<Tables> = Mutex::new(HashMap::new());
         Self {
             error: false,
-            items,
+            delay: None,
+            items: Arc::new(items),
+            bills: Arc::new(Mutex::new(vec![])),
+            idempotency_keys: Arc::new(Mutex::new(HashMap::new())),
+            audit_log: Arc::new(InMemoryAuditRepository::new()),
+            caller: None,
+        }
+    }
+
+    // The audit log the changes are appended to
+    pub fn audit_log(&self) -> Arc<InMemoryAuditRepository> {
+        self.audit_log.clone()
+    }
+
+    // Appends the change to the audit log while the items lock is held, when it is
+    // made for a caller
+    fn audit(
+        &self,
+        table_id: TableId<IdType>,
+        action: AuditAction,
+        old: Option<&Item>,
+        new: Option<&Item>,
+    ) -> Result<(), AuditError> {
+        match audit::entry_for(self.caller.as_ref(), table_id, action, old, new) {
+            Some(entry) => self.audit_log.append(entry),
+            None => Ok(()),
         }
     }
 
@@ -29,6 +76,143 @@ impl InMemoryRepository {
             ..self
         }
     }
+
+    // Makes fetches block the calling thread, like a slow storage would
+    #[cfg(test)]
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Self {
+            delay: Some(delay),
+            ..self
+        }
+    }
+
+    fn simulate_delay(&self) {
+        if let Some(delay) = self.delay {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+// A re-created item continues after the versions of its deleted copies
+fn insert_into(
+    tables: &mut Tables,
+    table_id: TableId<IdType>,
+    mut item: Item,
+) -> Result<Item, InsertError> {
+    let items = tables.entry(table_id).or_default();
+
+    if items.iter().any(|it| it.id == item.id && !it.deleted) {
+        return Err(InsertError::Conflict);
+    }
+    let last_version = items
+        .iter()
+        .filter(|it| it.id == item.id)
+        .map(|it| it.version.clone())
+        .reduce(|a, b| if a > b { a } else { b });
+    if let Some(last_version) = last_version.filter(|last| *last >= item.version) {
+        item.version = last_version + ItemVersion::from_int(1);
+    }
+
+    items.push(item.clone());
+    Ok(item)
+}
+
+fn find_active(
+    tables: &Tables,
+    table_id: TableId<IdType>,
+    item_id: ItemId<IdType>,
+) -> Option<Item> {
+    tables
+        .get(&table_id)?
+        .iter()
+        .find(|it| it.id == item_id && !it.deleted)
+        .cloned()
+}
+
+// The version of the given item is the one the caller read
+fn update_in(
+    tables: &mut Tables,
+    table_id: TableId<IdType>,
+    mut item: Item,
+) -> Result<Item, UpdateError> {
+    let items = match tables.get_mut(&table_id) {
+        Some(items) => items,
+        None => return Err(UpdateError::UnknownTableId),
+    };
+
+    let pos = match items.iter().position(|it| it.id == item.id && !it.deleted) {
+        Some(pos) => pos,
+        None => return Err(UpdateError::UnknownItemId),
+    };
+
+    if items[pos].version > item.version {
+        return Err(UpdateError::VersionConflict(items[pos].clone()));
+    }
+
+    let mut current_version = items[pos].version.clone();
+
+    current_version += ItemVersion::from_int(1);
+
+    item.version = current_version;
+    // Changing the order doesn't move it through the kitchen
+    item.state = items[pos].state;
+    items[pos] = item.clone();
+    Ok(item)
+}
+
+fn patch_in(
+    tables: &mut Tables,
+    table_id: TableId<IdType>,
+    item_id: ItemId<IdType>,
+    patch: ItemPatch,
+    item_version: ItemVersion<VersionType>,
+) -> Result<Item, UpdateError> {
+    let current = match tables.get(&table_id) {
+        Some(items) => items.iter().find(|it| it.id == item_id && !it.deleted),
+        None => return Err(UpdateError::UnknownTableId),
+    };
+    let mut item = match current {
+        Some(current) => patch.apply_to(current),
+        None => return Err(UpdateError::UnknownItemId),
+    };
+
+    item.version = item_version;
+    update_in(tables, table_id, item)
+}
+
+// Deleting bumps the version, no two copies of an item share one
+fn cancel(item: &mut Item) {
+    item.deleted = true;
+    item.state = ItemState::Cancelled;
+    item.deleted_at = Some(now_in_seconds());
+    item.version += ItemVersion::from_int(1);
+}
+
+fn delete_from(
+    tables: &mut Tables,
+    table_id: TableId<IdType>,
+    item_id: ItemId<IdType>,
+    item_version: Option<ItemVersion<VersionType>>,
+) -> Result<(), DeleteError> {
+    let items = match tables.get_mut(&table_id) {
+        Some(items) => items,
+        None => return Err(DeleteError::UnknownTableId),
+    };
+
+    match items.iter().position(|p| p.id == item_id && !p.deleted) {
+        Some(index)
+            if item_version
+                .as_ref()
+                .is_some_and(|version| &items[index].version != version) =>
+        {
+            Err(DeleteError::VersionConflict)
+        }
+        Some(index) => {
+            cancel(&mut items[index]);
+            Ok(())
+        }
+        None => Err(DeleteError::UnknownItemId),
+    }
 }
 
 impl Repository for InMemoryRepository {
@@ -41,7 +225,7 @@ impl Repository for InMemoryRepository {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, InsertError> {
         if self.error {
             return Err(InsertError::Unknown);
@@ -52,28 +236,23 @@ impl Repository for InMemoryRepository {
             _ => return Err(InsertError::Unknown),
         };
 
-        if !lock.contains_key(&table_id) {
-            lock.insert(table_id, vec![]);
+        let item = insert_into(
+            &mut lock,
+            table_id,
+            Item::new(
+                item_id,
+                item_name,
+                item_notes,
+                item_quantity,
+                item_deleted,
+                item_version,
+                item_prep_time,
+            ),
+        )?;
+        match self.audit(table_id, AuditAction::Insert, None, Some(&item)) {
+            Ok(_) => Ok(item),
+            _ => Err(InsertError::Unknown),
         }
-
-        if lock[&table_id]
-            .iter()
-            .any(|item| item.id == item_id && item.deleted == false)
-        {
-            return Err(InsertError::Conflict);
-        }
-
-        let item = Item::new(
-            item_id,
-            item_name,
-            item_notes,
-            item_quantity,
-            item_deleted,
-            item_version,
-            item_time_to_prepare,
-        );
-        lock.get_mut(&table_id).unwrap().push(item.clone());
-        Ok(item)
     }
 
     fn update(
@@ -85,7 +264,7 @@ impl Repository for InMemoryRepository {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, UpdateError> {
         if self.error {
             return Err(UpdateError::Unknown);
@@ -96,44 +275,35 @@ impl Repository for InMemoryRepository {
             _ => return Err(UpdateError::Unknown),
         };
 
-        if !lock.contains_key(&table_id) {
-            return Err(UpdateError::UnknownTableId);
+        let old = find_active(&lock, table_id, item_id);
+        let item = update_in(
+            &mut lock,
+            table_id,
+            Item::new(
+                item_id,
+                item_name,
+                item_notes,
+                item_quantity,
+                item_deleted,
+                item_version,
+                item_prep_time,
+            ),
+        )?;
+        match self.audit(table_id, AuditAction::Update, old.as_ref(), Some(&item)) {
+            Ok(_) => Ok(item),
+            _ => Err(UpdateError::Unknown),
         }
-
-        let pos = match lock[&table_id]
-            .iter()
-            .position(|item| item.id == item_id && item.deleted == false)
-        {
-            Some(pos) => pos,
-            None => return Err(UpdateError::UnknownItemId),
-        };
-
-        if lock.get_mut(&table_id).unwrap()[pos].version > item_version {
-            return Err(UpdateError::VersionConflict);
-        }
-
-        let mut current_version = lock.get_mut(&table_id).unwrap()[pos].version.clone();
-
-        current_version += ItemVersion::from_int(1);
-
-        let item = Item::new(
-            item_id,
-            item_name,
-            item_notes,
-            item_quantity,
-            item_deleted,
-            current_version,
-            item_time_to_prepare,
-        );
-        lock.get_mut(&table_id).unwrap()[pos] = item.clone();
-        Ok(item)
     }
 
     fn fetch_all(
         &self,
         table_id: TableId<IdType>,
-        _include_deleted: bool,
+        include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
     ) -> Result<Vec<Item>, FetchAllError> {
+        self.simulate_delay();
         if self.error {
             return Err(FetchAllError::Unknown);
         }
@@ -147,13 +317,31 @@ impl Repository for InMemoryRepository {
             return Err(FetchAllError::UnknownTableId);
         }
 
-        let cond = match _include_deleted {
-            true => |_: &&Item| true,
-            false => |it: &&Item| it.deleted == false,
-        };
-        let mut items: Vec<_> = lock[&table_id].iter().filter(cond).cloned().collect();
-        items.sort_by(|a, b| a.id.to_string().cmp(&b.id.to_string()));
-        Ok(items)
+        let mut items: Vec<_> = lock[&table_id]
+            .iter()
+            .filter(|it| include_deleted || !it.deleted)
+            .filter(|it| filter.matches(it))
+            .cloned()
+            .collect();
+
+        match page {
+            Some(page) => {
+                items
+                    .sort_by_key(|it| (IdType::from(it.id), VersionType::from(it.version.clone())));
+                Ok(items
+                    .into_iter()
+                    .filter(|it| match &page.after {
+                        Some(cursor) => cursor.is_before(it),
+                        None => true,
+                    })
+                    .take(page.limit)
+                    .collect())
+            }
+            None => {
+                items.sort_by(|a, b| sort_by.compare(a, b));
+                Ok(items)
+            }
+        }
     }
 
     fn fetch_one(
@@ -161,6 +349,7 @@ impl Repository for InMemoryRepository {
         table_id: TableId<IdType>,
         item_id: ItemId<IdType>,
     ) -> Result<Item, FetchOneError> {
+        self.simulate_delay();
         if self.error {
             return Err(FetchOneError::Unknown);
         }
@@ -176,17 +365,201 @@ impl Repository for InMemoryRepository {
 
         match lock[&table_id]
             .iter()
-            .find(|p| p.id == item_id && p.deleted == false)
+            .find(|p| p.id == item_id && !p.deleted)
         {
             Some(item) => Ok(item.clone()),
             None => Err(FetchOneError::UnknownItemId),
         }
     }
 
+    fn fetch_active_across_tables(
+        &self,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError> {
+        self.simulate_delay();
+        if self.error {
+            return Err(FetchAllError::Unknown);
+        }
+
+        let lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchAllError::Unknown),
+        };
+
+        let mut queue: Vec<QueuedItem> = lock
+            .iter()
+            .flat_map(|(table_id, items)| {
+                items.iter().map(|item| QueuedItem {
+                    table_id: *table_id,
+                    item: item.clone(),
+                })
+            })
+            .filter(|queued| !queued.item.deleted && queued.item.state.in_kitchen())
+            .filter(|queued| ready_by.is_none_or(|by| queued.item.prep_time.ready_at <= by))
+            .collect();
+        queue.sort_by_key(|queued| {
+            (
+                queued.item.prep_time.ready_at,
+                IdType::from(queued.table_id),
+                IdType::from(queued.item.id),
+            )
+        });
+        Ok(queue)
+    }
+
+    fn update_partial(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError> {
+        if self.error {
+            return Err(UpdateError::Unknown);
+        }
+
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        let old = find_active(&lock, table_id, item_id);
+        let item = patch_in(&mut lock, table_id, item_id, patch, item_version)?;
+        match self.audit(table_id, AuditAction::Update, old.as_ref(), Some(&item)) {
+            Ok(_) => Ok(item),
+            _ => Err(UpdateError::Unknown),
+        }
+    }
+
+    fn fetch_history(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError> {
+        self.simulate_delay();
+        if self.error {
+            return Err(FetchOneError::Unknown);
+        }
+
+        let lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        // items are kept in the order they were inserted
+        let items: Vec<Item> = match lock.get(&table_id) {
+            Some(items) => items
+                .iter()
+                .filter(|it| it.id == item_id)
+                .cloned()
+                .collect(),
+            None => return Err(FetchOneError::UnknownTableId),
+        };
+        match items.is_empty() {
+            true => Err(FetchOneError::UnknownItemId),
+            false => Ok(items),
+        }
+    }
+
+    fn restore(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError> {
+        if self.error {
+            return Err(RestoreError::Unknown);
+        }
+
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(RestoreError::Unknown),
+        };
+
+        let items = match lock.get_mut(&table_id) {
+            Some(items) => items,
+            None => return Err(RestoreError::UnknownTableId),
+        };
+        if items.iter().any(|it| it.id == item_id && !it.deleted) {
+            return Err(RestoreError::AlreadyActive);
+        }
+        let item = match items.iter_mut().rev().find(|it| it.id == item_id) {
+            Some(item) => item,
+            None => return Err(RestoreError::UnknownItemId),
+        };
+        if item.state == ItemState::Paid {
+            return Err(RestoreError::Paid);
+        }
+        let old = item.clone();
+        item.deleted = false;
+        item.state = ItemState::Ordered;
+        item.deleted_at = None;
+        item.version += ItemVersion::from_int(1);
+        let item = item.clone();
+
+        match self.audit(table_id, AuditAction::Restore, Some(&old), Some(&item)) {
+            Ok(_) => Ok(item),
+            _ => Err(RestoreError::Unknown),
+        }
+    }
+
+    fn transition(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError> {
+        if self.error {
+            return Err(TransitionError::Unknown);
+        }
+
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(TransitionError::Unknown),
+        };
+
+        let item = match lock.get_mut(&table_id) {
+            Some(items) => match items.iter_mut().find(|it| it.id == item_id && !it.deleted) {
+                Some(item) => item,
+                None => return Err(TransitionError::UnknownItemId),
+            },
+            None => return Err(TransitionError::UnknownTableId),
+        };
+        if item_version
+            .as_ref()
+            .is_some_and(|version| &item.version != version)
+        {
+            return Err(TransitionError::VersionConflict(item.clone()));
+        }
+        if !item.state.can_become(state) {
+            return Err(TransitionError::Invalid(item.clone()));
+        }
+
+        let old = item.clone();
+        let (action, new) = match state {
+            ItemState::Cancelled => {
+                cancel(item);
+                (AuditAction::Delete, None)
+            }
+            _ => {
+                item.state = state;
+                item.version += ItemVersion::from_int(1);
+                (AuditAction::Update, Some(item.clone()))
+            }
+        };
+        let item = item.clone();
+
+        match self.audit(table_id, action, Some(&old), new.as_ref()) {
+            Ok(_) => Ok(item),
+            _ => Err(TransitionError::Unknown),
+        }
+    }
+
     fn delete(
         &self,
         table_id: TableId<IdType>,
         item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
     ) -> Result<(), DeleteError> {
         if self.error {
             return Err(DeleteError::Unknown);
@@ -197,19 +570,231 @@ impl Repository for InMemoryRepository {
             _ => return Err(DeleteError::Unknown),
         };
 
-        if !lock.contains_key(&table_id) {
-            return Err(DeleteError::UnknownTableId);
+        let old = find_active(&lock, table_id, item_id);
+        delete_from(&mut lock, table_id, item_id, item_version)?;
+        match self.audit(table_id, AuditAction::Delete, old.as_ref(), None) {
+            Ok(_) => Ok(()),
+            _ => Err(DeleteError::Unknown),
+        }
+    }
+
+    fn apply_batch(
+        &self,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError> {
+        if self.error {
+            return Err(BatchError::Unknown);
         }
 
-        let index = match lock[&table_id]
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(BatchError::Unknown),
+        };
+
+        // Work on a copy of the table, it replaces the table only if every operation succeeds
+        let mut scratch: Tables = HashMap::new();
+        if let Some(items) = lock.get(&table_id) {
+            scratch.insert(table_id, items.clone());
+        }
+        let mut results = vec![];
+        let mut changes = vec![];
+        for op in ops {
+            let (action, old) = match &op {
+                BatchOp::Insert(_) => (AuditAction::Insert, None),
+                BatchOp::Update(item) => (
+                    AuditAction::Update,
+                    find_active(&scratch, table_id, item.id),
+                ),
+                BatchOp::Delete(item_id) => (
+                    AuditAction::Delete,
+                    find_active(&scratch, table_id, *item_id),
+                ),
+            };
+            let result = match op {
+                BatchOp::Insert(item) => {
+                    let id = item.id;
+                    insert_into(&mut scratch, table_id, item)
+                        .map(Some)
+                        .map_err(|err| BatchError::Insert(id, err))
+                }
+                BatchOp::Update(item) => {
+                    let id = item.id;
+                    update_in(&mut scratch, table_id, item)
+                        .map(Some)
+                        .map_err(|err| BatchError::Update(id, err))
+                }
+                BatchOp::Delete(item_id) => delete_from(&mut scratch, table_id, item_id, None)
+                    .map(|_| None)
+                    .map_err(|err| BatchError::Delete(item_id, err)),
+            };
+            let new = result?;
+            changes.push((action, old, new.clone()));
+            results.push(new);
+        }
+
+        for (action, old, new) in &changes {
+            if self
+                .audit(table_id, *action, old.as_ref(), new.as_ref())
+                .is_err()
+            {
+                return Err(BatchError::Unknown);
+            }
+        }
+        lock.extend(scratch);
+        Ok(results)
+    }
+
+    fn claim_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError> {
+        if self.error {
+            return Err(IdempotencyError::Unknown);
+        }
+
+        let mut lock = match self.idempotency_keys.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        lock.retain(|_, record| record.expires_at > now);
+        match lock.get(&key) {
+            Some(record) if record.request_hash != request_hash => Ok(KeyClaim::Mismatch),
+            Some(record) => match &record.response {
+                Some(response) => Ok(KeyClaim::Replay(response.clone())),
+                None => Ok(KeyClaim::InProgress),
+            },
+            None => {
+                lock.insert(
+                    key,
+                    KeyRecord {
+                        request_hash,
+                        response: None,
+                        expires_at,
+                    },
+                );
+                Ok(KeyClaim::Claimed)
+            }
+        }
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError> {
+        let mut lock = match self.idempotency_keys.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        if let Some(record) = lock.get_mut(&key) {
+            record.response = Some(response);
+        }
+        Ok(())
+    }
+
+    fn release_idempotency_key(&self, key: IdempotencyKey) -> Result<(), IdempotencyError> {
+        let mut lock = match self.idempotency_keys.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        lock.remove(&key);
+        Ok(())
+    }
+
+    fn checkout(&self, bill: Bill) -> Result<ReceiptId, CheckoutError> {
+        if self.error {
+            return Err(CheckoutError::Unknown);
+        }
+
+        // Holding the items lock while recording the bill keeps both changes atomic
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(CheckoutError::Unknown),
+        };
+        let items = match lock.get_mut(&bill.table_id) {
+            Some(items) => items,
+            None => return Err(CheckoutError::UnknownTableId),
+        };
+
+        let mut active: Vec<(IdType, VersionType)> = items
             .iter()
-            .position(|p| p.id == item_id && p.deleted == false)
-        {
-            Some(index) => index,
-            None => return Err(DeleteError::UnknownItemId),
+            .filter(|it| !it.deleted)
+            .map(|it| (IdType::from(it.id), VersionType::from(it.version.clone())))
+            .collect();
+        if active.is_empty() {
+            return Err(CheckoutError::UnknownTableId);
+        }
+        active.sort();
+        if active != bill.item_versions() {
+            return Err(CheckoutError::Conflict);
+        }
+
+        let mut bills = match self.bills.lock() {
+            Ok(bills) => bills,
+            _ => return Err(CheckoutError::Unknown),
         };
+        let mut billed: Vec<Item> = items.iter().filter(|it| !it.deleted).cloned().collect();
+        billed.sort_by_key(|it| IdType::from(it.id));
+        for item in &billed {
+            if self
+                .audit(bill.table_id, AuditAction::Delete, Some(item), None)
+                .is_err()
+            {
+                return Err(CheckoutError::Unknown);
+            }
+        }
+        bills.push(bill);
+        let now = now_in_seconds();
+        items.iter_mut().filter(|it| !it.deleted).for_each(|it| {
+            it.deleted = true;
+            it.deleted_at = Some(now);
+            it.state = ItemState::Paid;
+            it.version += ItemVersion::from_int(1);
+        });
+        Ok(ReceiptId::from(bills.len() as IdType))
+    }
+
+    fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<usize, PurgeError> {
+        if self.error {
+            return Err(PurgeError::Unknown);
+        }
 
-        lock.get_mut(&table_id).unwrap()[index].deleted = true;
+        let mut lock = match self.items.lock() {
+            Ok(lock) => lock,
+            _ => return Err(PurgeError::Unknown),
+        };
+
+        let mut removed = 0;
+        for items in lock.values_mut() {
+            let before = items.len();
+            items.retain(|it| it.deleted_at.is_none_or(|at| at >= deleted_before));
+            removed += before - items.len();
+        }
+        Ok(removed)
+    }
+
+    // Nothing to write out, the items go away with the repository
+    fn shutdown(&self) -> Result<(), ShutdownError> {
         Ok(())
     }
+
+    // Shares the items, bills, keys and audit log
+    fn for_caller(&self, caller: Caller) -> Arc<dyn Repository> {
+        Arc::new(Self {
+            error: self.error,
+            delay: self.delay,
+            items: self.items.clone(),
+            bills: self.bills.clone(),
+            idempotency_keys: self.idempotency_keys.clone(),
+            audit_log: self.audit_log.clone(),
+            caller: Some(caller),
+        })
+    }
 }
@@ -0,0 +1,558 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+// Versioned schema migrations for the sqlite repositories.
+// Steps are applied in order and every applied version is recorded in the
+// schema_version table together with the time it was applied.
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+// Append new steps at the end, never edit a step that has been released
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create item table",
+        // IF NOT EXISTS lets databases created before migrations adopt this step
+        up: "CREATE TABLE IF NOT EXISTS item (
+                item_id      INTEGER NOT NULL,
+                table_id     INTEGER NOT NULL,
+                name         TEXT NOT NULL,
+                notes        TEXT,
+                quantity     INTEGER,
+                version      INTEGER,
+                deleted      INTEGER,
+                time_to_prepare TEXT
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS idx1 ON item(item_id, table_id) WHERE deleted = 0;",
+        down: "DROP INDEX IF EXISTS idx1;
+            DROP TABLE IF EXISTS item;",
+    },
+    Migration {
+        version: 2,
+        description: "replace time_to_prepare with prep time columns",
+        // time_to_prepare only held the ready time in chrono's Display format
+        // ("2023-12-12 10:15:30.123 UTC"), so existing rows are ordered at their
+        // ready time with 0 prep minutes. Unparsable values fall back to the epoch.
+        up: "ALTER TABLE item ADD COLUMN ordered_at TEXT;
+            ALTER TABLE item ADD COLUMN prep_minutes INTEGER;
+            ALTER TABLE item ADD COLUMN ready_at TEXT;
+            UPDATE item SET ready_at = coalesce(
+                strftime('%Y-%m-%dT%H:%M:%SZ', substr(time_to_prepare, 1, 19)),
+                '1970-01-01T00:00:00Z'
+            );
+            UPDATE item SET ordered_at = ready_at, prep_minutes = 0;
+            ALTER TABLE item DROP COLUMN time_to_prepare;",
+        down: "ALTER TABLE item ADD COLUMN time_to_prepare TEXT;
+            UPDATE item SET time_to_prepare = replace(replace(ready_at, 'T', ' '), 'Z', ' UTC');
+            ALTER TABLE item DROP COLUMN ready_at;
+            ALTER TABLE item DROP COLUMN prep_minutes;
+            ALTER TABLE item DROP COLUMN ordered_at;",
+    },
+    Migration {
+        version: 3,
+        description: "create menu_item table",
+        up: "CREATE TABLE menu_item (
+                id           INTEGER PRIMARY KEY,
+                name         TEXT NOT NULL,
+                category     TEXT NOT NULL,
+                price        INTEGER NOT NULL,
+                available    INTEGER NOT NULL
+            );
+            CREATE UNIQUE INDEX idx_menu_item_name ON menu_item(name COLLATE NOCASE);",
+        down: "DROP INDEX idx_menu_item_name;
+            DROP TABLE menu_item;",
+    },
+    Migration {
+        version: 4,
+        description: "create bill table",
+        // Bill lines are kept as a JSON array, amounts are in cents
+        up: "CREATE TABLE bill (
+                receipt_id   INTEGER PRIMARY KEY AUTOINCREMENT,
+                table_id     INTEGER NOT NULL,
+                lines        TEXT NOT NULL,
+                subtotal     INTEGER NOT NULL,
+                tax          INTEGER NOT NULL,
+                total        INTEGER NOT NULL,
+                created_at   TEXT NOT NULL
+            );",
+        down: "DROP TABLE bill;",
+    },
+    Migration {
+        version: 5,
+        description: "create idempotency_key table",
+        // status and body stay NULL while the first request with the key runs
+        up: "CREATE TABLE idempotency_key (
+                key          TEXT PRIMARY KEY,
+                request_hash TEXT NOT NULL,
+                status       INTEGER,
+                body         TEXT,
+                expires_at   TEXT NOT NULL
+            );
+            CREATE INDEX idx_idempotency_key_expires_at ON idempotency_key(expires_at);",
+        down: "DROP INDEX idx_idempotency_key_expires_at;
+            DROP TABLE idempotency_key;",
+    },
+    Migration {
+        version: 6,
+        description: "add item deleted_at column",
+        // Rows deleted before this step get the migration time, so the retention
+        // window starts now for them instead of purging them right away
+        up: "ALTER TABLE item ADD COLUMN deleted_at TEXT;
+            UPDATE item SET deleted_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now') WHERE deleted = 1;
+            CREATE INDEX idx_item_deleted_at ON item(deleted_at) WHERE deleted = 1;",
+        down: "DROP INDEX idx_item_deleted_at;
+            ALTER TABLE item DROP COLUMN deleted_at;",
+    },
+    Migration {
+        version: 7,
+        description: "create audit_log table",
+        // Triggers keep the log append-only
+        up: "CREATE TABLE audit_log (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                table_id   INTEGER NOT NULL,
+                item_id    INTEGER NOT NULL,
+                action     TEXT NOT NULL,
+                old_value  TEXT,
+                new_value  TEXT,
+                version    INTEGER NOT NULL,
+                at         TEXT NOT NULL,
+                caller     TEXT NOT NULL
+            );
+            CREATE INDEX idx_audit_log_table_id_at ON audit_log(table_id, at);
+            CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log is append-only');
+            END;
+            CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log is append-only');
+            END;",
+        down: "DROP TRIGGER audit_log_no_delete;
+            DROP TRIGGER audit_log_no_update;
+            DROP INDEX idx_audit_log_table_id_at;
+            DROP TABLE audit_log;",
+    },
+    Migration {
+        version: 8,
+        description: "index active items by ready time",
+        // Serves the kitchen queue across tables, deleted copies are left out
+        up: "CREATE INDEX idx_item_ready_at ON item(ready_at) WHERE deleted = 0;",
+        down: "DROP INDEX idx_item_ready_at;",
+    },
+    Migration {
+        version: 9,
+        description: "add item state column",
+        // Items deleted so far were cancelled, the others are taken as just ordered
+        up: "ALTER TABLE item ADD COLUMN state TEXT NOT NULL DEFAULT 'ordered';
+            UPDATE item SET state = 'cancelled' WHERE deleted = 1;",
+        down: "ALTER TABLE item DROP COLUMN state;",
+    },
+    Migration {
+        version: 10,
+        description: "mark checked out items as paid",
+        // Checkouts so far left the state alone, deletes always set it to cancelled.
+        // Going down, paid items are taken as served, the state they most likely had.
+        up: "UPDATE item SET state = 'paid' WHERE deleted = 1 AND state <> 'cancelled';",
+        down: "UPDATE item SET state = 'served' WHERE state = 'paid';",
+    },
+];
+
+#[derive(Debug, PartialEq)]
+pub enum MigrationError {
+    // The database was migrated by a newer build, or the target is not a known step
+    UnknownVersion(u32),
+    // Pending migrations were found but applying them on startup is disabled
+    Outdated { current: u32, latest: u32 },
+    Unknown,
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MigrationError::UnknownVersion(version) => {
+                write!(f, "unknown schema version {}", version)
+            }
+            MigrationError::Outdated { current, latest } => write!(
+                f,
+                "schema version {} is behind the latest version {}",
+                current, latest
+            ),
+            MigrationError::Unknown => write!(f, "migration failed"),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+pub struct MigrationStatus {
+    pub version: u32,
+    pub description: &'static str,
+    pub applied_at: Option<String>,
+}
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+fn init_version_table(connection: &Connection) -> Result<(), MigrationError> {
+    match connection.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version      INTEGER PRIMARY KEY,
+            description  TEXT NOT NULL,
+            applied_at   TEXT NOT NULL
+        )",
+        [],
+    ) {
+        Ok(_) => Ok(()),
+        _ => Err(MigrationError::Unknown),
+    }
+}
+
+pub fn current_version(connection: &Connection) -> Result<u32, MigrationError> {
+    init_version_table(connection)?;
+    match connection.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+        row.get::<usize, Option<u32>>(0)
+    }) {
+        Ok(version) => Ok(version.unwrap_or(0)),
+        _ => Err(MigrationError::Unknown),
+    }
+}
+
+pub fn status(connection: &Connection) -> Result<Vec<MigrationStatus>, MigrationError> {
+    init_version_table(connection)?;
+    let mut statuses = vec![];
+    for migration in MIGRATIONS {
+        let applied_at = match connection
+            .query_row(
+                "SELECT applied_at FROM schema_version WHERE version = ?1",
+                params![migration.version],
+                |row| row.get::<usize, String>(0),
+            )
+            .optional()
+        {
+            Ok(applied_at) => applied_at,
+            _ => return Err(MigrationError::Unknown),
+        };
+        statuses.push(MigrationStatus {
+            version: migration.version,
+            description: migration.description,
+            applied_at,
+        });
+    }
+    Ok(statuses)
+}
+
+// Applies pending steps up to target (latest when None), returns the applied versions
+pub fn migrate_up(
+    connection: &mut Connection,
+    target: Option<u32>,
+) -> Result<Vec<u32>, MigrationError> {
+    let target = target.unwrap_or_else(latest_version);
+    let current = current_version(connection)?;
+    if current > latest_version() {
+        return Err(MigrationError::UnknownVersion(current));
+    }
+    if target != 0 && !MIGRATIONS.iter().any(|m| m.version == target) {
+        return Err(MigrationError::UnknownVersion(target));
+    }
+
+    let mut applied = vec![];
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+    {
+        let tx = match connection.transaction() {
+            Ok(tx) => tx,
+            _ => return Err(MigrationError::Unknown),
+        };
+        if tx.execute_batch(migration.up).is_err()
+            || tx
+                .execute(
+                    "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+                    params![
+                        migration.version,
+                        migration.description,
+                        chrono::Utc::now().to_rfc3339()
+                    ],
+                )
+                .is_err()
+            || tx.commit().is_err()
+        {
+            return Err(MigrationError::Unknown);
+        }
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+// Reverts applied steps down to target (0 reverts everything), returns the reverted versions
+pub fn migrate_down(connection: &mut Connection, target: u32) -> Result<Vec<u32>, MigrationError> {
+    let current = current_version(connection)?;
+    if current > latest_version() {
+        return Err(MigrationError::UnknownVersion(current));
+    }
+    if target != 0 && !MIGRATIONS.iter().any(|m| m.version == target) {
+        return Err(MigrationError::UnknownVersion(target));
+    }
+
+    let mut reverted = vec![];
+    for migration in MIGRATIONS
+        .iter()
+        .rev()
+        .filter(|m| m.version <= current && m.version > target)
+    {
+        let tx = match connection.transaction() {
+            Ok(tx) => tx,
+            _ => return Err(MigrationError::Unknown),
+        };
+        if tx.execute_batch(migration.down).is_err()
+            || tx
+                .execute(
+                    "DELETE FROM schema_version WHERE version = ?1",
+                    params![migration.version],
+                )
+                .is_err()
+            || tx.commit().is_err()
+        {
+            return Err(MigrationError::Unknown);
+        }
+        reverted.push(migration.version);
+    }
+    Ok(reverted)
+}
+
+// Called when a repository opens its database: applies pending steps, or
+// refuses to use an outdated schema when apply_pending is false
+pub fn prepare(connection: &mut Connection, apply_pending: bool) -> Result<(), MigrationError> {
+    if apply_pending {
+        return migrate_up(connection, None).map(|_| ());
+    }
+    let current = current_version(connection)?;
+    let latest = latest_version();
+    match current {
+        current if current == latest => Ok(()),
+        current if current > latest => Err(MigrationError::UnknownVersion(current)),
+        current => Err(MigrationError::Outdated { current, latest }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table_exists(connection: &Connection, name: &str) -> bool {
+        connection
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![name],
+                |row| row.get::<usize, u32>(0),
+            )
+            .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn it_should_apply_all_migrations_in_order() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&connection), Ok(0));
+
+        let applied = migrate_up(&mut connection, None).unwrap();
+
+        let expected: Vec<u32> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(applied, expected);
+        assert_eq!(current_version(&connection), Ok(latest_version()));
+        assert!(table_exists(&connection, "item"));
+        assert!(status(&connection)
+            .unwrap()
+            .iter()
+            .all(|s| s.applied_at.is_some()));
+
+        // Nothing left to apply
+        assert_eq!(migrate_up(&mut connection, None), Ok(vec![]));
+    }
+
+    #[test]
+    fn it_should_revert_migrations() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_up(&mut connection, None).unwrap();
+
+        let reverted = migrate_down(&mut connection, 0).unwrap();
+
+        let expected: Vec<u32> = MIGRATIONS.iter().rev().map(|m| m.version).collect();
+        assert_eq!(reverted, expected);
+        assert_eq!(current_version(&connection), Ok(0));
+        assert!(!table_exists(&connection, "item"));
+        assert!(status(&connection)
+            .unwrap()
+            .iter()
+            .all(|s| s.applied_at.is_none()));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_target_versions() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let unknown = latest_version() + 1;
+
+        assert_eq!(
+            migrate_up(&mut connection, Some(unknown)),
+            Err(MigrationError::UnknownVersion(unknown))
+        );
+        assert_eq!(
+            migrate_down(&mut connection, unknown),
+            Err(MigrationError::UnknownVersion(unknown))
+        );
+        assert_eq!(current_version(&connection), Ok(0));
+    }
+
+    #[test]
+    fn it_should_refuse_an_outdated_schema_when_not_applying() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        assert_eq!(
+            prepare(&mut connection, false),
+            Err(MigrationError::Outdated {
+                current: 0,
+                latest: latest_version()
+            })
+        );
+        assert_eq!(prepare(&mut connection, true), Ok(()));
+        assert_eq!(prepare(&mut connection, false), Ok(()));
+    }
+
+    #[test]
+    fn it_should_adopt_a_database_created_before_migrations() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0].up).unwrap();
+        connection
+            .execute(
+                "INSERT INTO item VALUES (1, 1, 'pizza', '', 1, 1, 0, '')",
+                [],
+            )
+            .unwrap();
+
+        migrate_up(&mut connection, None).unwrap();
+
+        let count: u32 = connection
+            .query_row("SELECT count(*) FROM item", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn it_should_convert_time_to_prepare_into_prep_time_columns() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_up(&mut connection, Some(1)).unwrap();
+        connection
+            .execute(
+                "INSERT INTO item VALUES (1, 1, 'pizza', '', 1, 1, 0, '2023-12-12 10:15:30.123 UTC')",
+                [],
+            )
+            .unwrap();
+
+        migrate_up(&mut connection, Some(2)).unwrap();
+
+        let (ordered_at, prep_minutes, ready_at): (String, u32, String) = connection
+            .query_row(
+                "SELECT ordered_at, prep_minutes, ready_at FROM item",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(ordered_at, "2023-12-12T10:15:30Z");
+        assert_eq!(prep_minutes, 0);
+        assert_eq!(ready_at, "2023-12-12T10:15:30Z");
+
+        migrate_down(&mut connection, 1).unwrap();
+
+        let time_to_prepare: String = connection
+            .query_row("SELECT time_to_prepare FROM item", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(time_to_prepare, "2023-12-12 10:15:30 UTC");
+    }
+
+    #[test]
+    fn it_should_stamp_rows_deleted_before_the_deleted_at_column() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_up(&mut connection, Some(5)).unwrap();
+        connection
+            .execute(
+                "INSERT INTO item (item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, ready_at)
+                VALUES (1, 1, 'pizza', '', 1, 1, 1, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z'),
+                       (2, 1, 'pasta', '', 1, 0, 1, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z')",
+                [],
+            )
+            .unwrap();
+
+        migrate_up(&mut connection, Some(6)).unwrap();
+
+        let stamped: Vec<bool> = connection
+            .prepare("SELECT deleted_at IS NOT NULL FROM item ORDER BY item_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(stamped, vec![true, false]);
+
+        migrate_down(&mut connection, 5).unwrap();
+    }
+
+    #[test]
+    fn it_should_mark_rows_deleted_before_the_state_column_as_cancelled() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_up(&mut connection, Some(8)).unwrap();
+        connection
+            .execute(
+                "INSERT INTO item (item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, ready_at)
+                VALUES (1, 1, 'pizza', '', 1, 1, 1, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z'),
+                       (2, 1, 'pasta', '', 1, 0, 1, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z')",
+                [],
+            )
+            .unwrap();
+
+        migrate_up(&mut connection, Some(9)).unwrap();
+
+        let states: Vec<String> = connection
+            .prepare("SELECT state FROM item ORDER BY item_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(states, vec!["cancelled", "ordered"]);
+
+        migrate_down(&mut connection, 8).unwrap();
+    }
+
+    #[test]
+    fn it_should_mark_rows_checked_out_before_the_paid_state_as_paid() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_up(&mut connection, Some(9)).unwrap();
+        connection
+            .execute(
+                "INSERT INTO item (item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, ready_at, state)
+                VALUES (1, 1, 'pizza', '', 1, 1, 2, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z', 'cancelled'),
+                       (2, 1, 'pasta', '', 1, 1, 2, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z', 'served'),
+                       (3, 1, 'salad', '', 1, 0, 1, '2023-12-12T10:00:00Z', 0, '2023-12-12T10:00:00Z', 'served')",
+                [],
+            )
+            .unwrap();
+
+        migrate_up(&mut connection, Some(10)).unwrap();
+
+        let states: Vec<String> = connection
+            .prepare("SELECT state FROM item ORDER BY item_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(states, vec!["cancelled", "paid", "served"]);
+
+        migrate_down(&mut connection, 9).unwrap();
+    }
+}
@@ -1,12 +1,23 @@
+pub mod async_repository;
 pub mod inmemory;
+pub mod migrations;
 pub mod sqlite;
+pub mod sqlite_pool;
 
 // Repository interface and errors
 
+use crate::audit::Caller;
 use crate::domain::types::{
-    IdType, Item, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, QuantityType, TableId,
-    VersionType,
+    Bill, IdType, IdempotencyKey, Item, ItemFilter, ItemId, ItemName, ItemNotes, ItemPage,
+    ItemPatch, ItemQuantity, ItemSort, ItemState, ItemVersion, KeyClaim, PrepTime, QuantityType,
+    QueuedItem, ReceiptId, StoredResponse, TableId, VersionType,
 };
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+// How long an Sqlite connection waits on a locked database before failing
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub enum InsertError {
     Conflict,
@@ -17,7 +28,8 @@ pub enum UpdateError {
     UnknownItemId,
     UnknownTableId,
     Unknown,
-    VersionConflict,
+    // The stored item is newer than the caller's version, it holds the stored item
+    VersionConflict(Item),
 }
 
 pub enum FetchAllError {
@@ -36,9 +48,69 @@ pub enum DeleteError {
     Unknown,
     UnknownItemId,
     UnknownTableId,
+    // The item is not in the expected version anymore
+    VersionConflict,
+}
+
+pub enum RestoreError {
+    Unknown,
+    UnknownItemId,
+    UnknownTableId,
+    // An active item with the same id exists
+    AlreadyActive,
+    // The latest deleted copy was paid at checkout
+    Paid,
+}
+
+pub enum TransitionError {
+    Unknown,
+    UnknownItemId,
+    UnknownTableId,
+    // The item is in another version than the one given
+    VersionConflict(Item),
+    // The item can't go from its current state to the requested one
+    Invalid(Item),
+}
+
+pub enum CheckoutError {
+    // The table has no active items
+    UnknownTableId,
+    // Items of the table were added, changed or removed after the bill was computed
+    Conflict,
+    Unknown,
+}
+
+pub enum PurgeError {
+    Unknown,
+}
+
+pub enum ShutdownError {
+    Unknown,
+}
+
+// One change of a batch applied with Repository::apply_batch
+pub enum BatchOp {
+    Insert(Item),
+    Update(Item),
+    Delete(ItemId<IdType>),
+}
+
+// The operation that failed a batch, none of the batch was applied
+pub enum BatchError {
+    Insert(ItemId<IdType>, InsertError),
+    Update(ItemId<IdType>, UpdateError),
+    Delete(ItemId<IdType>, DeleteError),
+    Unknown,
+}
+
+pub enum IdempotencyError {
+    Unknown,
 }
 
 pub trait Repository: Send + Sync {
+    // An item re-created after a delete gets a version above its deleted copies,
+    // so (item_id, version) tells the copies of an item apart
+    #[allow(clippy::too_many_arguments)]
     fn insert(
         &self,
         table_id: TableId<IdType>,
@@ -48,9 +120,10 @@ pub trait Repository: Send + Sync {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, InsertError>;
 
+    #[allow(clippy::too_many_arguments)]
     fn update(
         &self,
         table_id: TableId<IdType>,
@@ -60,13 +133,17 @@ pub trait Repository: Send + Sync {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, UpdateError>;
 
+    // When a page is given items are ordered by (item_id, version) and sort_by is ignored
     fn fetch_all(
         &self,
         table_id: TableId<IdType>,
-        _include_deleted: bool,
+        include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
     ) -> Result<Vec<Item>, FetchAllError>;
 
     fn fetch_one(
@@ -75,6 +152,92 @@ pub trait Repository: Send + Sync {
         item_id: ItemId<IdType>,
     ) -> Result<Item, FetchOneError>;
 
-    fn delete(&self, table_id: TableId<IdType>, item_id: ItemId<IdType>)
-        -> Result<(), DeleteError>;
+    // Items of every table still in the kitchen, see ItemState::in_kitchen(), ordered
+    // by ready time. Only the ones ready by the given time when there is one.
+    fn fetch_active_across_tables(
+        &self,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError>;
+
+    // Changes the fields of the patch only. Versions are checked like in update()
+    fn update_partial(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError>;
+
+    // Every copy of the item, deleted ones included, oldest first
+    fn fetch_history(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError>;
+
+    // Brings back the latest deleted copy of the item in a new version
+    fn restore(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError>;
+
+    // Moves the item to the given state in a new version, only if it is still in
+    // item_version when one is given. Cancelling soft deletes it like delete().
+    fn transition(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError>;
+
+    // Soft deletes the item as cancelled in a new version, only if it is still in
+    // item_version when one is given
+    fn delete(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<(), DeleteError>;
+
+    // Applies all operations or none of them. Returns the inserted or updated item
+    // of every operation in order, None for deletes.
+    fn apply_batch(
+        &self,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError>;
+
+    // Claims the key for a request, records of expired keys are dropped first
+    fn claim_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError>;
+
+    // Stores the response of a claimed key to replay it to retries
+    fn complete_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError>;
+
+    // Forgets a claimed key so that the request can be retried
+    fn release_idempotency_key(&self, key: IdempotencyKey) -> Result<(), IdempotencyError>;
+
+    // Records the bill and soft deletes every billed item of its table at once
+    fn checkout(&self, bill: Bill) -> Result<ReceiptId, CheckoutError>;
+
+    // Removes for good the items deleted before the given time, returns how many
+    fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<usize, PurgeError>;
+
+    // Called once the server stopped serving, writes out what is still pending
+    fn shutdown(&self) -> Result<(), ShutdownError>;
+
+    // The same items, with every change made through the returned repository
+    // appended to the audit log as made by caller, in the transaction of the change
+    fn for_caller(&self, caller: Caller) -> Arc<dyn Repository>;
 }
@@ -1,89 +1,162 @@
-use std::sync::{Mutex, MutexGuard};
+use crate::audit::{self, sqlite::SqliteAuditRepository, AuditAction, Caller};
 use crate::domain::types::{
-    IdType, Item, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, QuantityType, TableId,
-    VersionType,
+    now_in_seconds, to_rfc3339, Bill, IdType, IdempotencyKey, Item, ItemFilter, ItemId, ItemName,
+    ItemNotes, ItemPage, ItemPatch, ItemQuantity, ItemSort, ItemSortField, ItemState, ItemVersion,
+    KeyClaim, PrepMinutesType, PrepTime, QuantityType, QueuedItem, ReceiptId, StoredResponse,
+    TableId, VersionType,
 };
+use crate::repository::migrations;
 use crate::repository::*;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::OptionalExtension;
 use rusqlite::{params, params_from_iter, Connection, Error::SqliteFailure};
+use std::sync::{Arc, Mutex};
 
 // An Sqlite repository implementation
 
+// item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, deleted_at, state
+const ITEM_COLUMNS: &str = "item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, deleted_at, state";
+type ItemRow = (
+    IdType,
+    IdType,
+    String,
+    String,
+    QuantityType,
+    bool,
+    VersionType,
+    String,
+    PrepMinutesType,
+    Option<String>,
+    String,
+);
+
 pub struct SqliteRepository {
-    pub connection: Mutex<Connection>,
+    pub connection: Arc<Mutex<Connection>>,
+    // Who the changes are logged as made by, nothing is logged without one
+    caller: Option<Caller>,
 }
 
 impl SqliteRepository {
-    pub fn try_new(path: &str) -> Result<Self, ()> {
-        let connection = match Connection::open(path) {
+    // Opens the database, applying pending migrations when apply_migrations is set
+    pub fn try_new(path: &str, apply_migrations: bool) -> Result<Self, ()> {
+        let mut connection = match Connection::open(path) {
             Ok(connection) => connection,
             _ => return Err(()),
         };
-
-
-        match connection.execute(
-            "CREATE TABLE IF NOT EXISTS item (
-                item_id      INTEGER NOT NULL,
-                table_id     INTEGER NOT NULL,
-                name         TEXT NOT NULL,
-                notes        TEXT,
-                quantity     INTEGER,
-                version      INTEGER,
-                deleted      INTEGER, 
-                time_to_prepare TEXT
-            )",
-            [],
-        ) {
-            Ok(_) => {},
-            Err(_) => return Err(()),
+        if connection.busy_timeout(BUSY_TIMEOUT).is_err() {
+            return Err(());
         }
-
-        match connection.execute(
-            "CREATE UNIQUE INDEX IF NOT EXISTS idx1 ON item(item_id, table_id) WHERE deleted = 0",
-            [],
-        ) {
+        if Self::enable_incremental_vacuum(&connection).is_err() {
+            return Err(());
+        }
+        match migrations::prepare(&mut connection, apply_migrations) {
             Ok(_) => Ok(Self {
-                connection: Mutex::new(connection),
+                connection: Arc::new(Mutex::new(connection)),
+                caller: None,
             }),
             _ => Err(()),
         }
     }
 
+    // The audit log the changes are appended to, read through the same connection
+    pub fn audit_log(&self) -> SqliteAuditRepository {
+        SqliteAuditRepository::sharing(self.connection.clone())
+    }
+
+    // Only takes effect on a new database, an existing one needs a VACUUM to switch
+    pub(super) fn enable_incremental_vacuum(connection: &Connection) -> Result<(), ()> {
+        connection
+            .pragma_update(None, "auto_vacuum", "INCREMENTAL")
+            .map_err(|_| ())
+    }
+
+    // Rebuilds the database file, switching it to incremental auto vacuum on the way
+    pub fn vacuum(&self) -> Result<(), PurgeError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(PurgeError::Unknown),
+        };
+
+        match Self::enable_incremental_vacuum(&lock).map(|_| lock.execute_batch("VACUUM")) {
+            Ok(Ok(_)) => Ok(()),
+            _ => Err(PurgeError::Unknown),
+        }
+    }
+
     fn fetch_item_rows(
-        lock: &MutexGuard<'_, Connection>,
+        connection: &Connection,
         table_id: IdType,
         item_id: Option<IdType>,
-        include_deleted: bool
-    ) -> Result<
-        Vec<(
-            IdType,
-            IdType,
-            String,
-            String,
-            QuantityType,
-            bool,
-            VersionType,
-            String,
-        )>,
-        (),
-    > {
-        let (query, params) = match item_id {
-            Some(item_id) => {
-                match include_deleted {
-                    true => ("select item_id, table_id, name, notes, quantity, deleted, version, time_to_prepare from item where item_id = ? and table_id= ?",
-                        vec![item_id, table_id]),
-                    false=>("select item_id, table_id, name, notes, quantity, deleted, version, time_to_prepare from item where item_id = ? and table_id= ? and deleted=0",
-                        vec![item_id, table_id]),
-            }
-        },
-            _ => {
-                match include_deleted {
-                    true => ("select item_id, table_id, name, notes, quantity, deleted, version, time_to_prepare from item where table_id = ?", vec![table_id]),
-                    false => ("select item_id, table_id, name, notes, quantity, deleted, version, time_to_prepare from item where table_id = ? and deleted=0", vec![table_id])
+        include_deleted: bool,
+        filter: &ItemFilter,
+        sort_by: &ItemSort,
+        page: Option<&ItemPage>,
+    ) -> Result<Vec<ItemRow>, ()> {
+        let mut query = format!("select {} from item where table_id = ?", ITEM_COLUMNS);
+        let mut params: Vec<Value> = vec![Value::from(table_id)];
+
+        if let Some(item_id) = item_id {
+            query.push_str(" and item_id = ?");
+            params.push(Value::from(item_id));
+        }
+        if !include_deleted {
+            query.push_str(" and deleted=0");
+        }
+        if let Some(name_contains) = &filter.name_contains {
+            query.push_str(" and instr(lower(name), lower(?)) > 0");
+            params.push(Value::from(name_contains.clone()));
+        }
+        if let Some(min_quantity) = &filter.min_quantity {
+            query.push_str(" and quantity >= ?");
+            params.push(Value::from(QuantityType::from(min_quantity.clone())));
+        }
+        if let Some(max_quantity) = &filter.max_quantity {
+            query.push_str(" and quantity <= ?");
+            params.push(Value::from(QuantityType::from(max_quantity.clone())));
+        }
+
+        match page {
+            Some(page) => {
+                if let Some(cursor) = &page.after {
+                    query.push_str(" and (item_id > ? or (item_id = ? and version > ?))");
+                    params.push(Value::from(IdType::from(cursor.item_id)));
+                    params.push(Value::from(IdType::from(cursor.item_id)));
+                    params.push(Value::from(VersionType::from(cursor.version.clone())));
                 }
-            },
-        };
+                query.push_str(" order by item_id asc, version asc limit ?");
+                params.push(Value::from(page.limit as i64));
+            }
+            None => {
+                let sort_column = match sort_by.field {
+                    ItemSortField::Id => "item_id",
+                    ItemSortField::Name => "name",
+                    ItemSortField::Quantity => "quantity",
+                    ItemSortField::Version => "version",
+                    ItemSortField::ReadyAt => "ready_at",
+                };
+                let sort_direction = match sort_by.descending {
+                    true => "desc",
+                    false => "asc",
+                };
+                // rowid keeps the copies of an item in the order they were written
+                query.push_str(&format!(
+                    " order by {} {}, item_id asc, rowid asc",
+                    sort_column, sort_direction
+                ));
+            }
+        }
 
-        let mut stmt = match lock.prepare(query) {
+        Self::query_item_rows(connection, &query, params)
+    }
+
+    // Runs a select of the ItemRow columns
+    fn query_item_rows(
+        connection: &Connection,
+        query: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<ItemRow>, ()> {
+        let mut stmt = match connection.prepare(query) {
             Ok(stmt) => stmt,
             _ => return Err(()),
         };
@@ -105,6 +178,9 @@ impl SqliteRepository {
                 row.get::<usize, bool>(5),
                 row.get::<usize, VersionType>(6),
                 row.get::<usize, String>(7),
+                row.get::<usize, PrepMinutesType>(8),
+                row.get::<usize, Option<String>>(9),
+                row.get::<usize, String>(10),
             ) {
                 (
                     Ok(item_id),
@@ -114,7 +190,10 @@ impl SqliteRepository {
                     Ok(quantity),
                     Ok(deleted),
                     Ok(version),
-                    Ok(time_to_prepare),
+                    Ok(ordered_at),
+                    Ok(prep_minutes),
+                    Ok(deleted_at),
+                    Ok(state),
                 ) => item_rows.push((
                     item_id,
                     table_id,
@@ -123,7 +202,10 @@ impl SqliteRepository {
                     quantity,
                     deleted,
                     version,
-                    time_to_prepare,
+                    ordered_at,
+                    prep_minutes,
+                    deleted_at,
+                    state,
                 )),
                 _ => return Err(()),
             };
@@ -133,9 +215,28 @@ impl SqliteRepository {
     }
 }
 
-impl Repository for SqliteRepository {
-    fn insert(
-        &self,
+// Queries shared by the single connection and the pooled sqlite repositories
+impl SqliteRepository {
+    // Appends the change to the audit log in the transaction of the change, when
+    // it is made for a caller
+    fn audit(
+        connection: &Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        action: AuditAction,
+        old: Option<&Item>,
+        new: Option<&Item>,
+    ) -> Result<(), ()> {
+        match audit::entry_for(caller, table_id, action, old, new) {
+            Some(entry) => audit::sqlite::append_entry(connection, entry).map_err(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn insert_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
         table_id: TableId<IdType>,
         item_id: ItemId<IdType>,
         item_name: ItemName,
@@ -143,27 +244,67 @@ impl Repository for SqliteRepository {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, InsertError> {
-        let mut lock = match self.connection.lock() {
-            Ok(lock) => lock,
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
             _ => return Err(InsertError::Unknown),
         };
+        let item = Self::insert_row(
+            &transaction,
+            caller,
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )?;
 
-        let transaction = match lock.transaction() {
-            Ok(transaction) => transaction,
-            _ => return Err(InsertError::Unknown),
+        match transaction.commit() {
+            Ok(_) => Ok(item),
+            _ => Err(InsertError::Unknown),
+        }
+    }
+
+    // Inserts an item without committing, the caller owns the transaction
+    #[allow(clippy::too_many_arguments)]
+    fn insert_row(
+        connection: &Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, InsertError> {
+        let (deleted_int, state) = match item_deleted {
+            true => (1, ItemState::Cancelled),
+            false => (0, ItemState::Ordered),
         };
-        let deleted_int = match item_deleted {
-            true => 1,
-            false => 0
+        // A re-created item continues after the versions of its deleted copies
+        let item_version = match connection.query_row(
+            "select max(version) from item where table_id = ? and item_id = ?",
+            params![IdType::from(table_id), IdType::from(item_id)],
+            |row| row.get::<usize, Option<VersionType>>(0),
+        ) {
+            Ok(Some(last)) if last >= VersionType::from(item_version.clone()) => {
+                ItemVersion::from_int(last + 1)
+            }
+            Ok(_) => item_version,
+            _ => return Err(InsertError::Unknown),
         };
 
-        match transaction.execute(
-            "insert into item (item_id, table_id, name, notes, quantity, deleted, version, time_to_prepare) values (?,?,?,?,?,?,?,?)",
+        match connection.execute(
+            "insert into item (item_id, table_id, name, notes, quantity, deleted, version, ordered_at, prep_minutes, ready_at, state) values (?,?,?,?,?,?,?,?,?,?,?)",
             params![IdType::from(item_id), IdType::from(table_id), String::from(item_name.clone()),
             String::from(item_notes.clone()), QuantityType::from(item_quantity.clone()), deleted_int, VersionType::from(item_version.clone()),
-            item_time_to_prepare],
+            to_rfc3339(&item_prep_time.ordered_at), item_prep_time.prep_minutes, to_rfc3339(&item_prep_time.ready_at), state.as_str()],
         ) {
             Ok(_) => {}
             Err(SqliteFailure(_, Some(message))) => {
@@ -177,142 +318,1092 @@ impl Repository for SqliteRepository {
             _ => return Err(InsertError::Unknown),
         };
 
-        match transaction.commit() {
-            Ok(_) => Ok(Item::new(
-                item_id,
-                item_name,
-                item_notes,
-                item_quantity,
-                item_deleted,
-                item_version,
-                item_time_to_prepare,
-            )),
+        let item = Item::new(
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        );
+        match Self::audit(
+            connection,
+            caller,
+            table_id,
+            AuditAction::Insert,
+            None,
+            Some(&item),
+        ) {
+            Ok(_) => Ok(item),
             _ => Err(InsertError::Unknown),
         }
     }
 
-    fn fetch_all(
-        &self,
+    pub(super) fn fetch_all_items(
+        connection: &Connection,
         table_id: TableId<IdType>,
         include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
     ) -> Result<Vec<Item>, FetchAllError> {
-        let lock = match self.connection.lock() {
-            Ok(lock) => lock,
+        let item_rows = match Self::fetch_item_rows(
+            connection,
+            IdType::from(table_id),
+            None,
+            include_deleted,
+            &filter,
+            &sort_by,
+            page.as_ref(),
+        ) {
+            Ok(item_rows) => item_rows,
             _ => return Err(FetchAllError::Unknown),
         };
 
-        let item_rows = match Self::fetch_item_rows(&lock, IdType::from(table_id), None, include_deleted) {
+        item_rows
+            .into_iter()
+            .map(Self::item_from_row)
+            .collect::<Option<Vec<Item>>>()
+            .ok_or(FetchAllError::Unknown)
+    }
+
+    fn item_from_row(item_row: ItemRow) -> Option<Item> {
+        match (
+            ItemId::try_from(item_row.0.to_string()),
+            ItemName::try_from(item_row.2),
+            ItemNotes::try_from(item_row.3),
+            ItemQuantity::try_from(item_row.4.to_string()),
+            item_row.5,
+            ItemVersion::try_from(item_row.6.to_string()),
+            PrepTime::try_from_rfc3339(&item_row.7, item_row.8),
+            item_row
+                .9
+                .map(|at| DateTime::parse_from_rfc3339(&at))
+                .transpose(),
+            ItemState::try_from(item_row.10),
+        ) {
+            (
+                Ok(id),
+                Ok(name),
+                Ok(notes),
+                Ok(quantity),
+                deleted,
+                Ok(version),
+                Ok(prep_time),
+                Ok(deleted_at),
+                Ok(state),
+            ) => Some(Item {
+                state,
+                deleted_at: deleted_at.map(|at| at.with_timezone(&Utc)),
+                ..Item::new(id, name, notes, quantity, deleted, version, prep_time)
+            }),
+            _ => None,
+        }
+    }
+
+    // Active items of all tables, the partial index on ready_at serves the order
+    pub(super) fn fetch_queue_items(
+        connection: &Connection,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError> {
+        // The states of ItemState::in_kitchen()
+        let mut query = format!(
+            "select {} from item where deleted=0 and state in ('ordered', 'cooking', 'ready')",
+            ITEM_COLUMNS
+        );
+        let mut params: Vec<Value> = vec![];
+        if let Some(ready_by) = ready_by {
+            query.push_str(" and ready_at <= ?");
+            params.push(Value::from(to_rfc3339(&ready_by)));
+        }
+        query.push_str(" order by ready_at asc, table_id asc, item_id asc");
+
+        let item_rows = match Self::query_item_rows(connection, &query, params) {
             Ok(item_rows) => item_rows,
             _ => return Err(FetchAllError::Unknown),
         };
-
-        let mut items = vec![];
-
+        let mut queued = vec![];
         for item_row in item_rows {
-            let item = match (
-                ItemId::try_from(item_row.0.to_string()),
-                ItemName::try_from(item_row.2),
-                ItemNotes::try_from(item_row.3),
-                ItemQuantity::try_from(item_row.4.to_string()),
-                item_row.5,
-                ItemVersion::try_from(item_row.6.to_string()),
-                item_row.7,
-            ) {
-                (
-                    Ok(id),
-                    Ok(name),
-                    Ok(notes),
-                    Ok(quantity),
-                    deleted,
-                    Ok(version),
-                    time_to_prepare,
-                ) => Item::new(
-                    id,
-                    name,
-                    notes,
-                    quantity,
-                    deleted,
-                    version,
-                    time_to_prepare,
-                ),
-                _ => return Err(FetchAllError::Unknown),
+            // A table left out of the configured range can't be served, neither are its items
+            let table_id = match TableId::try_from(item_row.1.to_string()) {
+                Ok(table_id) => table_id,
+                Err(_) => {
+                    tracing::warn!(
+                        table_id = item_row.1,
+                        item_id = item_row.0,
+                        "skipped a queued item of a table out of range"
+                    );
+                    continue;
+                }
             };
+            match Self::item_from_row(item_row) {
+                Some(item) => queued.push(QueuedItem { table_id, item }),
+                None => return Err(FetchAllError::Unknown),
+            }
+        }
+        Ok(queued)
+    }
+
+    // Every copy of the item, deleted ones included, oldest first
+    pub(super) fn fetch_item_history(
+        connection: &Connection,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError> {
+        let item_rows = match Self::fetch_item_rows(
+            connection,
+            IdType::from(table_id),
+            Some(IdType::from(item_id)),
+            true,
+            &ItemFilter::default(),
+            &ItemSort::default(),
+            None,
+        ) {
+            Ok(item_rows) => item_rows,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        match item_rows
+            .into_iter()
+            .map(Self::item_from_row)
+            .collect::<Option<Vec<Item>>>()
+        {
+            Some(items) if items.is_empty() => Err(FetchOneError::UnknownItemId),
+            Some(items) => Ok(items),
+            None => Err(FetchOneError::Unknown),
+        }
+    }
+
+    // Brings back the latest deleted copy in a new version
+    pub(super) fn restore_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(RestoreError::Unknown),
+        };
 
-            items.push(item);
+        match Self::fetch_one_item(&transaction, table_id, item_id) {
+            Ok(_) => return Err(RestoreError::AlreadyActive),
+            Err(FetchOneError::UnknownItemId) => {}
+            _ => return Err(RestoreError::Unknown),
+        }
+        let rowid: i64 = match transaction.query_row(
+            "select rowid from item where table_id = ? and item_id = ? and deleted=1 order by rowid desc limit 1",
+            params![IdType::from(table_id), IdType::from(item_id)],
+            |row| row.get(0),
+        ).optional() {
+            Ok(Some(rowid)) => rowid,
+            Ok(None) => return Err(RestoreError::UnknownItemId),
+            _ => return Err(RestoreError::Unknown),
+        };
+        let query = format!("select {} from item where rowid = ?", ITEM_COLUMNS);
+        let old = match Self::query_item_rows(&transaction, &query, vec![Value::from(rowid)]) {
+            Ok(mut rows) if rows.len() == 1 => Self::item_from_row(rows.remove(0)),
+            _ => None,
+        };
+        let old = match old {
+            Some(old) => old,
+            None => return Err(RestoreError::Unknown),
+        };
+        if old.state == ItemState::Paid {
+            return Err(RestoreError::Paid);
+        }
+        if transaction.execute(
+            "update item set deleted=0, deleted_at = null, state = 'ordered', version = version + 1 where rowid = ?",
+            params![rowid],
+        ).is_err() {
+            return Err(RestoreError::Unknown);
+        }
+        let item = match Self::fetch_one_item(&transaction, table_id, item_id) {
+            Ok(item) => item,
+            _ => return Err(RestoreError::Unknown),
+        };
+        if Self::audit(
+            &transaction,
+            caller,
+            table_id,
+            AuditAction::Restore,
+            Some(&old),
+            Some(&item),
+        )
+        .is_err()
+        {
+            return Err(RestoreError::Unknown);
         }
 
-        Ok(items)
+        match transaction.commit() {
+            Ok(_) => Ok(item),
+            _ => Err(RestoreError::Unknown),
+        }
     }
 
-    fn fetch_one(
-        &self,
+    pub(super) fn fetch_one_item(
+        connection: &Connection,
         table_id: TableId<IdType>,
         item_id: ItemId<IdType>,
     ) -> Result<Item, FetchOneError> {
-        let lock = match self.connection.lock() {
-            Ok(lock) => lock,
+        let mut item_rows = match Self::fetch_item_rows(
+            connection,
+            IdType::from(table_id),
+            Some(IdType::from(item_id)),
+            false,
+            &ItemFilter::default(),
+            &ItemSort::default(),
+            None,
+        ) {
+            Ok(rows) => rows,
             _ => return Err(FetchOneError::Unknown),
         };
 
-        let mut item_rows =
-            match Self::fetch_item_rows(&lock, IdType::from(table_id), Some(IdType::from(item_id)), false)
-            {
-                Ok(rows) => rows,
-                _ => return Err(FetchOneError::Unknown),
-            };
-        
         if item_rows.is_empty() {
             return Err(FetchOneError::UnknownItemId);
         }
 
-        let item_row = item_rows.remove(0);
+        Self::item_from_row(item_rows.remove(0)).ok_or(FetchOneError::Unknown)
+    }
+
+    pub(super) fn delete_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<(), DeleteError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(DeleteError::Unknown),
+        };
+        Self::delete_row(&transaction, caller, table_id, item_id, item_version)?;
+
+        match transaction.commit() {
+            Ok(_) => Ok(()),
+            _ => Err(DeleteError::Unknown),
+        }
+    }
+
+    // Deletes an item without committing, the caller owns the transaction. Returns
+    // the deleted_at written to the row.
+    fn delete_row(
+        connection: &Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<DateTime<Utc>, DeleteError> {
+        let old = match Self::fetch_one_item(connection, table_id, item_id) {
+            Ok(old) => old,
+            Err(FetchOneError::UnknownItemId) => return Err(DeleteError::UnknownItemId),
+            _ => return Err(DeleteError::Unknown),
+        };
+        if item_version.is_some_and(|version| old.version != version) {
+            return Err(DeleteError::VersionConflict);
+        }
+
+        let deleted_at = now_in_seconds();
+        match connection.execute(
+            "update item set deleted=1, deleted_at = ?, state = 'cancelled', version = version + 1 where table_id = ? and item_id = ? and deleted=0",
+            params![to_rfc3339(&deleted_at), IdType::from(table_id), IdType::from(item_id)],
+        ) {
+            Ok(_) => {}
+            Err(SqliteFailure(_, Some(message))) => {
+                println!("Message delete {}", message);
+                return Err(DeleteError::Unknown);
+            }
+            _ => return Err(DeleteError::Unknown),
+        };
+
+        match Self::audit(
+            connection,
+            caller,
+            table_id,
+            AuditAction::Delete,
+            Some(&old),
+            None,
+        ) {
+            Ok(_) => Ok(deleted_at),
+            _ => Err(DeleteError::Unknown),
+        }
+    }
+
+    // Checks the move against the current state and version, cancelling goes through delete_row()
+    pub(super) fn transition_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(TransitionError::Unknown),
+        };
+
+        let current = match Self::fetch_one_item(&transaction, table_id, item_id) {
+            Ok(item) => item,
+            Err(FetchOneError::UnknownItemId) => return Err(TransitionError::UnknownItemId),
+            _ => return Err(TransitionError::Unknown),
+        };
+        if item_version
+            .as_ref()
+            .is_some_and(|version| &current.version != version)
+        {
+            return Err(TransitionError::VersionConflict(current));
+        }
+        if !current.state.can_become(state) {
+            return Err(TransitionError::Invalid(current));
+        }
+
+        let item = match state {
+            ItemState::Cancelled => {
+                match Self::delete_row(&transaction, caller, table_id, item_id, None) {
+                    Ok(deleted_at) => Item {
+                        deleted: true,
+                        state,
+                        version: current.version.clone() + ItemVersion::from_int(1),
+                        deleted_at: Some(deleted_at),
+                        ..current
+                    },
+                    _ => return Err(TransitionError::Unknown),
+                }
+            }
+            _ => {
+                if transaction.execute(
+                    "update item set state = ?, version = version + 1 where table_id = ? and item_id = ? and deleted=0",
+                    params![state.as_str(), IdType::from(table_id), IdType::from(item_id)],
+                ).is_err() {
+                    return Err(TransitionError::Unknown);
+                }
+                let item = match Self::fetch_one_item(&transaction, table_id, item_id) {
+                    Ok(item) => item,
+                    _ => return Err(TransitionError::Unknown),
+                };
+                if Self::audit(
+                    &transaction,
+                    caller,
+                    table_id,
+                    AuditAction::Update,
+                    Some(&current),
+                    Some(&item),
+                )
+                .is_err()
+                {
+                    return Err(TransitionError::Unknown);
+                }
+                item
+            }
+        };
+
+        match transaction.commit() {
+            Ok(_) => Ok(item),
+            _ => Err(TransitionError::Unknown),
+        }
+    }
+
+    pub(super) fn checkout_table(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        bill: &Bill,
+    ) -> Result<ReceiptId, CheckoutError> {
+        let lines = match serde_json::to_string(&bill.lines) {
+            Ok(lines) => lines,
+            _ => return Err(CheckoutError::Unknown),
+        };
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(CheckoutError::Unknown),
+        };
+
+        // Ordered by item id, like the versions of the bill
+        let items = match Self::fetch_all_items(
+            &transaction,
+            bill.table_id,
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        ) {
+            Ok(items) => items,
+            _ => return Err(CheckoutError::Unknown),
+        };
+        let active: Vec<(IdType, VersionType)> = items
+            .iter()
+            .map(|item| {
+                (
+                    IdType::from(item.id),
+                    VersionType::from(item.version.clone()),
+                )
+            })
+            .collect();
+        if active.is_empty() {
+            return Err(CheckoutError::UnknownTableId);
+        }
+        if active != bill.item_versions() {
+            return Err(CheckoutError::Conflict);
+        }
+
+        match transaction.execute(
+            "insert into bill (table_id, lines, subtotal, tax, total, created_at) values (?,?,?,?,?,?)",
+            params![IdType::from(bill.table_id), lines, bill.subtotal, bill.tax, bill.total, to_rfc3339(&chrono::Utc::now())],
+        ) {
+            Ok(_) => {}
+            _ => return Err(CheckoutError::Unknown),
+        };
+        let receipt_id = transaction.last_insert_rowid();
+
+        match transaction.execute(
+            "update item set deleted=1, deleted_at = ?, state = 'paid', version = version + 1 where table_id = ? and deleted=0",
+            params![to_rfc3339(&Utc::now()), IdType::from(bill.table_id)],
+        ) {
+            Ok(_) => {}
+            _ => return Err(CheckoutError::Unknown),
+        };
+        for item in &items {
+            if Self::audit(
+                &transaction,
+                caller,
+                bill.table_id,
+                AuditAction::Delete,
+                Some(item),
+                None,
+            )
+            .is_err()
+            {
+                return Err(CheckoutError::Unknown);
+            }
+        }
+
+        match (transaction.commit(), IdType::try_from(receipt_id)) {
+            (Ok(_), Ok(receipt_id)) => Ok(ReceiptId::from(receipt_id)),
+            _ => Err(CheckoutError::Unknown),
+        }
+    }
+
+    // Hard deletes the old deleted copies and hands the freed pages back to the system
+    pub(super) fn purge_items(
+        connection: &Connection,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<usize, PurgeError> {
+        let removed = match connection.execute(
+            "delete from item where deleted=1 and deleted_at < ?",
+            params![to_rfc3339(&deleted_before)],
+        ) {
+            Ok(removed) => removed,
+            _ => return Err(PurgeError::Unknown),
+        };
+
+        // A no-op unless the database uses incremental auto vacuum
+        match connection.execute_batch("PRAGMA incremental_vacuum") {
+            Ok(_) => Ok(removed),
+            _ => Err(PurgeError::Unknown),
+        }
+    }
+
+    // Copies the WAL back into the database file and empties it, a no-op unless
+    // the database is in WAL mode
+    pub(crate) fn checkpoint(connection: &Connection) -> Result<(), ShutdownError> {
+        // The first column is 1 when a reader kept the checkpoint from completing
+        match connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+            row.get::<usize, i64>(0)
+        }) {
+            Ok(0) => Ok(()),
+            _ => Err(ShutdownError::Unknown),
+        }
+    }
+
+    // Reads the item and writes it back patched in one transaction
+    pub(super) fn patch_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        let item = match Self::fetch_one_item(&transaction, table_id, item_id) {
+            Ok(current) => patch.apply_to(&current),
+            Err(FetchOneError::UnknownItemId) => return Err(UpdateError::UnknownItemId),
+            Err(FetchOneError::UnknownTableId) => return Err(UpdateError::UnknownTableId),
+            Err(FetchOneError::Unknown) => return Err(UpdateError::Unknown),
+        };
+        let item = Self::update_row(
+            &transaction,
+            caller,
+            table_id,
+            item_id,
+            item.name,
+            item.notes,
+            item.quantity,
+            item.deleted,
+            item_version,
+            item.prep_time,
+        )?;
+
+        match transaction.commit() {
+            Ok(_) => Ok(item),
+            _ => Err(UpdateError::Unknown),
+        }
+    }
+
+    // A conflict carries the stored item so that the caller can retry on top of it
+    fn version_conflict(
+        connection: &Connection,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> UpdateError {
+        match Self::fetch_one_item(connection, table_id, item_id) {
+            Ok(item) => UpdateError::VersionConflict(item),
+            _ => UpdateError::Unknown,
+        }
+    }
+
+    // Timestamps are compared as RFC 3339 strings, which sort chronologically
+    pub(super) fn claim_key(
+        connection: &mut Connection,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError> {
+        let key = String::from(key);
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        if transaction
+            .execute(
+                "delete from idempotency_key where expires_at <= ?",
+                params![to_rfc3339(&now)],
+            )
+            .is_err()
+        {
+            return Err(IdempotencyError::Unknown);
+        }
+        let record = match transaction
+            .query_row(
+                "select request_hash, status, body from idempotency_key where key = ?",
+                params![key],
+                |row| {
+                    Ok((
+                        row.get::<usize, String>(0)?,
+                        row.get::<usize, Option<u16>>(1)?,
+                        row.get::<usize, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()
+        {
+            Ok(record) => record,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        let claim = match record {
+            Some((stored_hash, _, _)) if stored_hash != request_hash => KeyClaim::Mismatch,
+            Some((_, Some(status), Some(body))) => {
+                KeyClaim::Replay(StoredResponse { status, body })
+            }
+            Some(_) => KeyClaim::InProgress,
+            None => {
+                match transaction.execute(
+                    "insert into idempotency_key (key, request_hash, expires_at) values (?,?,?)",
+                    params![key, request_hash, to_rfc3339(&expires_at)],
+                ) {
+                    Ok(_) => KeyClaim::Claimed,
+                    _ => return Err(IdempotencyError::Unknown),
+                }
+            }
+        };
+
+        match transaction.commit() {
+            Ok(_) => Ok(claim),
+            _ => Err(IdempotencyError::Unknown),
+        }
+    }
+
+    pub(super) fn complete_key(
+        connection: &Connection,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError> {
+        match connection.execute(
+            "update idempotency_key set status = ?, body = ? where key = ?",
+            params![response.status, response.body, String::from(key)],
+        ) {
+            Ok(_) => Ok(()),
+            _ => Err(IdempotencyError::Unknown),
+        }
+    }
+
+    pub(super) fn release_key(
+        connection: &Connection,
+        key: IdempotencyKey,
+    ) -> Result<(), IdempotencyError> {
+        match connection.execute(
+            "delete from idempotency_key where key = ?",
+            params![String::from(key)],
+        ) {
+            Ok(_) => Ok(()),
+            _ => Err(IdempotencyError::Unknown),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn update_item(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, UpdateError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(UpdateError::Unknown),
+        };
+        let item = Self::update_row(
+            &transaction,
+            caller,
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )?;
+
+        match transaction.commit() {
+            Ok(_) => Ok(item),
+            _ => Err(UpdateError::Unknown),
+        }
+    }
+
+    // Updates an item without committing, the caller owns the transaction
+    #[allow(clippy::too_many_arguments)]
+    fn update_row(
+        connection: &Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, UpdateError> {
+        let item_rows = match Self::fetch_item_rows(
+            connection,
+            IdType::from(table_id),
+            Some(IdType::from(item_id)),
+            false,
+            &ItemFilter::default(),
+            &ItemSort::default(),
+            None,
+        ) {
+            Ok(rows) => rows,
+            _ => return Err(UpdateError::UnknownItemId),
+        };
+
+        if item_rows.is_empty() {
+            return Err(UpdateError::UnknownItemId);
+        }
+
+        let row = &item_rows[0];
+        let old = match Self::item_from_row(row.clone()) {
+            Some(old) => old,
+            None => return Err(UpdateError::Unknown),
+        };
+
+        if ItemVersion::from_int(row.6) > item_version {
+            return Err(Self::version_conflict(connection, table_id, item_id));
+        }
+
+        let new_version = ItemVersion::from_int(row.6) + ItemVersion::from_int(1);
+        // Changing the order doesn't move it through the kitchen
+        let state = match ItemState::try_from(row.10.clone()) {
+            Ok(state) => state,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        match connection.execute(
+            "update item set name = ?, notes = ?, quantity = ?, version = ?, ordered_at = ?, prep_minutes = ?, ready_at = ? where table_id = ? and item_id = ? and deleted=0",
+            params![
+                String::from(item_name.clone()),
+                String::from(item_notes.clone()),
+                QuantityType::from(item_quantity.clone()),
+                VersionType::from(new_version.clone()),
+                to_rfc3339(&item_prep_time.ordered_at),
+                item_prep_time.prep_minutes,
+                to_rfc3339(&item_prep_time.ready_at),
+                IdType::from(table_id),
+                IdType::from(item_id)],
+        ) {
+            Ok(_) => {}
+            Err(SqliteFailure(_, Some(message))) => {
+                if message.contains("UNIQUE constraint failed") {
+                    return Err(Self::version_conflict(connection, table_id, item_id));
+                } else {
+                    println!("Message update {}", message);
+                    return Err(UpdateError::Unknown);
+                }
+            }
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        let item = Item {
+            state,
+            ..Item::new(
+                item_id,
+                item_name,
+                item_notes,
+                item_quantity,
+                item_deleted,
+                new_version,
+                item_prep_time,
+            )
+        };
+        match Self::audit(
+            connection,
+            caller,
+            table_id,
+            AuditAction::Update,
+            Some(&old),
+            Some(&item),
+        ) {
+            Ok(_) => Ok(item),
+            _ => Err(UpdateError::Unknown),
+        }
+    }
+
+    // Applies every operation in one transaction, nothing is written when one fails
+    pub(super) fn apply_batch_ops(
+        connection: &mut Connection,
+        caller: Option<&Caller>,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError> {
+        let transaction = match connection.transaction() {
+            Ok(transaction) => transaction,
+            _ => return Err(BatchError::Unknown),
+        };
+
+        let mut results = vec![];
+        for op in ops {
+            let result = match op {
+                BatchOp::Insert(item) => {
+                    let id = item.id;
+                    Self::insert_row(
+                        &transaction,
+                        caller,
+                        table_id,
+                        item.id,
+                        item.name,
+                        item.notes,
+                        item.quantity,
+                        item.deleted,
+                        item.version,
+                        item.prep_time,
+                    )
+                    .map(Some)
+                    .map_err(|err| BatchError::Insert(id, err))
+                }
+                BatchOp::Update(item) => {
+                    let id = item.id;
+                    Self::update_row(
+                        &transaction,
+                        caller,
+                        table_id,
+                        item.id,
+                        item.name,
+                        item.notes,
+                        item.quantity,
+                        item.deleted,
+                        item.version,
+                        item.prep_time,
+                    )
+                    .map(Some)
+                    .map_err(|err| BatchError::Update(id, err))
+                }
+                BatchOp::Delete(item_id) => {
+                    Self::delete_row(&transaction, caller, table_id, item_id, None)
+                        .map(|_| None)
+                        .map_err(|err| BatchError::Delete(item_id, err))
+                }
+            };
+            // Dropping the transaction without a commit rolls it back
+            results.push(result?);
+        }
+
+        match transaction.commit() {
+            Ok(_) => Ok(results),
+            _ => Err(BatchError::Unknown),
+        }
+    }
+}
+
+impl Repository for SqliteRepository {
+    fn insert(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, InsertError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(InsertError::Unknown),
+        };
+
+        Self::insert_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )
+    }
+
+    fn fetch_all(
+        &self,
+        table_id: TableId<IdType>,
+        include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
+    ) -> Result<Vec<Item>, FetchAllError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchAllError::Unknown),
+        };
+
+        Self::fetch_all_items(&lock, table_id, include_deleted, filter, sort_by, page)
+    }
+
+    fn fetch_one(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, FetchOneError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        Self::fetch_one_item(&lock, table_id, item_id)
+    }
+
+    fn fetch_active_across_tables(
+        &self,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchAllError::Unknown),
+        };
+
+        Self::fetch_queue_items(&lock, ready_by)
+    }
+
+    fn update_partial(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        Self::patch_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            patch,
+            item_version,
+        )
+    }
+
+    fn fetch_history(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        Self::fetch_item_history(&lock, table_id, item_id)
+    }
+
+    fn restore(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(RestoreError::Unknown),
+        };
+
+        Self::restore_item(&mut lock, self.caller.as_ref(), table_id, item_id)
+    }
+
+    fn transition(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(TransitionError::Unknown),
+        };
+
+        Self::transition_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            state,
+            item_version,
+        )
+    }
+
+    fn delete(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<(), DeleteError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(DeleteError::Unknown),
+        };
+
+        Self::delete_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_version,
+        )
+    }
+
+    fn apply_batch(
+        &self,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(BatchError::Unknown),
+        };
+
+        Self::apply_batch_ops(&mut lock, self.caller.as_ref(), table_id, ops)
+    }
+
+    fn claim_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        Self::claim_key(&mut lock, key, request_hash, now, expires_at)
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        Self::complete_key(&lock, key, response)
+    }
+
+    fn release_idempotency_key(&self, key: IdempotencyKey) -> Result<(), IdempotencyError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        Self::release_key(&lock, key)
+    }
+
+    fn checkout(&self, bill: Bill) -> Result<ReceiptId, CheckoutError> {
+        let mut lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(CheckoutError::Unknown),
+        };
+
+        Self::checkout_table(&mut lock, self.caller.as_ref(), &bill)
+    }
+
+    fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<usize, PurgeError> {
+        let lock = match self.connection.lock() {
+            Ok(lock) => lock,
+            _ => return Err(PurgeError::Unknown),
+        };
 
-        match (
-            ItemId::try_from(item_row.0.to_string()),
-            ItemName::try_from(item_row.2),
-            ItemNotes::try_from(item_row.3),
-            ItemQuantity::try_from(item_row.4.to_string()),
-            item_row.5,
-            ItemVersion::try_from(item_row.6.to_string()),
-            item_row.7,
-        ) {
-            (Ok(id), Ok(name), Ok(notes), Ok(quantity), deleted, Ok(version), time_to_prepare) => {
-                Ok(Item::new(
-                    id,
-                    name,
-                    notes,
-                    quantity,
-                    deleted,
-                    version,
-                    time_to_prepare,
-                ))
-            }
-            _ => return Err(FetchOneError::Unknown)
-        }
+        Self::purge_items(&lock, deleted_before)
     }
 
-    fn delete(
-        &self,
-        table_id: TableId<IdType>,
-        item_id: ItemId<IdType>,
-    ) -> Result<(), DeleteError> {
+    // The connection itself is closed when the repository is dropped
+    fn shutdown(&self) -> Result<(), ShutdownError> {
         let lock = match self.connection.lock() {
             Ok(lock) => lock,
-            _ => return Err(DeleteError::Unknown),
+            _ => return Err(ShutdownError::Unknown),
         };
 
-        match lock.execute(
-            "update item set deleted=1 where table_id=? and item_id = ? and deleted=0",
-            params![IdType::from(table_id), IdType::from(item_id)],
-        ) {
-            Ok(0) => Err(DeleteError::UnknownItemId),
-            Ok(_) => Ok(()),
-            Err(SqliteFailure(_, Some(message))) => {
-                println!("Message delete {}", message);
-                return Err(DeleteError::Unknown);
-            }
-            _ => Err(DeleteError::Unknown),
-        }
+        Self::checkpoint(&lock)
+    }
+
+    // Shares the connection
+    fn for_caller(&self, caller: Caller) -> Arc<dyn Repository> {
+        Arc::new(Self {
+            connection: self.connection.clone(),
+            caller: Some(caller),
+        })
     }
 
     fn update(
@@ -324,91 +1415,48 @@ impl Repository for SqliteRepository {
         item_quantity: ItemQuantity<QuantityType>,
         item_deleted: bool,
         item_version: ItemVersion<VersionType>,
-        item_time_to_prepare: String,
+        item_prep_time: PrepTime,
     ) -> Result<Item, UpdateError> {
         let mut lock = match self.connection.lock() {
             Ok(lock) => lock,
             _ => return Err(UpdateError::Unknown),
         };
 
-        let item_rows =
-            match Self::fetch_item_rows(&lock, IdType::from(table_id), Some(IdType::from(item_id)), false)
-            {
-                Ok(rows) => rows,
-                _ => return Err(UpdateError::UnknownItemId),
-            };
-        
-        if item_rows.is_empty() {
-            return Err(UpdateError::UnknownItemId);
-        }
-
-        let row = &item_rows[0];
-
-        if ItemVersion::from_int(row.6) > item_version {
-            return Err(UpdateError::VersionConflict);
-        }
-
-        let new_version = ItemVersion::from_int(row.6) + ItemVersion::from_int(1);
-        let transaction = match lock.transaction() {
-            Ok(transaction) => transaction,
-            _ => return Err(UpdateError::Unknown),
-        };
-
-        match transaction.execute(
-            "update item set name = ?, notes = ?, quantity = ?, version = ?, time_to_prepare = ? where table_id = ? and item_id = ? and deleted=0",
-            params![
-                String::from(item_name.clone()),
-                String::from(item_notes.clone()), 
-                QuantityType::from(item_quantity.clone()), 
-                VersionType::from(new_version.clone()),
-                item_time_to_prepare,
-                IdType::from(table_id),
-                IdType::from(item_id)],
-        ) {
-            Ok(_) => {}
-            Err(SqliteFailure(_, Some(message))) => {
-                if message.contains("UNIQUE constraint failed") {
-                    return Err(UpdateError::VersionConflict);
-                } else {
-                    println!("Message update {}", message);
-                    return Err(UpdateError::Unknown);
-                }
-            }
-            _ => return Err(UpdateError::Unknown),
-        };
-
-        match transaction.commit() {
-            Ok(_) => Ok(Item::new(
-                item_id,
-                item_name,
-                item_notes,
-                item_quantity,
-                item_deleted,
-                new_version,
-                item_time_to_prepare,
-            )),
-            _ => Err(UpdateError::Unknown),
-        }
+        Self::update_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::domain::types::{
+        ItemCursor, ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, TableId, TaxRate,
+    };
+    use crate::menu::{MenuItem, MenuPrices};
     use std::sync::Arc;
-    use crate::domain::types::{ItemId, ItemName, ItemNotes, ItemQuantity, ItemVersion, TableId};
 
     #[tokio::test]
     async fn it_should_create_db_with_table() {
-        match SqliteRepository::try_new("") {
-            Ok(_) => {},
+        match SqliteRepository::try_new("", true) {
+            Ok(_) => {}
             _ => panic!("Error while creating sqlite repo"),
         };
     }
 
     #[tokio::test]
     async fn it_should_insert_one_record() {
-        let repo = match SqliteRepository::try_new("") {
+        let repo = match SqliteRepository::try_new("", true) {
             Ok(r) => Arc::new(r),
             _ => panic!("Error while creating sqlite repo"),
         };
@@ -421,16 +1469,16 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(_) => {}
+            _ => unreachable!(),
         }
     }
 
     #[tokio::test]
     async fn it_should_read_one_record() {
-        let repo = match SqliteRepository::try_new("") {
+        let repo = match SqliteRepository::try_new("", true) {
             Ok(r) => Arc::new(r),
             _ => panic!("Error while creating sqlite repo"),
         };
@@ -443,32 +1491,29 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(_) => {}
+            _ => unreachable!(),
         }
 
-        match repo.fetch_one(
-            TableId::from_int(1),
-            ItemId::from_int(1),
-        ) {
+        match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
             Ok(item) => {
                 assert_eq!(item.id, ItemId::from_int(1));
                 assert_eq!(item.name, ItemName::pizza());
                 assert_eq!(item.notes, ItemNotes::some_notes());
                 assert_eq!(item.quantity, ItemQuantity::one());
-                assert_eq!(item.deleted, false);
+                assert!(!item.deleted);
                 assert_eq!(item.version, ItemVersion::ver_one());
-                assert_eq!(item.time_to_prepare, "2023/12/12".to_string())
-            },
-            _ => unreachable!()
+                assert_eq!(item.prep_time, PrepTime::from_date("2023-12-12"))
+            }
+            _ => unreachable!(),
         }
     }
 
     #[tokio::test]
     async fn it_should_update_one_record() {
-        let repo = match SqliteRepository::try_new("") {
+        let repo = match SqliteRepository::try_new("", true) {
             Ok(r) => Arc::new(r),
             _ => panic!("Error while creating sqlite repo"),
         };
@@ -481,10 +1526,10 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(_) => {}
+            _ => unreachable!(),
         }
 
         match repo.update(
@@ -495,32 +1540,29 @@ mod test {
             ItemQuantity::two(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/14".to_string()
+            PrepTime::from_date("2023-12-14"),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(_) => {}
+            _ => unreachable!(),
         }
 
-        match repo.fetch_one(
-            TableId::from_int(1),
-            ItemId::from_int(1),
-        ) {
+        match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
             Ok(item) => {
                 assert_eq!(item.id, ItemId::from_int(1));
                 assert_eq!(item.name, ItemName::pasta());
                 assert_eq!(item.notes, ItemNotes::other_notes());
                 assert_eq!(item.quantity, ItemQuantity::two());
-                assert_eq!(item.deleted, false);
+                assert!(!item.deleted);
                 assert_eq!(item.version, ItemVersion::from_int(2));
-                assert_eq!(item.time_to_prepare, "2023/12/14".to_string())
-            },
-            _ => unreachable!()
+                assert_eq!(item.prep_time, PrepTime::from_date("2023-12-14"))
+            }
+            _ => unreachable!(),
         }
     }
 
     #[tokio::test]
     async fn it_should_delete_one_record() {
-        let repo = match SqliteRepository::try_new("") {
+        let repo = match SqliteRepository::try_new("", true) {
             Ok(r) => Arc::new(r),
             _ => panic!("Error while creating sqlite repo"),
         };
@@ -533,34 +1575,41 @@ mod test {
             ItemQuantity::one(),
             false,
             ItemVersion::ver_one(),
-            "2023/12/12".to_string(),
+            PrepTime::from_date("2023-12-12"),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(_) => {}
+            _ => unreachable!(),
         }
 
         match repo.delete(
             TableId::from_int(1),
             ItemId::from_int(1),
+            Some(ItemVersion::from_int(2)),
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Err(DeleteError::VersionConflict) => {}
+            _ => unreachable!(),
         }
 
-        match repo.fetch_one(
+        match repo.delete(
             TableId::from_int(1),
             ItemId::from_int(1),
+            Some(ItemVersion::ver_one()),
         ) {
+            Ok(_) => {}
+            _ => unreachable!(),
+        }
+
+        match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
             Err(e) => {
                 assert!(e == FetchOneError::UnknownItemId);
-            },
-            _ => unreachable!()
+            }
+            _ => unreachable!(),
         }
     }
 
     #[tokio::test]
     async fn it_should_fetch_all_record() {
-        let repo = match SqliteRepository::try_new("") {
+        let repo = match SqliteRepository::try_new("", true) {
             Ok(r) => Arc::new(r),
             _ => panic!("Error while creating sqlite repo"),
         };
@@ -568,41 +1617,422 @@ mod test {
         ids.iter().for_each(|id| {
             repo.insert(
                 TableId::from_int(1),
-                ItemId::from_int(id.clone()),
+                ItemId::from_int(*id),
                 ItemName::pizza(),
                 ItemNotes::some_notes(),
                 ItemQuantity::one(),
                 false,
                 ItemVersion::ver_one(),
-                "2023/12/12".to_string(),
+                PrepTime::from_date("2023-12-12"),
             )
             .ok();
         });
 
         match repo.fetch_all(
-            TableId::from_int(1), false
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
         ) {
             Ok(items) => {
                 assert_eq!(ids.len(), items.len())
             }
-            _ => unreachable!()
+            _ => unreachable!(),
         }
-        
-        match repo.delete(
+
+        match repo.delete(TableId::from_int(1), ItemId::from_int(1), None) {
+            Ok(_) => {}
+            _ => unreachable!(),
+        }
+
+        match repo.fetch_all(
             TableId::from_int(1),
-            ItemId::from_int(1),
+            false,
+            ItemFilter::default(),
+            ItemSort::default(),
+            None,
+        ) {
+            Ok(items) => {
+                assert_eq!(ids.len() - 1, items.len())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_all_filtered_and_sorted_records() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        let rows = vec![
+            (1, ItemName::pizza(), 1, "2023-12-13"),
+            (2, ItemName::pasta(), 3, "2023-12-12"),
+            (
+                3,
+                ItemName::from_str("Pizza Diavola".to_string()),
+                2,
+                "2023-12-14",
+            ),
+        ];
+        rows.into_iter()
+            .for_each(|(id, name, quantity, ordered_on)| {
+                repo.insert(
+                    TableId::from_int(1),
+                    ItemId::from_int(id),
+                    name,
+                    ItemNotes::some_notes(),
+                    ItemQuantity::from_int(quantity),
+                    false,
+                    ItemVersion::ver_one(),
+                    PrepTime::from_date(ordered_on),
+                )
+                .ok();
+            });
+
+        let filter = ItemFilter {
+            name_contains: Some("PIZZA".to_string()),
+            min_quantity: Some(ItemQuantity::from_int(2)),
+            max_quantity: None,
+        };
+        match repo.fetch_all(
+            TableId::from_int(1),
+            false,
+            filter,
+            ItemSort::default(),
+            None,
         ) {
-            Ok(_) => {},
-            _ => unreachable!()
+            Ok(items) => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].id, ItemId::from_int(3));
+            }
+            _ => unreachable!(),
         }
 
+        let sort_by = ItemSort::try_from("-ready_at".to_string()).unwrap();
         match repo.fetch_all(
-            TableId::from_int(1), false
+            TableId::from_int(1),
+            false,
+            ItemFilter::default(),
+            sort_by,
+            None,
         ) {
             Ok(items) => {
-                assert_eq!(ids.len() - 1, items.len())
+                let ids: Vec<ItemId<IdType>> = items.iter().map(|it| it.id).collect();
+                assert_eq!(
+                    ids,
+                    vec![
+                        ItemId::from_int(3),
+                        ItemId::from_int(1),
+                        ItemId::from_int(2)
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_all_records_page_by_page() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        for id in [3, 1, 2] {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+        }
+        repo.delete(TableId::from_int(1), ItemId::from_int(2), None)
+            .ok();
+
+        let page = ItemPage {
+            after: None,
+            limit: 2,
+        };
+        let first = match repo.fetch_all(
+            TableId::from_int(1),
+            true,
+            ItemFilter::default(),
+            ItemSort::default(),
+            Some(page),
+        ) {
+            Ok(items) => items,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            first.iter().map(|it| it.id).collect::<Vec<_>>(),
+            vec![ItemId::from_int(1), ItemId::from_int(2)]
+        );
+
+        let page = ItemPage {
+            after: Some(ItemCursor::from_item(&first[1])),
+            limit: 2,
+        };
+        match repo.fetch_all(
+            TableId::from_int(1),
+            true,
+            ItemFilter::default(),
+            ItemSort::default(),
+            Some(page),
+        ) {
+            Ok(items) => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].id, ItemId::from_int(3));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_check_out_only_the_billed_items() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        let prices = MenuPrices::from_items(&[MenuItem::new(1, "Some pizza", 950)]);
+        let items = repo
+            .fetch_all(
+                TableId::from_int(1),
+                false,
+                ItemFilter::default(),
+                ItemSort::default(),
+                None,
+            )
+            .ok()
+            .unwrap();
+        let bill = Bill::compute(TableId::from_int(1), &items, &prices, TaxRate::default())
+            .ok()
+            .unwrap();
+
+        // The item changes after it was billed
+        repo.update(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::two(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        match repo.checkout(bill) {
+            Err(CheckoutError::Conflict) => {}
+            _ => unreachable!(),
+        }
+
+        let items = repo
+            .fetch_all(
+                TableId::from_int(1),
+                false,
+                ItemFilter::default(),
+                ItemSort::default(),
+                None,
+            )
+            .ok()
+            .unwrap();
+        let bill = Bill::compute(TableId::from_int(1), &items, &prices, TaxRate::default())
+            .ok()
+            .unwrap();
+        match repo.checkout(bill.clone()) {
+            Ok(receipt_id) => assert_eq!(receipt_id, ReceiptId::from(1)),
+            _ => unreachable!(),
+        }
+        match repo.checkout(bill) {
+            Err(CheckoutError::UnknownTableId) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_claim_complete_and_expire_idempotency_keys() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        let key = IdempotencyKey::try_from("order-1".to_string()).unwrap();
+        let now = Utc::now();
+        let later = now + chrono::Duration::minutes(5);
+        let response = StoredResponse {
+            status: 201,
+            body: "{}".to_string(),
+        };
+
+        match repo.claim_idempotency_key(key.clone(), "hash".to_string(), now, later) {
+            Ok(KeyClaim::Claimed) => {}
+            _ => unreachable!(),
+        }
+        match repo.claim_idempotency_key(key.clone(), "hash".to_string(), now, later) {
+            Ok(KeyClaim::InProgress) => {}
+            _ => unreachable!(),
+        }
+        assert!(repo
+            .complete_idempotency_key(key.clone(), response.clone())
+            .is_ok());
+        match repo.claim_idempotency_key(key.clone(), "hash".to_string(), now, later) {
+            Ok(KeyClaim::Replay(stored)) => assert_eq!(stored, response),
+            _ => unreachable!(),
+        }
+        match repo.claim_idempotency_key(key.clone(), "other".to_string(), now, later) {
+            Ok(KeyClaim::Mismatch) => {}
+            _ => unreachable!(),
+        }
+
+        // Once expired the key can be claimed again, as it can after a release
+        match repo.claim_idempotency_key(key.clone(), "other".to_string(), later, later) {
+            Ok(KeyClaim::Claimed) => {}
+            _ => unreachable!(),
+        }
+        assert!(repo.release_idempotency_key(key.clone()).is_ok());
+        match repo.claim_idempotency_key(key, "hash".to_string(), now, later) {
+            Ok(KeyClaim::Claimed) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_stamp_deletes_and_purge_them_after_a_vacuum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("restaurant.sqlite");
+        let repo = match SqliteRepository::try_new(path.to_str().unwrap(), true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        for item_id in 1..=2 {
+            repo.insert(
+                TableId::from_int(1),
+                ItemId::from_int(item_id),
+                ItemName::pizza(),
+                ItemNotes::some_notes(),
+                ItemQuantity::one(),
+                false,
+                ItemVersion::ver_one(),
+                PrepTime::from_date("2023-12-12"),
+            )
+            .ok();
+            repo.delete(TableId::from_int(1), ItemId::from_int(item_id), None)
+                .ok();
+        }
+
+        match repo.fetch_history(TableId::from_int(1), ItemId::from_int(1)) {
+            Ok(items) => assert!(items[0].deleted_at.is_some()),
+            _ => unreachable!(),
+        }
+        match repo.restore(TableId::from_int(1), ItemId::from_int(1)) {
+            Ok(item) => assert_eq!(item.deleted_at, None),
+            _ => unreachable!(),
+        }
+
+        assert!(repo.vacuum().is_ok());
+        let auto_vacuum: u32 = repo
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(auto_vacuum, 2);
+
+        match repo.purge_deleted(Utc::now() - chrono::Duration::days(1)) {
+            Ok(removed) => assert_eq!(removed, 0),
+            _ => unreachable!(),
+        }
+        match repo.purge_deleted(Utc::now() + chrono::Duration::days(1)) {
+            Ok(removed) => assert_eq!(removed, 1),
+            _ => unreachable!(),
+        }
+        assert!(repo
+            .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+            .is_ok());
+        match repo.fetch_history(TableId::from_int(1), ItemId::from_int(2)) {
+            Err(FetchOneError::UnknownItemId) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_read_the_kitchen_queue_through_the_ready_at_index() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => r,
+            _ => panic!("Error while creating sqlite repo"),
+        };
+
+        let connection = repo.connection.lock().unwrap();
+        let plan: String = connection
+            .query_row(
+                &format!("explain query plan select {} from item where deleted=0 and state in ('ordered', 'cooking', 'ready') and ready_at <= ? order by ready_at asc, table_id asc, item_id asc", ITEM_COLUMNS),
+                params!["2023-12-12T10:00:00Z"],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("idx_item_ready_at"), "{}", plan);
+    }
+
+    #[tokio::test]
+    async fn it_should_skip_queued_items_of_tables_out_of_range() {
+        let repo = match SqliteRepository::try_new("", true) {
+            Ok(r) => r,
+            _ => panic!("Error while creating sqlite repo"),
+        };
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        // Stored while the range allowed it
+        repo.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "update item set table_id = ? where item_id = 1",
+                params![IdType::MAX],
+            )
+            .unwrap();
+        repo.insert(
+            TableId::from_int(2),
+            ItemId::from_int(1),
+            ItemName::pasta(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+
+        match repo.fetch_active_across_tables(None) {
+            Ok(queued) => {
+                let tables: Vec<TableId<IdType>> =
+                    queued.into_iter().map(|queued| queued.table_id).collect();
+                assert_eq!(tables, vec![TableId::from_int(2)]);
             }
-            _ => unreachable!()
+            _ => unreachable!(),
         }
     }
 }
@@ -0,0 +1,565 @@
+use crate::audit::Caller;
+use crate::domain::types::{
+    Bill, IdType, IdempotencyKey, Item, ItemFilter, ItemId, ItemName, ItemNotes, ItemPage,
+    ItemPatch, ItemQuantity, ItemSort, ItemState, ItemVersion, KeyClaim, PrepTime, QuantityType,
+    QueuedItem, ReceiptId, StoredResponse, TableId, VersionType,
+};
+use crate::repository::migrations;
+use crate::repository::sqlite::SqliteRepository;
+use crate::repository::*;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OpenFlags};
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+
+// A pooled Sqlite repository implementation. A fixed set of read-only connections
+// serves fetches while a single writer connection applies changes. The database runs
+// in WAL mode, so readers keep seeing the last committed state during a long write.
+
+pub const DEFAULT_READERS: usize = 4;
+
+pub struct SqlitePoolRepository {
+    writer: Arc<Mutex<Connection>>,
+    // None once the repository is shut down
+    readers: Arc<Mutex<Option<Vec<Connection>>>>,
+    reader_returned: Arc<Condvar>,
+    // Who the changes are logged as made by, nothing is logged without one
+    caller: Option<Caller>,
+}
+
+// A reader checked out of the pool, returned to it on drop
+struct PooledReader<'a> {
+    pool: &'a SqlitePoolRepository,
+    connection: Option<Connection>,
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let (Some(connection), Ok(mut readers)) =
+            (self.connection.take(), self.pool.readers.lock())
+        {
+            // A reader returned after the shutdown is closed right away
+            if let Some(readers) = readers.as_mut() {
+                readers.push(connection);
+                self.pool.reader_returned.notify_one();
+            }
+        }
+    }
+}
+
+impl SqlitePoolRepository {
+    // Opens the database, applying pending migrations when apply_migrations is set
+    pub fn try_new(path: &str, num_readers: usize, apply_migrations: bool) -> Result<Self, ()> {
+        if num_readers == 0 {
+            return Err(());
+        }
+
+        let mut writer = match Connection::open(path) {
+            Ok(connection) => connection,
+            _ => return Err(()),
+        };
+        if writer.busy_timeout(BUSY_TIMEOUT).is_err() {
+            return Err(());
+        }
+        if SqliteRepository::enable_incremental_vacuum(&writer).is_err() {
+            return Err(());
+        }
+        // In-memory databases cannot be shared between connections and report "memory" here
+        match writer.query_row("PRAGMA journal_mode=WAL", [], |row| {
+            row.get::<usize, String>(0)
+        }) {
+            Ok(mode) if mode.eq_ignore_ascii_case("wal") => {}
+            _ => return Err(()),
+        }
+        if writer.pragma_update(None, "synchronous", "NORMAL").is_err() {
+            return Err(());
+        }
+        if migrations::prepare(&mut writer, apply_migrations).is_err() {
+            return Err(());
+        }
+
+        let mut readers = Vec::with_capacity(num_readers);
+        for _ in 0..num_readers {
+            let reader = match Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            ) {
+                Ok(connection) => connection,
+                _ => return Err(()),
+            };
+            if reader.busy_timeout(BUSY_TIMEOUT).is_err() {
+                return Err(());
+            }
+            readers.push(reader);
+        }
+
+        Ok(Self {
+            writer: Arc::new(Mutex::new(writer)),
+            readers: Arc::new(Mutex::new(Some(readers))),
+            reader_returned: Arc::new(Condvar::new()),
+            caller: None,
+        })
+    }
+
+    // Waits until one of the reader connections is free, fails after the shutdown
+    fn reader(&self) -> Result<PooledReader<'_>, ()> {
+        let mut readers = self.readers.lock().map_err(|_| ())?;
+        loop {
+            if let Some(connection) = readers.as_mut().ok_or(())?.pop() {
+                return Ok(PooledReader {
+                    pool: self,
+                    connection: Some(connection),
+                });
+            }
+            readers = self.reader_returned.wait(readers).map_err(|_| ())?;
+        }
+    }
+}
+
+impl Repository for SqlitePoolRepository {
+    fn insert(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, InsertError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(InsertError::Unknown),
+        };
+
+        SqliteRepository::insert_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )
+    }
+
+    fn update(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_name: ItemName,
+        item_notes: ItemNotes,
+        item_quantity: ItemQuantity<QuantityType>,
+        item_deleted: bool,
+        item_version: ItemVersion<VersionType>,
+        item_prep_time: PrepTime,
+    ) -> Result<Item, UpdateError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        SqliteRepository::update_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_name,
+            item_notes,
+            item_quantity,
+            item_deleted,
+            item_version,
+            item_prep_time,
+        )
+    }
+
+    fn fetch_all(
+        &self,
+        table_id: TableId<IdType>,
+        include_deleted: bool,
+        filter: ItemFilter,
+        sort_by: ItemSort,
+        page: Option<ItemPage>,
+    ) -> Result<Vec<Item>, FetchAllError> {
+        let reader = match self.reader() {
+            Ok(reader) => reader,
+            _ => return Err(FetchAllError::Unknown),
+        };
+
+        SqliteRepository::fetch_all_items(&reader, table_id, include_deleted, filter, sort_by, page)
+    }
+
+    fn fetch_one(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, FetchOneError> {
+        let reader = match self.reader() {
+            Ok(reader) => reader,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        SqliteRepository::fetch_one_item(&reader, table_id, item_id)
+    }
+
+    fn fetch_active_across_tables(
+        &self,
+        ready_by: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QueuedItem>, FetchAllError> {
+        let reader = match self.reader() {
+            Ok(reader) => reader,
+            _ => return Err(FetchAllError::Unknown),
+        };
+
+        SqliteRepository::fetch_queue_items(&reader, ready_by)
+    }
+
+    fn update_partial(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        patch: ItemPatch,
+        item_version: ItemVersion<VersionType>,
+    ) -> Result<Item, UpdateError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(UpdateError::Unknown),
+        };
+
+        SqliteRepository::patch_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            patch,
+            item_version,
+        )
+    }
+
+    fn fetch_history(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Vec<Item>, FetchOneError> {
+        let reader = match self.reader() {
+            Ok(reader) => reader,
+            _ => return Err(FetchOneError::Unknown),
+        };
+
+        SqliteRepository::fetch_item_history(&reader, table_id, item_id)
+    }
+
+    fn restore(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+    ) -> Result<Item, RestoreError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(RestoreError::Unknown),
+        };
+
+        SqliteRepository::restore_item(&mut lock, self.caller.as_ref(), table_id, item_id)
+    }
+
+    fn transition(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        state: ItemState,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<Item, TransitionError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(TransitionError::Unknown),
+        };
+
+        SqliteRepository::transition_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            state,
+            item_version,
+        )
+    }
+
+    fn delete(
+        &self,
+        table_id: TableId<IdType>,
+        item_id: ItemId<IdType>,
+        item_version: Option<ItemVersion<VersionType>>,
+    ) -> Result<(), DeleteError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(DeleteError::Unknown),
+        };
+
+        SqliteRepository::delete_item(
+            &mut lock,
+            self.caller.as_ref(),
+            table_id,
+            item_id,
+            item_version,
+        )
+    }
+
+    fn apply_batch(
+        &self,
+        table_id: TableId<IdType>,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Option<Item>>, BatchError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(BatchError::Unknown),
+        };
+
+        SqliteRepository::apply_batch_ops(&mut lock, self.caller.as_ref(), table_id, ops)
+    }
+
+    fn claim_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        request_hash: String,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<KeyClaim, IdempotencyError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        SqliteRepository::claim_key(&mut lock, key, request_hash, now, expires_at)
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: IdempotencyKey,
+        response: StoredResponse,
+    ) -> Result<(), IdempotencyError> {
+        let lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        SqliteRepository::complete_key(&lock, key, response)
+    }
+
+    fn release_idempotency_key(&self, key: IdempotencyKey) -> Result<(), IdempotencyError> {
+        let lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(IdempotencyError::Unknown),
+        };
+
+        SqliteRepository::release_key(&lock, key)
+    }
+
+    fn checkout(&self, bill: Bill) -> Result<ReceiptId, CheckoutError> {
+        let mut lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(CheckoutError::Unknown),
+        };
+
+        SqliteRepository::checkout_table(&mut lock, self.caller.as_ref(), &bill)
+    }
+
+    fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<usize, PurgeError> {
+        let lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(PurgeError::Unknown),
+        };
+
+        SqliteRepository::purge_items(&lock, deleted_before)
+    }
+
+    // Closes the readers and checkpoints the WAL through the writer, which is closed
+    // when the repository is dropped
+    fn shutdown(&self) -> Result<(), ShutdownError> {
+        let readers = match self.readers.lock() {
+            Ok(mut readers) => readers.take(),
+            _ => return Err(ShutdownError::Unknown),
+        };
+        // Wake the requests waiting for a reader, they fail now
+        self.reader_returned.notify_all();
+        for reader in readers.into_iter().flatten() {
+            if reader.close().is_err() {
+                return Err(ShutdownError::Unknown);
+            }
+        }
+
+        let lock = match self.writer.lock() {
+            Ok(lock) => lock,
+            _ => return Err(ShutdownError::Unknown),
+        };
+
+        SqliteRepository::checkpoint(&lock)
+    }
+
+    // Shares the writer and the readers
+    fn for_caller(&self, caller: Caller) -> Arc<dyn Repository> {
+        Arc::new(Self {
+            writer: self.writer.clone(),
+            readers: self.readers.clone(),
+            reader_returned: self.reader_returned.clone(),
+            caller: Some(caller),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn repo_in(dir: &tempfile::TempDir, num_readers: usize) -> Arc<SqlitePoolRepository> {
+        let path = dir.path().join("restaurant.sqlite");
+        match SqlitePoolRepository::try_new(path.to_str().unwrap(), num_readers, true) {
+            Ok(r) => Arc::new(r),
+            _ => panic!("Error while creating pooled sqlite repo"),
+        }
+    }
+
+    #[test]
+    fn it_should_reject_in_memory_databases_and_empty_pools() {
+        assert!(SqlitePoolRepository::try_new("", DEFAULT_READERS, true).is_err());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("restaurant.sqlite");
+        assert!(SqlitePoolRepository::try_new(path.to_str().unwrap(), 0, true).is_err());
+    }
+
+    #[test]
+    fn it_should_open_an_unmigrated_database_only_when_applying_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("restaurant.sqlite");
+        let path = path.to_str().unwrap();
+
+        assert!(SqlitePoolRepository::try_new(path, DEFAULT_READERS, false).is_err());
+        assert!(SqlitePoolRepository::try_new(path, DEFAULT_READERS, true).is_ok());
+        assert!(SqlitePoolRepository::try_new(path, DEFAULT_READERS, false).is_ok());
+    }
+
+    #[test]
+    fn it_should_read_what_the_writer_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_in(&dir, 2);
+
+        match repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        ) {
+            Ok(_) => {}
+            _ => unreachable!(),
+        }
+
+        match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
+            Ok(item) => assert_eq!(item.name, ItemName::pizza()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_should_not_block_reads_during_a_long_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_in(&dir, 2);
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+
+        // Hold the writer inside an open transaction until the reads are done
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let writer_repo = repo.clone();
+        let writer = thread::spawn(move || {
+            let mut lock = writer_repo.writer.lock().unwrap();
+            let transaction = lock.transaction().unwrap();
+            transaction
+                .execute("update item set quantity = 5 where item_id = 1", [])
+                .unwrap();
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            transaction.commit().unwrap();
+        });
+        started_rx.recv().unwrap();
+
+        let (read_tx, read_rx) = mpsc::channel();
+        for _ in 0..4 {
+            let repo = repo.clone();
+            let read_tx = read_tx.clone();
+            thread::spawn(move || {
+                match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
+                    Ok(item) => read_tx.send(item.quantity).unwrap(),
+                    _ => unreachable!(),
+                }
+            });
+        }
+        // The writer can't commit before it is released, so every read completes while
+        // it holds the transaction. The timeout only keeps a blocked read from hanging.
+        for _ in 0..4 {
+            match read_rx.recv_timeout(Duration::from_secs(30)) {
+                Ok(quantity) => assert_eq!(quantity, ItemQuantity::one()),
+                Err(_) => panic!("a read was blocked by the open write"),
+            }
+        }
+
+        release_tx.send(()).unwrap();
+        writer.join().unwrap();
+        match repo.fetch_one(TableId::from_int(1), ItemId::from_int(1)) {
+            Ok(item) => assert_eq!(item.quantity, ItemQuantity::from_int(5)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_should_checkpoint_the_wal_and_close_the_readers_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repo_in(&dir, 2);
+        repo.insert(
+            TableId::from_int(1),
+            ItemId::from_int(1),
+            ItemName::pizza(),
+            ItemNotes::some_notes(),
+            ItemQuantity::one(),
+            false,
+            ItemVersion::ver_one(),
+            PrepTime::from_date("2023-12-12"),
+        )
+        .ok();
+        let wal = dir.path().join("restaurant.sqlite-wal");
+        assert!(std::fs::metadata(&wal).unwrap().len() > 0);
+
+        assert!(repo.shutdown().is_ok());
+        assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+        // Reads fail instead of waiting for a reader forever
+        assert!(repo
+            .fetch_one(TableId::from_int(1), ItemId::from_int(1))
+            .is_err());
+    }
+}